@@ -0,0 +1,95 @@
+//! Compare tracing primary rays with and without the bounding volume hierarchy.
+//!
+//! Run with `cargo bench`.
+
+use std::f64::consts::PI;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use ray_tracing::color::Color;
+use ray_tracing::math::{Point, Vector3};
+use ray_tracing::rendering::Ray;
+use ray_tracing::scene::{Coloration, Element, Material, Plane, Scene, Sphere, SurfaceType};
+
+fn material() -> Material {
+    Material {
+        surface_type: SurfaceType::Diffuse,
+        color: Coloration::Color(Color {
+            red: 0.2,
+            green: 1.0,
+            blue: 0.2,
+        }),
+        albedo: 0.18,
+        emission: None,
+        specular: None,
+    }
+}
+
+/// A ring of spheres in front of the camera above a ground plane, like the demo scene.
+fn sphere_scene(spheres: usize) -> Scene {
+    let mut scene = Scene::new(200, 200, 90., 1e-6, 10);
+    for n in 1..(spheres + 1) {
+        let phi = ((2 * n) as f64) * PI / (spheres as f64);
+        scene.add_element(Element::Sphere(Sphere {
+            center: Point::from_list([phi.cos(), phi.sin(), -2.8 + (n as f64) / (spheres as f64)]),
+            radius: 0.5 * (n as f64) / (spheres as f64),
+            material: material(),
+        }));
+    }
+    scene.add_element(Element::Plane(Plane {
+        normal: Vector3 {
+            x: 0.,
+            y: -1.,
+            z: 0.0,
+        },
+        p0: Point {
+            x: 0.,
+            y: -2.,
+            z: 0.,
+        },
+        material: material(),
+    }));
+    scene
+}
+
+/// Count the camera rays that hit something, with or without the bounding volume
+/// hierarchy.
+fn trace_all(scene: &Scene, linear: bool) -> usize {
+    let mut hits = 0;
+    for y in 0..scene.height {
+        for x in 0..scene.width {
+            let ray = Ray::create_prime(x, y, scene);
+            let hit = if linear {
+                scene.trace_linear(&ray)
+            } else {
+                scene.trace(&ray)
+            };
+            if hit.is_some() {
+                hits += 1;
+            }
+        }
+    }
+    hits
+}
+
+fn bench_trace(c: &mut Criterion) {
+    let mut group = c.benchmark_group("trace");
+    group.sample_size(10);
+
+    for spheres in [15, 1000] {
+        let scene = sphere_scene(spheres);
+        group.bench_with_input(BenchmarkId::new("linear", spheres), &scene, |b, s| {
+            b.iter(|| trace_all(s, true))
+        });
+
+        // The first trace builds the hierarchy, which is not part of the benchmark
+        trace_all(&scene, false);
+        group.bench_with_input(BenchmarkId::new("bvh", spheres), &scene, |b, s| {
+            b.iter(|| trace_all(s, false))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_trace);
+criterion_main!(benches);
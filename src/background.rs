@@ -0,0 +1,282 @@
+//! What rays see when they miss every element: a constant color, a gradient, an
+//! environment image all around the scene, or a daylight sky.
+//!
+//! The background is also a light source. It is sampled at every diffuse surface like
+//! the glowing elements are, and an environment image is importance sampled, so that
+//! its bright spots (like the sun) are found with few samples.
+//!
+//! See https://pbr-book.org/3ed-2018/Light_Transport_I_Surface_Reflection/Sampling_Light_Sources#InfiniteAreaLights
+
+use std::f64::consts::PI;
+
+use image::DynamicImage;
+
+use crate::color::{linear_rgb, Color, BLACK};
+use crate::math::Vector3;
+use crate::sampling::{cosine_sample_hemisphere, Rng};
+use crate::sky::Sky;
+
+pub enum Background {
+    Color(Color),
+    /// Blends from `bottom`, straight down, to `top`, straight up.
+    Gradient {
+        bottom: Color,
+        top: Color,
+    },
+    Environment(EnvironmentMap),
+    Sky(Sky),
+}
+
+/// A direction from which background light arrives.
+pub struct BackgroundSample {
+    pub direction: Vector3,
+    pub radiance: Color,
+    /// Probability density of picking `direction`, per unit solid angle
+    pub pdf: f64,
+}
+
+impl Background {
+    /// The light arriving from the background along `direction`, which points away
+    /// from the scene.
+    pub fn radiance(&self, direction: &Vector3) -> Color {
+        match self {
+            Background::Color(color) => *color,
+            Background::Gradient { bottom, top } => {
+                let t = ((direction.normalize().y + 1.0) / 2.0) as f32;
+                *bottom * (1.0 - t) + *top * t
+            }
+            Background::Environment(map) => map.radiance(direction),
+            Background::Sky(sky) => sky.radiance(direction),
+        }
+    }
+
+    /// A black background gives off no light, and is not worth sampling.
+    pub fn is_black(&self) -> bool {
+        let black = |c: &Color| c.red <= 0.0 && c.green <= 0.0 && c.blue <= 0.0;
+        match self {
+            Background::Color(color) => black(color),
+            Background::Gradient { bottom, top } => black(bottom) && black(top),
+            Background::Environment(map) => map.total <= 0.0,
+            Background::Sky(_) => false,
+        }
+    }
+
+    /// A random direction to sample the background light in, for a surface with the
+    /// given normal. Smooth backgrounds are sampled like a diffuse bounce, environment
+    /// images following their brightness.
+    pub fn sample(&self, normal: Vector3, rng: &mut Rng) -> BackgroundSample {
+        match self {
+            Background::Environment(map) => map.sample(rng),
+            _ => {
+                let direction = cosine_sample_hemisphere(normal, rng);
+                BackgroundSample {
+                    direction,
+                    radiance: self.radiance(&direction),
+                    pdf: normal.dot(&direction).max(0.0) / PI,
+                }
+            }
+        }
+    }
+}
+
+impl Default for Background {
+    fn default() -> Background {
+        Background::Color(BLACK)
+    }
+}
+
+/// An equirectangular (latitude-longitude) image of the light arriving from every
+/// direction, usually a high dynamic range `.hdr` or `.exr` photograph.
+///
+/// The top row of the image is straight up (+y), and its center column looks down the
+/// negative z axis.
+pub struct EnvironmentMap {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+    /// `row_cdf[y]` is the probability of picking a row before `y`
+    row_cdf: Vec<f64>,
+    /// `column_cdfs[y][x]` is the probability of picking a column before `x` in row `y`
+    column_cdfs: Vec<Vec<f64>>,
+    /// Sum of the sampling weights of all pixels
+    total: f64,
+}
+
+impl EnvironmentMap {
+    /// `strength` scales the brightness of the image. High dynamic range images are
+    /// linear already, 8 and 16 bit ones are decoded from sRGB. Returns `None` for an
+    /// empty image.
+    pub fn new(image: &DynamicImage, strength: f32) -> Option<EnvironmentMap> {
+        if image.width() == 0 || image.height() == 0 {
+            return None;
+        }
+
+        let image = linear_rgb(image);
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        let pixels: Vec<Color> = image
+            .pixels()
+            .map(|p| {
+                Color {
+                    red: p[0],
+                    green: p[1],
+                    blue: p[2],
+                } * strength
+            })
+            .collect();
+
+        // Pixels near the poles cover a smaller solid angle than those at the horizon
+        let mut row_weights = Vec::with_capacity(height);
+        let mut column_cdfs = Vec::with_capacity(height);
+        for y in 0..height {
+            let sin_theta = (PI * (y as f64 + 0.5) / height as f64).sin();
+            let weights = (0..width)
+                .map(|x| pixels[y * width + x].luminance() as f64 * sin_theta)
+                .collect::<Vec<f64>>();
+            let (cdf, row_total) = cumulative(&weights);
+            row_weights.push(row_total);
+            column_cdfs.push(cdf);
+        }
+        let (row_cdf, total) = cumulative(&row_weights);
+
+        Some(EnvironmentMap {
+            width,
+            height,
+            pixels,
+            row_cdf,
+            column_cdfs,
+            total,
+        })
+    }
+
+    pub fn radiance(&self, direction: &Vector3) -> Color {
+        let (u, v) = direction_to_uv(&direction.normalize());
+        let x = ((u * self.width as f64) as usize).min(self.width - 1);
+        let y = ((v * self.height as f64) as usize).min(self.height - 1);
+        self.pixels[y * self.width + x]
+    }
+
+    /// Pick a pixel with a probability following its brightness, and a random
+    /// direction within it.
+    fn sample(&self, rng: &mut Rng) -> BackgroundSample {
+        let y = pick(&self.row_cdf, rng.next_f64());
+        let x = pick(&self.column_cdfs[y], rng.next_f64());
+        let u = (x as f64 + rng.next_f64()) / self.width as f64;
+        let v = (y as f64 + rng.next_f64()) / self.height as f64;
+        let direction = uv_to_direction(u, v);
+
+        BackgroundSample {
+            direction,
+            radiance: self.pixels[y * self.width + x],
+            pdf: self.pdf(x, y, v),
+        }
+    }
+
+    /// Density per unit solid angle of sampling a direction in pixel `(x, y)`, at
+    /// latitude `v`.
+    fn pdf(&self, x: usize, y: usize, v: f64) -> f64 {
+        let sin_theta = (PI * v).sin();
+        if self.total <= 0.0 || sin_theta <= 0.0 {
+            return 0.0;
+        }
+        let row_probability = probability(&self.row_cdf, y);
+        let column_probability = probability(&self.column_cdfs[y], x);
+        // The pixel covers 2pi^2 sin(theta) / (width * height) steradians
+        let pixel_probability = row_probability * column_probability;
+        pixel_probability * (self.width * self.height) as f64 / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+/// The normalized cumulative distribution of the weights, with a leading 0, and their
+/// total. Uniform if all weights are 0.
+fn cumulative(weights: &[f64]) -> (Vec<f64>, f64) {
+    let total: f64 = weights.iter().sum();
+    let mut cdf = Vec::with_capacity(weights.len() + 1);
+    let mut sum = 0.0;
+    cdf.push(0.0);
+    for (i, weight) in weights.iter().enumerate() {
+        sum += weight;
+        cdf.push(if total > 0.0 {
+            sum / total
+        } else {
+            (i + 1) as f64 / weights.len() as f64
+        });
+    }
+    (cdf, total)
+}
+
+/// The index of the entry that `u`, between 0 and 1, falls in.
+fn pick(cdf: &[f64], u: f64) -> usize {
+    let index = cdf.partition_point(|&c| c <= u);
+    index.clamp(1, cdf.len() - 1) - 1
+}
+
+fn probability(cdf: &[f64], index: usize) -> f64 {
+    cdf[index + 1] - cdf[index]
+}
+
+/// Image coordinates between 0 and 1 of a normalized direction.
+fn direction_to_uv(direction: &Vector3) -> (f64, f64) {
+    let u = 0.5 + direction.x.atan2(-direction.z) / (2.0 * PI);
+    let v = direction.y.clamp(-1.0, 1.0).acos() / PI;
+    (u, v)
+}
+
+fn uv_to_direction(u: f64, v: f64) -> Vector3 {
+    let phi = (u - 0.5) * 2.0 * PI;
+    let theta = v * PI;
+    Vector3 {
+        x: theta.sin() * phi.sin(),
+        y: theta.cos(),
+        z: -theta.sin() * phi.cos(),
+    }
+}
+
+#[test]
+fn test_environment_map_directions_round_trip() {
+    for (u, v) in [(0.1, 0.2), (0.5, 0.5), (0.9, 0.7), (0.3, 0.95)] {
+        let (u2, v2) = direction_to_uv(&uv_to_direction(u, v));
+        assert!((u - u2).abs() < 1e-9 && (v - v2).abs() < 1e-9);
+    }
+    // The center of the image is straight ahead of the default camera
+    let ahead = uv_to_direction(0.5, 0.5);
+    assert!((ahead.z + 1.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_environment_map_sampling_finds_bright_spot() {
+    use image::{Rgb, Rgb32FImage};
+
+    // A dim sky with one bright pixel
+    let mut image = Rgb32FImage::from_pixel(32, 16, Rgb([0.1, 0.1, 0.1]));
+    image.put_pixel(20, 4, Rgb([1000.0, 1000.0, 1000.0]));
+    let map = EnvironmentMap::new(&DynamicImage::ImageRgb32F(image), 1.0).unwrap();
+    let background = Background::Environment(map);
+
+    let mut rng = Rng::new(4);
+    let n = 20000;
+    let mut bright = 0;
+    // Monte Carlo estimate of the total power, which must not depend on the sampling
+    let mut power = 0.0;
+    for _ in 0..n {
+        let sample = background.sample(Vector3::zero(), &mut rng);
+        if sample.radiance.red > 1.0 {
+            bright += 1;
+        }
+        assert!(sample.pdf > 0.0);
+        power += sample.radiance.red as f64 / sample.pdf / n as f64;
+    }
+    assert!(bright > n / 2);
+
+    let solid_angle = |y: f64| {
+        let theta = |y: f64| PI * y / 16.0;
+        2.0 * PI / 32.0 * (theta(y).cos() - theta(y + 1.0).cos())
+    };
+    let expected = 1000.0 * solid_angle(4.0) + 0.1 * (4.0 * PI - solid_angle(4.0));
+    assert!((power - expected).abs() < 0.05 * expected);
+}
+
+#[test]
+fn test_empty_environment_map_is_rejected() {
+    assert!(EnvironmentMap::new(&DynamicImage::new_rgb32f(0, 0), 1.0).is_none());
+    assert!(EnvironmentMap::new(&DynamicImage::new_rgb32f(4, 0), 1.0).is_none());
+}
@@ -0,0 +1,472 @@
+//! Bounding volume hierarchies, used to avoid testing every object against every ray.
+//!
+//! See https://pbr-book.org/3ed-2018/Primitives_and_Intersection_Acceleration/Bounding_Volume_Hierarchies
+
+use crate::math::Point;
+use crate::rendering::Ray;
+
+/// An axis-aligned bounding box.
+#[derive(Copy, Clone, Debug)]
+pub struct BoundingBox {
+    pub min: Point,
+    pub max: Point,
+}
+
+impl BoundingBox {
+    /// A box containing nothing. Growing it by a point gives a box containing only that point.
+    pub fn empty() -> BoundingBox {
+        BoundingBox {
+            min: Point::from_list([f64::INFINITY; 3]),
+            max: Point::from_list([f64::NEG_INFINITY; 3]),
+        }
+    }
+
+    pub fn from_points(points: &[Point]) -> BoundingBox {
+        points
+            .iter()
+            .fold(BoundingBox::empty(), |bounds, p| bounds.grow(p))
+    }
+
+    pub fn grow(&self, point: &Point) -> BoundingBox {
+        BoundingBox {
+            min: Point {
+                x: self.min.x.min(point.x),
+                y: self.min.y.min(point.y),
+                z: self.min.z.min(point.z),
+            },
+            max: Point {
+                x: self.max.x.max(point.x),
+                y: self.max.y.max(point.y),
+                z: self.max.z.max(point.z),
+            },
+        }
+    }
+
+    pub fn union(&self, other: &BoundingBox) -> BoundingBox {
+        self.grow(&other.min).grow(&other.max)
+    }
+
+    pub fn centroid(&self) -> Point {
+        Point {
+            x: (self.min.x + self.max.x) * 0.5,
+            y: (self.min.y + self.max.y) * 0.5,
+            z: (self.min.z + self.max.z) * 0.5,
+        }
+    }
+
+    /// The eight corners of the box.
+    pub fn corners(&self) -> [Point; 8] {
+        let (a, b) = (self.min, self.max);
+        [
+            Point::from_list([a.x, a.y, a.z]),
+            Point::from_list([b.x, a.y, a.z]),
+            Point::from_list([a.x, b.y, a.z]),
+            Point::from_list([b.x, b.y, a.z]),
+            Point::from_list([a.x, a.y, b.z]),
+            Point::from_list([b.x, a.y, b.z]),
+            Point::from_list([a.x, b.y, b.z]),
+            Point::from_list([b.x, b.y, b.z]),
+        ]
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let d = self.max - self.min;
+        if d.x < 0. || d.y < 0. || d.z < 0. {
+            return 0.;
+        }
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    /// Whether the point is inside the box, or at most `epsilon` outside it.
+    pub fn contains(&self, point: &Point, epsilon: f64) -> bool {
+        point.x >= self.min.x - epsilon
+            && point.x <= self.max.x + epsilon
+            && point.y >= self.min.y - epsilon
+            && point.y <= self.max.y + epsilon
+            && point.z >= self.min.z - epsilon
+            && point.z <= self.max.z + epsilon
+    }
+
+    /// Distances along the ray to where it enters and leaves the box, negative behind
+    /// its origin. Returns `None` if the ray's line misses the box.
+    pub fn span(&self, ray: &Ray) -> Option<(f64, f64)> {
+        let inverse_direction = [
+            1.0 / ray.direction.x,
+            1.0 / ray.direction.y,
+            1.0 / ray.direction.z,
+        ];
+        self.slab_span(ray, &inverse_direction)
+    }
+
+    /// `span`, with the inverse of the ray direction computed by the caller, using the
+    /// slab method.
+    fn slab_span(&self, ray: &Ray, inverse_direction: &[f64; 3]) -> Option<(f64, f64)> {
+        let (mut enter, mut exit) = (f64::NEG_INFINITY, f64::INFINITY);
+        for (axis, inverse) in inverse_direction.iter().enumerate() {
+            let origin = BoundingBox::axis(&ray.origin, axis);
+            let t0 = (BoundingBox::axis(&self.min, axis) - origin) * inverse;
+            let t1 = (BoundingBox::axis(&self.max, axis) - origin) * inverse;
+            enter = enter.max(t0.min(t1));
+            exit = exit.min(t0.max(t1));
+        }
+        if enter <= exit {
+            Some((enter, exit))
+        } else {
+            None
+        }
+    }
+
+    /// Distance along the ray to where it enters the box. Returns `None` if the ray
+    /// misses the box or enters it further away than `max_distance`.
+    fn intersect(&self, ray: &Ray, inverse_direction: &[f64; 3], max_distance: f64) -> Option<f64> {
+        let (enter, exit) = self.slab_span(ray, inverse_direction)?;
+        let enter = enter.max(0.0);
+        if enter <= exit.min(max_distance) {
+            Some(enter)
+        } else {
+            None
+        }
+    }
+
+    fn axis(point: &Point, axis: usize) -> f64 {
+        match axis {
+            0 => point.x,
+            1 => point.y,
+            _ => point.z,
+        }
+    }
+}
+
+enum NodeKind {
+    /// Objects `indices[first..first + count]`
+    Leaf { first: usize, count: usize },
+    /// The left child always directly follows its parent
+    Interior { right: usize, axis: usize },
+}
+
+struct Node {
+    bounds: BoundingBox,
+    kind: NodeKind,
+}
+
+/// A bounding volume hierarchy over a list of objects, identified by their index.
+///
+/// The hierarchy only knows the bounding box of each object; the caller supplies the
+/// actual intersection test when tracing.
+pub struct Bvh {
+    nodes: Vec<Node>,
+    indices: Vec<usize>,
+}
+
+const BUCKETS: usize = 12;
+/// Maximum depth of the hierarchy, which bounds the traversal stack
+const MAX_DEPTH: usize = 64;
+const MAX_LEAF_SIZE: usize = 4;
+/// Cost of an intersection test relative to a traversal step
+const INTERSECTION_COST: f64 = 2.0;
+
+impl Bvh {
+    /// Build a hierarchy over the objects, given as their index and bounding box, by
+    /// splitting along the surface area heuristic.
+    pub fn build(objects: Vec<(usize, BoundingBox)>) -> Bvh {
+        let mut bvh = Bvh {
+            nodes: Vec::with_capacity(objects.len() * 2),
+            indices: Vec::with_capacity(objects.len()),
+        };
+
+        let mut objects = objects;
+        if !objects.is_empty() {
+            bvh.build_node(&mut objects, 1);
+        }
+        bvh
+    }
+
+    fn build_node(&mut self, objects: &mut [(usize, BoundingBox)], depth: usize) -> usize {
+        let bounds = objects
+            .iter()
+            .fold(BoundingBox::empty(), |b, (_, o)| b.union(o));
+        let centroid_bounds = objects
+            .iter()
+            .fold(BoundingBox::empty(), |b, (_, o)| b.grow(&o.centroid()));
+
+        let node_index = self.nodes.len();
+        self.nodes.push(Node {
+            bounds,
+            kind: NodeKind::Leaf {
+                first: self.indices.len(),
+                count: objects.len(),
+            },
+        });
+
+        let split = if objects.len() <= 1 || depth >= MAX_DEPTH - 1 {
+            None
+        } else {
+            Self::find_split(objects, &bounds, &centroid_bounds)
+        };
+
+        match split {
+            None => {
+                self.indices.extend(objects.iter().map(|(i, _)| *i));
+            }
+            Some((axis, split_position)) => {
+                let mut mid = partition(objects, |(_, o)| {
+                    BoundingBox::axis(&o.centroid(), axis) < split_position
+                });
+                if mid == 0 || mid == objects.len() {
+                    mid = objects.len() / 2;
+                }
+
+                let (left, right) = objects.split_at_mut(mid);
+                self.build_node(left, depth + 1);
+                let right_index = self.build_node(right, depth + 1);
+                self.nodes[node_index].kind = NodeKind::Interior {
+                    right: right_index,
+                    axis,
+                };
+            }
+        }
+
+        node_index
+    }
+
+    /// Find the best axis and position to split the objects at, or `None` if it is
+    /// cheaper to make a leaf.
+    fn find_split(
+        objects: &[(usize, BoundingBox)],
+        bounds: &BoundingBox,
+        centroid_bounds: &BoundingBox,
+    ) -> Option<(usize, f64)> {
+        let extent = centroid_bounds.max - centroid_bounds.min;
+        let axis = if extent.x > extent.y && extent.x > extent.z {
+            0
+        } else if extent.y > extent.z {
+            1
+        } else {
+            2
+        };
+
+        let low = BoundingBox::axis(&centroid_bounds.min, axis);
+        let high = BoundingBox::axis(&centroid_bounds.max, axis);
+        if high - low <= 0.0 {
+            // All centroids coincide, so the heuristic cannot tell the objects apart.
+            // Nothing lies left of `low`, which makes the caller split them in half.
+            return if objects.len() > MAX_LEAF_SIZE {
+                Some((axis, low))
+            } else {
+                None
+            };
+        }
+
+        let bucket_of = |o: &BoundingBox| {
+            let offset = (BoundingBox::axis(&o.centroid(), axis) - low) / (high - low);
+            ((offset * BUCKETS as f64) as usize).min(BUCKETS - 1)
+        };
+
+        let mut bucket_counts = [0usize; BUCKETS];
+        let mut bucket_bounds = [BoundingBox::empty(); BUCKETS];
+        for (_, o) in objects.iter() {
+            let b = bucket_of(o);
+            bucket_counts[b] += 1;
+            bucket_bounds[b] = bucket_bounds[b].union(o);
+        }
+
+        let mut best_cost = f64::INFINITY;
+        let mut best_bucket = 0;
+        for split in 1..BUCKETS {
+            let (mut left_bounds, mut right_bounds) = (BoundingBox::empty(), BoundingBox::empty());
+            let (mut left_count, mut right_count) = (0, 0);
+            for b in 0..split {
+                left_bounds = left_bounds.union(&bucket_bounds[b]);
+                left_count += bucket_counts[b];
+            }
+            for b in split..BUCKETS {
+                right_bounds = right_bounds.union(&bucket_bounds[b]);
+                right_count += bucket_counts[b];
+            }
+
+            let cost = 1.0
+                + INTERSECTION_COST
+                    * (left_count as f64 * left_bounds.surface_area()
+                        + right_count as f64 * right_bounds.surface_area())
+                    / bounds.surface_area().max(f64::MIN_POSITIVE);
+            if cost < best_cost {
+                best_cost = cost;
+                best_bucket = split;
+            }
+        }
+
+        let leaf_cost = INTERSECTION_COST * objects.len() as f64;
+        if objects.len() <= MAX_LEAF_SIZE && leaf_cost <= best_cost {
+            return None;
+        }
+
+        Some((
+            axis,
+            low + (high - low) * best_bucket as f64 / BUCKETS as f64,
+        ))
+    }
+
+    /// Find the closest object hit by the ray. `intersect` is called with the index of
+    /// each object whose bounding box the ray hits, and returns the distance to it,
+    /// together with anything else the caller wants to know about the closest hit.
+    pub fn trace<F, H>(&self, ray: &Ray, mut intersect: F) -> Option<(usize, f64, H)>
+    where
+        F: FnMut(usize) -> Option<(f64, H)>,
+    {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let inverse_direction = [
+            1.0 / ray.direction.x,
+            1.0 / ray.direction.y,
+            1.0 / ray.direction.z,
+        ];
+        let direction_is_negative = [
+            ray.direction.x < 0.,
+            ray.direction.y < 0.,
+            ray.direction.z < 0.,
+        ];
+
+        let mut closest: Option<(usize, f64, H)> = None;
+        let mut stack = [0usize; MAX_DEPTH];
+        let mut stack_size = 1;
+        while stack_size > 0 {
+            stack_size -= 1;
+            let node_index = stack[stack_size];
+            let node = &self.nodes[node_index];
+            let max_distance = closest.as_ref().map_or(f64::INFINITY, |(_, d, _)| *d);
+            if node
+                .bounds
+                .intersect(ray, &inverse_direction, max_distance)
+                .is_none()
+            {
+                continue;
+            }
+
+            match node.kind {
+                NodeKind::Leaf { first, count } => {
+                    for &index in &self.indices[first..first + count] {
+                        if let Some((distance, hit)) = intersect(index) {
+                            if distance < closest.as_ref().map_or(f64::INFINITY, |(_, d, _)| *d) {
+                                closest = Some((index, distance, hit));
+                            }
+                        }
+                    }
+                }
+                NodeKind::Interior { right, axis } => {
+                    // Visit the nearer child first, so that the farther one can often be skipped
+                    let (near, far) = if direction_is_negative[axis] {
+                        (right, node_index + 1)
+                    } else {
+                        (node_index + 1, right)
+                    };
+                    stack[stack_size] = far;
+                    stack[stack_size + 1] = near;
+                    stack_size += 2;
+                }
+            }
+        }
+
+        closest
+    }
+
+    /// Call `visit` with every object whose bounding box contains the point, with a
+    /// margin of `epsilon`.
+    pub fn query_point<F>(&self, point: &Point, epsilon: f64, mut visit: F)
+    where
+        F: FnMut(usize),
+    {
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        let mut stack = vec![0];
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index];
+            if !node.bounds.contains(point, epsilon) {
+                continue;
+            }
+
+            match node.kind {
+                NodeKind::Leaf { first, count } => {
+                    self.indices[first..first + count]
+                        .iter()
+                        .for_each(|&index| visit(index));
+                }
+                NodeKind::Interior { right, .. } => {
+                    stack.push(node_index + 1);
+                    stack.push(right);
+                }
+            }
+        }
+    }
+
+    /// Bounding box of everything in the hierarchy.
+    pub fn bounds(&self) -> BoundingBox {
+        self.nodes
+            .first()
+            .map(|n| n.bounds)
+            .unwrap_or_else(BoundingBox::empty)
+    }
+}
+
+/// Move the elements satisfying the predicate to the front of the slice, and return
+/// how many there are.
+fn partition<T, F: Fn(&T) -> bool>(items: &mut [T], predicate: F) -> usize {
+    let mut mid = 0;
+    for i in 0..items.len() {
+        if predicate(&items[i]) {
+            items.swap(i, mid);
+            mid += 1;
+        }
+    }
+    mid
+}
+
+#[test]
+fn test_bvh_finds_closest_object() {
+    use crate::math::Vector3;
+
+    // A row of unit boxes along the negative z axis
+    let boxes: Vec<(usize, BoundingBox)> = (0..20)
+        .map(|i| {
+            let z = -2.0 - 3.0 * i as f64;
+            (
+                i,
+                BoundingBox {
+                    min: Point::from_list([-0.5, -0.5, z - 0.5]),
+                    max: Point::from_list([0.5, 0.5, z + 0.5]),
+                },
+            )
+        })
+        .collect();
+    let bvh = Bvh::build(boxes.clone());
+
+    let ray = Ray {
+        origin: Point::zero(),
+        direction: Vector3 {
+            x: 0.,
+            y: 0.,
+            z: -1.,
+        },
+    };
+
+    let mut tested = 0;
+    let hit = bvh.trace(&ray, |i| {
+        tested += 1;
+        Some((1.5 + 3.0 * i as f64, ()))
+    });
+    assert_eq!(hit.map(|(i, _, _)| i), Some(0));
+    assert!(tested < boxes.len());
+
+    let miss = Ray {
+        origin: Point::zero(),
+        direction: Vector3 {
+            x: 0.,
+            y: 1.,
+            z: 0.,
+        },
+    };
+    assert!(bvh.trace(&miss, |_| Some((1.0, ()))).is_none());
+}
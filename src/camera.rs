@@ -0,0 +1,104 @@
+use crate::math::{Point, Vector3};
+
+/// A pinhole camera at `position`, looking towards `look_at`.
+pub struct Camera {
+    pub position: Point,
+    pub look_at: Point,
+    /// Which way is up. Does not need to be perpendicular to the viewing direction.
+    pub up: Vector3,
+    /// Vertical field of view in degrees
+    pub fov: f64,
+    /// Width divided by height of the image plane. If `None`, the aspect ratio of the
+    /// rendered image is used.
+    pub aspect: Option<f64>,
+}
+
+impl Camera {
+    /// A camera at the origin looking down the negative z axis.
+    pub fn new(fov: f64) -> Camera {
+        Camera {
+            position: Point::zero(),
+            look_at: Point {
+                x: 0.,
+                y: 0.,
+                z: -1.,
+            },
+            up: Vector3 {
+                x: 0.,
+                y: 1.,
+                z: 0.,
+            },
+            fov,
+            aspect: None,
+        }
+    }
+
+    /// Orthonormal basis `(right, up, forward)` of the camera.
+    pub fn basis(&self) -> (Vector3, Vector3, Vector3) {
+        let forward = (self.look_at - self.position).normalize();
+        let right = forward.cross(&self.up).normalize();
+        let up = right.cross(&forward);
+        (right, up, forward)
+    }
+
+    /// The point seen through the given point on the image, at distance `depth` in
+    /// front of the camera along the viewing direction.
+    ///
+    /// Image coordinates are in pixels: `(0, 0)` is the top left corner of the image,
+    /// and `(width, height)` the bottom right.
+    pub fn image_to_world(
+        &self,
+        image_x: f64,
+        image_y: f64,
+        width: u32,
+        height: u32,
+        depth: f64,
+    ) -> Point {
+        let fov_adjustment = (self.fov.to_radians() / 2.0).tan();
+        let aspect_ratio = self.aspect.unwrap_or((width as f64) / (height as f64));
+        let sensor_x = (((image_x / width as f64) * 2.0 - 1.0) * aspect_ratio) * fov_adjustment;
+        let sensor_y = (1.0 - (image_y / height as f64) * 2.0) * fov_adjustment;
+
+        let (right, up, forward) = self.basis();
+        let direction = right * sensor_x + up * sensor_y + forward;
+        (self.position.as_vector() + direction * depth).as_point()
+    }
+}
+
+#[test]
+fn test_default_camera_looks_down_negative_z() {
+    let camera = Camera::new(90.);
+
+    let center = camera.image_to_world(50., 50., 100, 100, 1.);
+    assert!(center.x.abs() < 0.0001);
+    assert!(center.y.abs() < 0.0001);
+    assert!((center.z + 1.).abs() < 0.0001);
+
+    // With a 90 degree field of view, the top left corner is at 45 degrees both ways
+    let corner = camera.image_to_world(0., 0., 100, 100, 1.);
+    assert!((corner.x + 1.).abs() < 0.0001);
+    assert!((corner.y - 1.).abs() < 0.0001);
+}
+
+#[test]
+fn test_look_at_camera() {
+    let camera = Camera {
+        position: Point::from_list([5., 0., 0.]),
+        look_at: Point::zero(),
+        up: Vector3 {
+            x: 0.,
+            y: 1.,
+            z: 0.,
+        },
+        fov: 60.,
+        aspect: None,
+    };
+
+    let center = camera.image_to_world(20., 10., 40, 20, 5.);
+    assert!(center.distance(&Point::zero()) < 0.0001);
+
+    // Looking down the negative x axis, the right of the image is towards negative z
+    let (right, up, _) = camera.basis();
+    assert!((right.z + 1.).abs() < 0.0001);
+    assert!((up.y - 1.).abs() < 0.0001);
+}
@@ -1,14 +1,112 @@
 use std::ops::Add;
 use std::ops::Mul;
 
-use image::{Pixel, Rgba};
+use image::{DynamicImage, Pixel, Rgb32FImage, Rgba};
+use serde::Deserialize;
 
-// Comment out unused code
-// const GAMMA: f32 = 2.2;
+use crate::sampling::Rng;
 
-// fn gamma_encode(linear: f32) -> f32 {
-//     linear.powf(1.0 / GAMMA)
-// }
+/// Colors are linear: rendering adds and scales light, which only works in a linear
+/// color space. Image files store sRGB encoded values instead, which are decoded when
+/// textures are read and encoded when images are written.
+///
+/// See https://en.wikipedia.org/wiki/SRGB#Transfer_function_(%22gamma%22)
+pub fn srgb_encode(linear: f32) -> f32 {
+    if linear <= 0.0031308 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// The inverse of `srgb_encode`.
+pub fn srgb_decode(encoded: f32) -> f32 {
+    if encoded <= 0.04045 {
+        encoded / 12.92
+    } else {
+        ((encoded + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// The pixels of an image as linear colors, so that textures are decoded once when
+/// they are loaded instead of at every lookup. Floating point images are linear
+/// already, 8 and 16 bit ones are decoded from sRGB at full precision.
+pub fn linear_rgb(image: &DynamicImage) -> Rgb32FImage {
+    let is_linear = matches!(
+        image,
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+    );
+    let mut linear = image.to_rgb32f();
+    if !is_linear {
+        for value in linear.iter_mut() {
+            *value = srgb_decode(*value);
+        }
+    }
+    linear
+}
+
+/// An 8 bit sRGB value for a linear value, clamped to the displayable range and
+/// rounded to the nearest step. `offset`, in steps, is added before rounding.
+fn encode_u8(linear: f32, offset: f32) -> u8 {
+    let encoded = srgb_encode(linear.clamp(0.0, 1.0)) * 255.0;
+    (encoded + offset + 0.5).floor().clamp(0.0, 255.0) as u8
+}
+
+/// How the unbounded radiance of a render is squeezed into the displayable range,
+/// after scaling it by the exposure.
+///
+/// See https://64.github.io/tonemapping/
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ToneMap {
+    /// Anything brighter than white is cut off
+    #[default]
+    Clamp,
+    /// Compresses bright light smoothly, keeping hues
+    Reinhard,
+    /// The filmic curve of the Academy Color Encoding System: more contrast, and very
+    /// bright colors fade to white
+    Aces,
+}
+
+impl ToneMap {
+    /// A displayable color, with components between 0 and 1, for a linear `color`.
+    /// `exposure` is in stops (EV): each step up doubles the brightness.
+    pub fn apply(&self, color: Color, exposure: f32) -> Color {
+        let color = color * 2f32.powf(exposure);
+        match self {
+            ToneMap::Clamp => color.clamp(),
+            ToneMap::Reinhard => {
+                let luminance = color.luminance();
+                if luminance <= 0.0 {
+                    return BLACK;
+                }
+                let mapped = color * (1.0 / (1.0 + luminance));
+                // Saturated colors can still be out of range. Scale them down rather
+                // than clamping, which would shift their hue.
+                let max = mapped.red.max(mapped.green).max(mapped.blue);
+                if max > 1.0 {
+                    mapped * (1.0 / max)
+                } else {
+                    mapped
+                }
+            }
+            ToneMap::Aces => {
+                // Krzysztof Narkowicz's fit of the ACES curve
+                let curve = |x: f32| {
+                    let x = x.max(0.0) * 0.6;
+                    (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+                };
+                Color {
+                    red: curve(color.red),
+                    green: curve(color.green),
+                    blue: curve(color.blue),
+                }
+                .clamp()
+            }
+        }
+    }
+}
 
 #[derive(Debug, Clone, Copy)]
 pub struct Color {
@@ -18,38 +116,54 @@ pub struct Color {
 }
 
 impl Color {
+    /// sRGB encoded, rounded to the nearest 8 bit value.
     pub fn to_rgba(&self) -> Rgba<u8> {
-        Rgba([
-            ((self.red) * 255.) as u8,
-            ((self.green) * 255.) as u8,
-            ((self.blue) * 255.) as u8,
-            0,
-        ])
+        let [red, green, blue] = self.to_srgb8(None);
+        Rgba([red, green, blue, 255])
     }
 
     // TODO: slice instead?
     pub fn to_vec(&self) -> Vec<u8> {
-        vec![
-            ((self.red) * 255.) as u8,
-            ((self.green) * 255.) as u8,
-            ((self.blue) * 255.) as u8,
-            255 as u8,
+        self.to_rgba().0.to_vec()
+    }
+
+    /// sRGB encoded 8 bit values. With `dither`, a little noise is added before
+    /// rounding, which turns banding in smooth gradients into fine grain.
+    pub fn to_srgb8(&self, dither: Option<&mut Rng>) -> [u8; 3] {
+        let mut offsets = [0.0; 3];
+        if let Some(rng) = dither {
+            // Triangular noise between -1 and 1 step
+            for offset in offsets.iter_mut() {
+                *offset = (rng.next_f64() - rng.next_f64()) as f32;
+            }
+        }
+        [
+            encode_u8(self.red, offsets[0]),
+            encode_u8(self.green, offsets[1]),
+            encode_u8(self.blue, offsets[2]),
         ]
     }
 
+    /// A color from sRGB encoded 8 bit values, like texture pixels.
     pub fn from_rgba(rgba: &Rgba<u8>) -> Self {
+        let decode = |value: u8| srgb_decode(value as f32 / 255.);
         Color {
-            red: (rgba.channels()[0] as f32) / 255.,
-            blue: (rgba.channels()[1] as f32) / 255.,
-            green: (rgba.channels()[2] as f32) / 255.,
+            red: decode(rgba.channels()[0]),
+            green: decode(rgba.channels()[1]),
+            blue: decode(rgba.channels()[2]),
         }
     }
 
+    /// Perceived brightness, for linear sRGB.
+    pub fn luminance(&self) -> f32 {
+        0.2126 * self.red + 0.7152 * self.green + 0.0722 * self.blue
+    }
+
     pub fn clamp(&self) -> Color {
         Color {
-            red: self.red.min(1.0).max(0.0),
-            blue: self.blue.min(1.0).max(0.0),
-            green: self.green.min(1.0).max(0.0),
+            red: self.red.clamp(0.0, 1.0),
+            blue: self.blue.clamp(0.0, 1.0),
+            green: self.green.clamp(0.0, 1.0),
         }
     }
 }
@@ -92,3 +206,95 @@ pub const BLACK: Color = Color {
     green: 0.,
     blue: 0.,
 };
+
+pub const WHITE: Color = Color {
+    red: 1.,
+    green: 1.,
+    blue: 1.,
+};
+
+#[test]
+fn test_srgb_round_trip() {
+    for i in 0..=255 {
+        let encoded = i as f32 / 255.0;
+        assert!((srgb_encode(srgb_decode(encoded)) - encoded).abs() < 1e-5);
+    }
+    // Middle grey is about 18% in linear light
+    assert!((srgb_decode(0.5) - 0.214).abs() < 0.001);
+
+    let color = Color::from_rgba(&Rgba([255, 128, 0, 255]));
+    assert_eq!(color.red, 1.0);
+    assert!((color.green - srgb_decode(128. / 255.)).abs() < 1e-6);
+    assert_eq!(color.blue, 0.0);
+    assert_eq!(color.to_rgba(), Rgba([255, 128, 0, 255]));
+}
+
+#[test]
+fn test_dithering_keeps_the_average() {
+    // Halfway between two 8 bit steps
+    let linear = srgb_decode(100.5 / 255.0);
+    let color = Color {
+        red: linear,
+        green: linear,
+        blue: linear,
+    };
+    assert_eq!(color.to_srgb8(None), [101, 101, 101]);
+
+    let mut rng = Rng::new(1);
+    let n = 10000;
+    let mut sum = 0.0;
+    for _ in 0..n {
+        let [red, _, _] = color.to_srgb8(Some(&mut rng));
+        assert!((99..=102).contains(&red));
+        sum += red as f64;
+    }
+    assert!((sum / n as f64 - 100.5).abs() < 0.05);
+}
+
+#[test]
+fn test_tone_maps_keep_bright_light_displayable() {
+    let grey = Color {
+        red: 0.18,
+        green: 0.18,
+        blue: 0.18,
+    };
+    let bright = Color {
+        red: 30.0,
+        green: 6.0,
+        blue: 1.0,
+    };
+
+    for tone_map in [ToneMap::Clamp, ToneMap::Reinhard, ToneMap::Aces] {
+        let mapped = tone_map.apply(bright, 0.0);
+        assert!(mapped.red <= 1.0 && mapped.green <= 1.0 && mapped.blue <= 1.0);
+        // Brighter stays brighter
+        let darker = tone_map.apply(grey, 0.0);
+        assert!(tone_map.apply(grey, 1.0).red > darker.red);
+    }
+
+    // Clamping loses the hue of bright light, Reinhard keeps it
+    let clamped = ToneMap::Clamp.apply(bright, 0.0);
+    assert_eq!(clamped.red, clamped.green);
+    let reinhard = ToneMap::Reinhard.apply(bright, 0.0);
+    assert!((reinhard.red / reinhard.green - 5.0).abs() < 1e-4);
+
+    // One stop up doubles the light
+    assert!((ToneMap::Clamp.apply(grey, 1.0).red - 0.36).abs() < 1e-6);
+}
+
+#[test]
+fn test_linear_rgb_keeps_precision() {
+    use image::{ImageBuffer, Rgb};
+
+    // Between two 8 bit steps
+    let value = 0x8080 + 0x40;
+    let image: ImageBuffer<Rgb<u16>, Vec<u16>> = ImageBuffer::from_pixel(1, 1, Rgb([value; 3]));
+    let linear = linear_rgb(&DynamicImage::ImageRgb16(image));
+    let expected = srgb_decode(value as f32 / u16::MAX as f32);
+    assert!((linear.get_pixel(0, 0)[0] - expected).abs() < 1e-6);
+
+    // Floating point images are not decoded again
+    let image = Rgb32FImage::from_pixel(1, 1, Rgb([0.5, 2.0, 0.0]));
+    let linear = linear_rgb(&DynamicImage::ImageRgb32F(image));
+    assert_eq!(linear.get_pixel(0, 0).0, [0.5, 2.0, 0.0]);
+}
@@ -0,0 +1,253 @@
+//! Constructive solid geometry: solids combined by union, intersection and difference.
+//!
+//! Combining solids needs more than the nearest hit along a ray. Every solid element
+//! gives the stretches of the ray that lie inside it as `Interval`s, and a `Csg`
+//! combines the intervals of its two children the way it combines the solids. The
+//! nearest boundary in front of the ray is then the hit.
+
+use std::cmp::Ordering;
+
+use serde::Deserialize;
+
+use crate::bvh::BoundingBox;
+use crate::math::{Point, Vector3};
+use crate::rendering::Ray;
+use crate::scene::{Element, Intersectable, Material, TextureCoords};
+
+/// A stretch of a ray inside a solid, from where the ray enters it to where it leaves.
+/// Distances are negative behind the ray's origin, and may be infinite for unbounded
+/// solids.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Interval {
+    pub enter: f64,
+    pub exit: f64,
+}
+
+impl Interval {
+    pub fn everywhere() -> Interval {
+        Interval {
+            enter: f64::NEG_INFINITY,
+            exit: f64::INFINITY,
+        }
+    }
+}
+
+/// The nearest finite boundary in front of the ray, if any.
+pub fn first_boundary(intervals: &[Interval]) -> Option<f64> {
+    intervals
+        .iter()
+        .flat_map(|interval| [interval.enter, interval.exit])
+        .find(|&t| t > 0. && t.is_finite())
+}
+
+/// The parts of sorted intervals between `min` and `max`.
+pub fn clip(intervals: &[Interval], min: f64, max: f64) -> Vec<Interval> {
+    intervals
+        .iter()
+        .map(|interval| Interval {
+            enter: interval.enter.max(min),
+            exit: interval.exit.min(max),
+        })
+        .filter(|interval| interval.enter < interval.exit)
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CsgOperation {
+    /// Inside either child
+    Union,
+    /// Inside both children
+    Intersection,
+    /// Inside the first child, but not the second
+    Difference,
+}
+
+impl CsgOperation {
+    fn contains(self, in_left: bool, in_right: bool) -> bool {
+        match self {
+            CsgOperation::Union => in_left || in_right,
+            CsgOperation::Intersection => in_left && in_right,
+            CsgOperation::Difference => in_left && !in_right,
+        }
+    }
+
+    /// Combines two sorted lists of intervals, by walking through all their
+    /// boundaries in order and keeping track of which children the ray is in.
+    pub fn combine(self, left: &[Interval], right: &[Interval]) -> Vec<Interval> {
+        let boundaries = |intervals: &[Interval], is_left: bool| {
+            intervals
+                .iter()
+                .flat_map(|i| [i.enter, i.exit])
+                .map(move |t| (t, is_left))
+                .collect::<Vec<_>>()
+        };
+        let mut boundaries = [boundaries(left, true), boundaries(right, false)].concat();
+        boundaries.sort_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+
+        let (mut in_left, mut in_right) = (false, false);
+        let mut enter = None;
+        let mut combined = vec![];
+        for (t, is_left) in boundaries {
+            if is_left {
+                in_left = !in_left;
+            } else {
+                in_right = !in_right;
+            }
+            match (self.contains(in_left, in_right), enter) {
+                (true, None) => enter = Some(t),
+                (false, Some(start)) => {
+                    if t > start {
+                        combined.push(Interval {
+                            enter: start,
+                            exit: t,
+                        });
+                    }
+                    enter = None;
+                }
+                _ => {}
+            }
+        }
+        combined
+    }
+}
+
+/// Two solid elements combined into one. It is made of the material of its first
+/// child, so that surfaces cut out by a difference look like the rest of the part.
+///
+/// Children must be solids, see `Element::is_solid`. CSG elements do not glow.
+pub struct Csg {
+    pub operation: CsgOperation,
+    pub left: Box<Element>,
+    pub right: Box<Element>,
+}
+
+impl Csg {
+    pub fn material(&self) -> &Material {
+        self.left.material()
+    }
+
+    pub fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        self.operation
+            .combine(&self.left.intervals(ray), &self.right.intervals(ray))
+    }
+
+    pub fn bounding_box(&self) -> Option<BoundingBox> {
+        let (left, right) = (self.left.bounding_box(), self.right.bounding_box());
+        match self.operation {
+            CsgOperation::Union => Some(left?.union(&right?)),
+            CsgOperation::Intersection => match (left, right) {
+                (Some(left), Some(right)) => Some(BoundingBox {
+                    min: Point {
+                        x: left.min.x.max(right.min.x),
+                        y: left.min.y.max(right.min.y),
+                        z: left.min.z.max(right.min.z),
+                    },
+                    max: Point {
+                        x: left.max.x.min(right.max.x),
+                        y: left.max.y.min(right.max.y),
+                        z: left.max.z.min(right.max.z),
+                    },
+                }),
+                (bounds, None) | (None, bounds) => bounds,
+            },
+            CsgOperation::Difference => left,
+        }
+    }
+
+    /// The solids the combination is built from, and whether each one's surface faces
+    /// the other way in the result, as it does for the solids that are cut away.
+    fn leaves(&self) -> Vec<(&Element, bool)> {
+        let mut leaves = vec![];
+        for (child, flipped) in [
+            (&self.left, false),
+            (&self.right, self.operation == CsgOperation::Difference),
+        ] {
+            match **child {
+                Element::Csg(ref csg) => leaves.extend(
+                    csg.leaves()
+                        .into_iter()
+                        .map(|(leaf, inner)| (leaf, inner != flipped)),
+                ),
+                ref leaf => leaves.push((leaf, flipped)),
+            }
+        }
+        leaves
+    }
+
+    /// Find the solid whose surface a point on the combination lies on, by looking
+    /// along each one's normal for the nearest of its boundaries.
+    fn locate(&self, point: &Point) -> Option<(&Element, bool)> {
+        self.leaves()
+            .into_iter()
+            .filter_map(|(leaf, flipped)| {
+                let probe = Ray {
+                    origin: *point,
+                    direction: leaf.normal(point),
+                };
+                leaf.intervals(&probe)
+                    .iter()
+                    .flat_map(|i| [i.enter, i.exit])
+                    .map(f64::abs)
+                    .filter(|distance| distance.is_finite())
+                    .min_by(|a, b| a.partial_cmp(b).unwrap())
+                    .map(|distance| (distance, leaf, flipped))
+            })
+            .min_by(|(a, _, _), (b, _, _)| a.partial_cmp(b).unwrap())
+            .map(|(_, leaf, flipped)| (leaf, flipped))
+    }
+}
+
+impl Intersectable for Csg {
+    fn intersect(&self, ray: &Ray) -> Option<f64> {
+        first_boundary(&self.intervals(ray))
+    }
+
+    fn surface_normal(&self, hit_point: &Point) -> Vector3 {
+        match self.locate(hit_point) {
+            Some((leaf, true)) => leaf.normal(hit_point) * -1.,
+            Some((leaf, false)) => leaf.normal(hit_point),
+            None => Vector3::zero(),
+        }
+    }
+
+    fn texture_coords(&self, hit_point: &Point) -> TextureCoords {
+        match self.locate(hit_point) {
+            Some((leaf, _)) => leaf.texture_coords(hit_point),
+            None => TextureCoords { x: 0., y: 0. },
+        }
+    }
+}
+
+#[test]
+fn test_combine_intervals() {
+    let interval = |enter, exit| Interval { enter, exit };
+    let left = [interval(1., 4.), interval(6., 8.)];
+    let right = [interval(3., 7.)];
+
+    assert_eq!(
+        CsgOperation::Union.combine(&left, &right),
+        vec![interval(1., 8.)]
+    );
+    assert_eq!(
+        CsgOperation::Intersection.combine(&left, &right),
+        vec![interval(3., 4.), interval(6., 7.)]
+    );
+    assert_eq!(
+        CsgOperation::Difference.combine(&left, &right),
+        vec![interval(1., 3.), interval(7., 8.)]
+    );
+    // Cutting away everything behind a plane
+    assert_eq!(
+        CsgOperation::Difference.combine(
+            &left,
+            &[Interval {
+                enter: f64::NEG_INFINITY,
+                exit: 2.
+            }]
+        ),
+        vec![interval(2., 4.), interval(6., 8.)]
+    );
+    assert_eq!(first_boundary(&[interval(-1., 3.)]), Some(3.));
+    assert_eq!(first_boundary(&[Interval::everywhere()]), None);
+}
@@ -0,0 +1,150 @@
+//! Writing rendered light as floating point images, without tone mapping, for
+//! compositing and other processing.
+//!
+//! OpenEXR and Radiance HDR files are written with the `image` crate. PFM, the
+//! Portable Float Map, is simple enough to write directly.
+//!
+//! See http://www.pauldebevec.com/Research/HDR/PFM/
+
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use image::{DynamicImage, ImageFormat, Rgb32FImage};
+
+#[derive(Debug)]
+pub enum HdrError {
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    Image {
+        path: PathBuf,
+        error: image::ImageError,
+    },
+}
+
+impl fmt::Display for HdrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HdrError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            HdrError::Image { path, error } => write!(f, "{}: {}", path.display(), error),
+        }
+    }
+}
+
+impl std::error::Error for HdrError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HdrFormat {
+    OpenExr,
+    /// Radiance RGBE, `.hdr`
+    Radiance,
+    Pfm,
+}
+
+impl HdrFormat {
+    /// The format for a file extension like `exr`, or `None` if it is not a floating
+    /// point format.
+    pub fn from_extension(extension: &str) -> Option<HdrFormat> {
+        match extension.to_ascii_lowercase().as_str() {
+            "exr" => Some(HdrFormat::OpenExr),
+            "hdr" => Some(HdrFormat::Radiance),
+            "pfm" => Some(HdrFormat::Pfm),
+            _ => None,
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<HdrFormat> {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(HdrFormat::from_extension)
+    }
+}
+
+/// Write linear RGB values to a file in the given format.
+pub fn save_hdr(image: &Rgb32FImage, path: &Path, format: HdrFormat) -> Result<(), HdrError> {
+    let image_error = |error| HdrError::Image {
+        path: path.to_path_buf(),
+        error,
+    };
+    match format {
+        HdrFormat::OpenExr => DynamicImage::ImageRgb32F(image.clone())
+            .save_with_format(path, ImageFormat::OpenExr)
+            .map_err(image_error),
+        HdrFormat::Radiance => DynamicImage::ImageRgb32F(image.clone())
+            .save_with_format(path, ImageFormat::Hdr)
+            .map_err(image_error),
+        HdrFormat::Pfm => {
+            let io_error = |error| HdrError::Io {
+                path: path.to_path_buf(),
+                error,
+            };
+            let mut writer = BufWriter::new(File::create(path).map_err(io_error)?);
+            write_pfm(image, &mut writer).map_err(io_error)?;
+            writer.flush().map_err(io_error)
+        }
+    }
+}
+
+/// Write a color PFM: a short text header, then little endian floats, bottom row
+/// first.
+pub fn write_pfm<W: Write>(image: &Rgb32FImage, writer: &mut W) -> std::io::Result<()> {
+    // A negative scale means little endian
+    write!(writer, "PF\n{} {}\n-1.0\n", image.width(), image.height())?;
+    for y in (0..image.height()).rev() {
+        for x in 0..image.width() {
+            for value in image.get_pixel(x, y).0 {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_write_pfm() {
+    use image::Rgb;
+
+    let mut image = Rgb32FImage::new(2, 2);
+    image.put_pixel(0, 0, Rgb([1.0, 2.0, 3.0]));
+    image.put_pixel(1, 1, Rgb([40.0, 0.5, 0.25]));
+    let mut bytes = vec![];
+    write_pfm(&image, &mut bytes).unwrap();
+
+    let header = b"PF\n2 2\n-1.0\n";
+    assert_eq!(&bytes[..header.len()], header);
+    let floats: Vec<f32> = bytes[header.len()..]
+        .chunks(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect();
+    assert_eq!(floats.len(), 12);
+    // The bottom row comes first
+    assert_eq!(&floats[3..6], &[40.0, 0.5, 0.25]);
+    assert_eq!(&floats[6..9], &[1.0, 2.0, 3.0]);
+}
+
+#[test]
+fn test_save_and_read_back_hdr_formats() {
+    use image::Rgb;
+
+    let image = Rgb32FImage::from_pixel(4, 3, Rgb([12.5, 0.25, 1.0]));
+    let directory = std::env::temp_dir();
+    for (name, format) in [
+        ("ray_tracing_test.exr", HdrFormat::OpenExr),
+        ("ray_tracing_test.hdr", HdrFormat::Radiance),
+    ] {
+        let path = directory.join(name);
+        assert_eq!(HdrFormat::from_path(&path), Some(format));
+        save_hdr(&image, &path, format).unwrap();
+        let read = image::open(&path).unwrap().to_rgb32f();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(read.dimensions(), (4, 3));
+        let pixel = read.get_pixel(3, 2);
+        assert!((pixel[0] - 12.5).abs() < 0.1);
+        assert!((pixel[1] - 0.25).abs() < 0.01);
+    }
+    assert_eq!(HdrFormat::from_extension("PFM"), Some(HdrFormat::Pfm));
+    assert_eq!(HdrFormat::from_extension("png"), None);
+}
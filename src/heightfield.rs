@@ -0,0 +1,225 @@
+//! Terrain given by a grid of heights, like a grayscale heightmap image.
+//!
+//! Each grid cell is drawn as two triangles between its four corner heights. Instead
+//! of testing every triangle, a ray walks through the cells below it in order, seen
+//! from above, and stops at the first cell it hits. Normals are interpolated between
+//! the grid points, so the terrain looks smooth.
+//!
+//! See https://www.scratchapixel.com/lessons/advanced-rendering/introduction-acceleration-structure/grid.html
+
+use image::DynamicImage;
+
+use crate::bvh::BoundingBox;
+use crate::math::{Point, Vector3};
+use crate::rendering::Ray;
+use crate::scene::{intersect_triangle, Intersectable, Material, TextureCoords};
+
+/// Terrain over the rectangle from `min` reaching `size.x` along x and `size.z` along
+/// z. Heights between 0 and 1 are raised from `min.y` by up to `size.y`.
+///
+/// Texture coordinates run from 0 to 1 over the rectangle, so an image laid over the
+/// terrain lines up with the heightmap.
+pub struct Heightfield {
+    pub min: Point,
+    pub size: Vector3,
+    pub material: Material,
+    /// Heights row by row, from `min.z` on
+    heights: Vec<f64>,
+    columns: usize,
+    rows: usize,
+    /// Normal at each grid point
+    normals: Vec<Vector3>,
+}
+
+impl Heightfield {
+    /// A heightfield from heights between 0 and 1, row by row. There must be at least
+    /// two rows of at least two heights, or `None` is returned.
+    pub fn new(
+        heights: Vec<f64>,
+        columns: usize,
+        min: Point,
+        size: Vector3,
+        material: Material,
+    ) -> Option<Heightfield> {
+        if columns < 2 || !heights.len().is_multiple_of(columns) || heights.len() / columns < 2 {
+            return None;
+        }
+        let mut heightfield = Heightfield {
+            min,
+            size,
+            material,
+            rows: heights.len() / columns,
+            heights,
+            columns,
+            normals: vec![],
+        };
+        heightfield.normals = (0..heightfield.rows)
+            .flat_map(|row| (0..columns).map(move |column| (column, row)))
+            .map(|(column, row)| heightfield.grid_normal(column, row))
+            .collect();
+        Some(heightfield)
+    }
+
+    /// A heightfield with a grid point for every pixel, black at the bottom and white
+    /// at the top. The top row of the image lies along `min.z`. 16 bit images keep
+    /// their precision.
+    pub fn from_image(
+        image: &DynamicImage,
+        min: Point,
+        size: Vector3,
+        material: Material,
+    ) -> Option<Heightfield> {
+        let image = image.to_luma16();
+        let heights = image
+            .pixels()
+            .map(|pixel| pixel.0[0] as f64 / u16::MAX as f64)
+            .collect();
+        Heightfield::new(heights, image.width() as usize, min, size, material)
+    }
+
+    pub fn bounding_box(&self) -> BoundingBox {
+        BoundingBox {
+            min: self.min,
+            max: (self.min.as_vector() + self.size).as_point(),
+        }
+    }
+
+    fn cell_size(&self) -> (f64, f64) {
+        (
+            self.size.x / (self.columns - 1) as f64,
+            self.size.z / (self.rows - 1) as f64,
+        )
+    }
+
+    fn vertex(&self, column: usize, row: usize) -> Point {
+        let (dx, dz) = self.cell_size();
+        Point {
+            x: self.min.x + column as f64 * dx,
+            y: self.min.y + self.heights[row * self.columns + column] * self.size.y,
+            z: self.min.z + row as f64 * dz,
+        }
+    }
+
+    /// The normal at a grid point, from the slope towards its neighbours.
+    fn grid_normal(&self, column: usize, row: usize) -> Vector3 {
+        let (left, right) = (column.saturating_sub(1), (column + 1).min(self.columns - 1));
+        let (back, front) = (row.saturating_sub(1), (row + 1).min(self.rows - 1));
+        let along_x = self.vertex(right, row) - self.vertex(left, row);
+        let along_z = self.vertex(column, front) - self.vertex(column, back);
+        along_z.cross(&along_x).normalize()
+    }
+
+    /// The cell the point lies over, clamped to the grid, and where in the cell it
+    /// lies, from 0 to 1 along x and z.
+    fn locate(&self, point: &Point) -> (usize, usize, f64, f64) {
+        let (dx, dz) = self.cell_size();
+        let x = ((point.x - self.min.x) / dx).clamp(0., (self.columns - 1) as f64);
+        let z = ((point.z - self.min.z) / dz).clamp(0., (self.rows - 1) as f64);
+        let column = (x as usize).min(self.columns - 2);
+        let row = (z as usize).min(self.rows - 2);
+        (column, row, x - column as f64, z - row as f64)
+    }
+
+    /// The two triangles of a cell. The cell is split along the diagonal from its
+    /// first corner, and the first triangle is the one where `fx >= fz`.
+    fn cell_triangles(&self, column: usize, row: usize) -> [[Point; 3]; 2] {
+        let corner00 = self.vertex(column, row);
+        let corner10 = self.vertex(column + 1, row);
+        let corner01 = self.vertex(column, row + 1);
+        let corner11 = self.vertex(column + 1, row + 1);
+        [
+            [corner00, corner10, corner11],
+            [corner00, corner11, corner01],
+        ]
+    }
+}
+
+impl Intersectable for Heightfield {
+    fn intersect(&self, ray: &Ray) -> Option<f64> {
+        let (enter, exit) = self.bounding_box().span(ray)?;
+        let enter = enter.max(0.);
+        if enter > exit {
+            return None;
+        }
+
+        // Walk through the cells the ray passes over, in order
+        let start = (ray.origin.as_vector() + ray.direction * enter).as_point();
+        let (mut column, mut row, _, _) = self.locate(&start);
+        let (dx, dz) = self.cell_size();
+        let mut next_x = next_boundary(ray.origin.x, ray.direction.x, self.min.x, dx, column);
+        let mut next_z = next_boundary(ray.origin.z, ray.direction.z, self.min.z, dz, row);
+        let step_x = dx / ray.direction.x.abs();
+        let step_z = dz / ray.direction.z.abs();
+
+        loop {
+            let hit = self
+                .cell_triangles(column, row)
+                .iter()
+                .filter_map(|[v0, v1, v2]| intersect_triangle(ray, v0, v1, v2))
+                .min_by(|a, b| a.partial_cmp(b).unwrap());
+            if hit.is_some() {
+                return hit;
+            }
+
+            if next_x.min(next_z) > exit {
+                return None;
+            }
+            if next_x < next_z {
+                if ray.direction.x > 0. {
+                    column += 1;
+                } else {
+                    column = column.checked_sub(1)?;
+                }
+                if column >= self.columns - 1 {
+                    return None;
+                }
+                next_x += step_x;
+            } else {
+                if ray.direction.z > 0. {
+                    row += 1;
+                } else {
+                    row = row.checked_sub(1)?;
+                }
+                if row >= self.rows - 1 {
+                    return None;
+                }
+                next_z += step_z;
+            }
+        }
+    }
+
+    fn surface_normal(&self, hit_point: &Point) -> Vector3 {
+        let (column, row, fx, fz) = self.locate(hit_point);
+        let normal = |column, row| self.normals[row * self.columns + column];
+        let (corner00, corner11) = (normal(column, row), normal(column + 1, row + 1));
+        if fx >= fz {
+            corner00 * (1. - fx) + normal(column + 1, row) * (fx - fz) + corner11 * fz
+        } else {
+            corner00 * (1. - fz) + normal(column, row + 1) * (fz - fx) + corner11 * fx
+        }
+        .normalize()
+    }
+
+    fn texture_coords(&self, hit_point: &Point) -> TextureCoords {
+        // Just below 1, so the far edges do not wrap around to the other side of the
+        // texture
+        let below_one = 1. - f32::EPSILON;
+        TextureCoords {
+            x: (((hit_point.x - self.min.x) / self.size.x) as f32).clamp(0., below_one),
+            y: (((hit_point.z - self.min.z) / self.size.z) as f32).clamp(0., below_one),
+        }
+    }
+}
+
+/// Distance along a ray, moving at `direction` along an axis from `origin`, to the
+/// next boundary between cells of `size` starting at `min`, leaving the cell at
+/// `index`.
+fn next_boundary(origin: f64, direction: f64, min: f64, size: f64, index: usize) -> f64 {
+    if direction > 0. {
+        (min + (index + 1) as f64 * size - origin) / direction
+    } else if direction < 0. {
+        (min + index as f64 * size - origin) / direction
+    } else {
+        f64::INFINITY
+    }
+}
@@ -0,0 +1,318 @@
+//! Loading of IES photometric profiles, which describe how bright a real light fixture
+//! is in every direction.
+//!
+//! Only type C photometry is supported, which is what almost all fixtures use: vertical
+//! angles are measured from straight down (the light's direction), horizontal angles go
+//! around that axis.
+//!
+//! See https://docs.agi32.com/PhotometricToolbox/Content/Open_Tool/iesna_lm-63_format.htm
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum IesError {
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        message: String,
+    },
+}
+
+impl fmt::Display for IesError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IesError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            IesError::Parse { path, message } => write!(f, "{}: {}", path.display(), message),
+        }
+    }
+}
+
+impl std::error::Error for IesError {}
+
+/// Luminous intensity of a fixture in candela, measured on a grid of angles.
+#[derive(Clone, Debug)]
+pub struct IesProfile {
+    /// Degrees from straight down, in increasing order
+    pub vertical_angles: Vec<f64>,
+    /// Degrees around the vertical axis, in increasing order
+    pub horizontal_angles: Vec<f64>,
+    /// `candela[h][v]` is the intensity at `horizontal_angles[h]` and `vertical_angles[v]`
+    pub candela: Vec<Vec<f64>>,
+    peak: f64,
+}
+
+impl IesProfile {
+    pub fn new(
+        vertical_angles: Vec<f64>,
+        horizontal_angles: Vec<f64>,
+        candela: Vec<Vec<f64>>,
+    ) -> IesProfile {
+        let peak = candela.iter().flatten().cloned().fold(0.0, f64::max);
+        IesProfile {
+            vertical_angles,
+            horizontal_angles,
+            candela,
+            peak,
+        }
+    }
+
+    /// The intensity in the given direction, in candela. Angles are in degrees.
+    /// Directions outside the measured vertical range are dark.
+    pub fn intensity(&self, vertical: f64, horizontal: f64) -> f64 {
+        let first = self.vertical_angles[0];
+        let last = self.vertical_angles[self.vertical_angles.len() - 1];
+        if vertical < first || vertical > last {
+            return 0.0;
+        }
+
+        let horizontal = self.fold_horizontal(horizontal);
+        let (h, h_t) = locate(&self.horizontal_angles, horizontal);
+        let (v, v_t) = locate(&self.vertical_angles, vertical);
+        let at = |h: usize| {
+            let row = &self.candela[h];
+            lerp(row[v], row[(v + 1).min(row.len() - 1)], v_t)
+        };
+        lerp(at(h), at((h + 1).min(self.candela.len() - 1)), h_t)
+    }
+
+    /// The intensity in the given direction relative to the brightest direction.
+    pub fn relative_intensity(&self, vertical: f64, horizontal: f64) -> f64 {
+        if self.peak <= 0.0 {
+            return 0.0;
+        }
+        self.intensity(vertical, horizontal) / self.peak
+    }
+
+    /// Map a horizontal angle into the measured range, using the symmetry implied by the
+    /// first and last horizontal angles.
+    fn fold_horizontal(&self, horizontal: f64) -> f64 {
+        let first = self.horizontal_angles[0];
+        let last = self.horizontal_angles[self.horizontal_angles.len() - 1];
+        let mut angle = horizontal.rem_euclid(360.0);
+        if last <= 0.0 {
+            // The same in every direction
+            return 0.0;
+        }
+        if first >= 90.0 {
+            // Measured from 90 to 270 degrees, symmetric about the 90-270 degree plane
+            if angle < 90.0 {
+                angle = 180.0 - angle;
+            } else if angle > 270.0 {
+                angle = 540.0 - angle;
+            }
+            return angle;
+        }
+        if last <= 180.0 && angle > 180.0 {
+            // Symmetric about the 0-180 degree plane
+            angle = 360.0 - angle;
+        }
+        if last <= 90.0 && angle > 90.0 {
+            // Symmetric in each quadrant
+            angle = 180.0 - angle;
+        }
+        angle
+    }
+}
+
+/// Index `i` and weight `t` so that `value` lies a fraction `t` of the way from
+/// `angles[i]` to `angles[i + 1]`. Values outside the range are clamped.
+fn locate(angles: &[f64], value: f64) -> (usize, f64) {
+    if angles.len() < 2 || value <= angles[0] {
+        return (0, 0.0);
+    }
+    for i in 0..angles.len() - 1 {
+        if value <= angles[i + 1] {
+            let span = angles[i + 1] - angles[i];
+            let t = if span > 0.0 {
+                (value - angles[i]) / span
+            } else {
+                0.0
+            };
+            return (i, t);
+        }
+    }
+    (angles.len() - 1, 0.0)
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+/// Load an IES profile (LM-63) from a file.
+pub fn load_ies<P: AsRef<Path>>(path: P) -> Result<IesProfile, IesError> {
+    let path = path.as_ref();
+    let text = fs::read_to_string(path).map_err(|error| IesError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+    parse_ies(&text, path)
+}
+
+/// Parse an IES profile. `path` is only used for error messages.
+pub fn parse_ies(text: &str, path: &Path) -> Result<IesProfile, IesError> {
+    let error = |message: String| IesError::Parse {
+        path: path.to_path_buf(),
+        message,
+    };
+
+    // The header is free text and keywords, up to the TILT line. After that, the file
+    // is a list of numbers, separated by whitespace or commas.
+    let mut lines = text.lines();
+    let tilt = lines
+        .by_ref()
+        .map(|line| line.trim())
+        .find(|line| line.starts_with("TILT="))
+        .ok_or_else(|| error("missing TILT line".to_string()))?;
+    let rest: Vec<&str> = lines.collect();
+    let mut numbers = rest
+        .iter()
+        .flat_map(|line| line.split(|c: char| c.is_whitespace() || c == ','))
+        .filter(|word| !word.is_empty());
+    let mut next = || -> Result<f64, IesError> {
+        let word = numbers
+            .next()
+            .ok_or_else(|| error("unexpected end of file".to_string()))?;
+        word.parse()
+            .map_err(|_| error(format!("invalid number '{}'", word)))
+    };
+
+    if tilt == "TILT=INCLUDE" {
+        // Tilt of the lamp changing its output. We ignore it.
+        let _lamp_to_luminaire_geometry = next()?;
+        let pairs = next()? as usize;
+        for _ in 0..pairs * 2 {
+            next()?;
+        }
+    }
+
+    let _number_of_lamps = next()?;
+    let _lumens_per_lamp = next()?;
+    let multiplier = next()?;
+    let vertical_count = next()? as usize;
+    let horizontal_count = next()? as usize;
+    let photometric_type = next()?;
+    let _units = next()?;
+    let _width = next()?;
+    let _length = next()?;
+    let _height = next()?;
+    let _ballast_factor = next()?;
+    let _future_use = next()?;
+    let _input_watts = next()?;
+
+    if photometric_type != 1.0 {
+        return Err(error(format!(
+            "only type C photometry is supported, found type {}",
+            photometric_type
+        )));
+    }
+    if vertical_count == 0 || horizontal_count == 0 {
+        return Err(error("no angles".to_string()));
+    }
+
+    let vertical_angles = (0..vertical_count)
+        .map(|_| next())
+        .collect::<Result<Vec<f64>, IesError>>()?;
+    let horizontal_angles = (0..horizontal_count)
+        .map(|_| next())
+        .collect::<Result<Vec<f64>, IesError>>()?;
+    // Type C profiles start at 0 degrees, or cover 90 to 270 degrees for fixtures
+    // symmetric about that plane
+    let (first, last) = (
+        horizontal_angles[0],
+        horizontal_angles[horizontal_count - 1],
+    );
+    if first != 0.0 && (first, last) != (90.0, 270.0) {
+        return Err(error(format!(
+            "horizontal angles from {} to {} degrees are not supported",
+            first, last
+        )));
+    }
+    let candela = (0..horizontal_count)
+        .map(|_| {
+            (0..vertical_count)
+                .map(|_| next().map(|value| value * multiplier))
+                .collect::<Result<Vec<f64>, IesError>>()
+        })
+        .collect::<Result<Vec<Vec<f64>>, IesError>>()?;
+
+    Ok(IesProfile::new(vertical_angles, horizontal_angles, candela))
+}
+
+#[cfg(test)]
+const EXAMPLE: &str = "\
+IESNA:LM-63-2002
+[MANUFAC] Example
+[LUMCAT] DOWN-1
+TILT=NONE
+1 1000 2 5 2 1 2 0.1 0.1 0
+1.0 1.0 20
+0 30 60 90 180
+0 90
+100 80 40 0 0
+100, 60, 20, 0, 0
+";
+
+#[test]
+fn test_parse_ies() {
+    let profile = parse_ies(EXAMPLE, Path::new("down.ies")).unwrap();
+    assert_eq!(profile.vertical_angles, vec![0., 30., 60., 90., 180.]);
+    assert_eq!(profile.horizontal_angles, vec![0., 90.]);
+    // Candela values are scaled by the multiplier
+    assert_eq!(profile.candela[1], vec![200., 120., 40., 0., 0.]);
+
+    assert!((profile.intensity(0., 0.) - 200.).abs() < 1e-9);
+    assert!((profile.intensity(15., 0.) - 180.).abs() < 1e-9);
+    assert!((profile.intensity(30., 45.) - 140.).abs() < 1e-9);
+    assert!((profile.relative_intensity(30., 90.) - 0.6).abs() < 1e-9);
+
+    // Quadrant symmetry: 180 degrees looks like 0, and 270 like 90
+    assert!((profile.intensity(30., 180.) - 160.).abs() < 1e-9);
+    assert!((profile.intensity(30., 270.) - 120.).abs() < 1e-9);
+    assert!((profile.intensity(30., -90.) - 120.).abs() < 1e-9);
+}
+
+#[test]
+fn test_parse_ies_reports_errors() {
+    let error = parse_ies("IESNA:LM-63-2002\n1 2 3\n", Path::new("x.ies"))
+        .err()
+        .unwrap();
+    assert_eq!(error.to_string(), "x.ies: missing TILT line");
+
+    let truncated = &EXAMPLE[..EXAMPLE.len() - 10];
+    let error = parse_ies(truncated, Path::new("x.ies")).err().unwrap();
+    assert_eq!(error.to_string(), "x.ies: unexpected end of file");
+
+    let shifted = EXAMPLE.replace("\n0 90\n", "\n45 90\n");
+    let error = parse_ies(&shifted, Path::new("x.ies")).err().unwrap();
+    assert_eq!(
+        error.to_string(),
+        "x.ies: horizontal angles from 45 to 90 degrees are not supported"
+    );
+}
+
+#[test]
+fn test_horizontal_angles_from_90_to_270() {
+    let text = "\
+IESNA:LM-63-2002
+TILT=NONE
+1 1000 1 2 3 1 2 0.1 0.1 0
+1.0 1.0 20
+0 90
+90 180 270
+100 0
+50 0
+10 0
+";
+    let profile = parse_ies(text, Path::new("wall.ies")).unwrap();
+    assert!((profile.intensity(0., 90.) - 100.).abs() < 1e-9);
+    assert!((profile.intensity(0., 225.) - 30.).abs() < 1e-9);
+    // Mirrored across the 90-270 degree plane
+    assert!((profile.intensity(0., 45.) - 75.).abs() < 1e-9);
+    assert!((profile.intensity(0., 0.) - 50.).abs() < 1e-9);
+    assert!((profile.intensity(0., 315.) - 30.).abs() < 1e-9);
+}
@@ -1,26 +1,43 @@
 extern crate image;
 
+pub mod background;
+pub mod bvh;
+pub mod camera;
 pub mod color;
+pub mod csg;
+pub mod hdr;
+pub mod heightfield;
+pub mod ies;
 pub mod math;
-mod rendering;
+pub mod microfacet;
+pub mod obj;
+pub mod path_tracing;
+pub mod rendering;
+pub mod sampling;
 pub mod scene;
+pub mod scene_file;
+pub mod sdf;
+pub mod sky;
 #[cfg(test)]
 pub mod test;
 pub mod test_scene;
 
 use color::Color;
 use color::BLACK;
-use image::{DynamicImage, GenericImage};
-use math::Vector3;
+use image::{DynamicImage, GenericImage, Rgb, Rgb32FImage, Rgba};
+use math::{Point, Vector3};
+use microfacet::Microfacet;
 use rendering::Ray;
-use scene::Intersectable;
+use sampling::Rng;
+use scene::Integrator;
 use scene::Intersection;
 use scene::Light;
+use scene::LightSample;
 use scene::Scene;
+use scene::Specular;
 use scene::SurfaceType;
 use scene::{Coloration, Element, Material, Sphere};
 
-use js_sys;
 use log::{info, Level};
 use serde::{Deserialize, Serialize};
 use wasm_bindgen::prelude::*;
@@ -81,7 +98,7 @@ pub fn get_image_data(width: u32, height: u32, point: JSPoint) -> ImageRawData {
             data.push((i * 255 / w) as u8);
             data.push((j * 255 / w) as u8);
             data.push((i * 255 / w) as u8);
-            data.push(255 as u8);
+            data.push(255_u8);
         }
     }
 
@@ -98,6 +115,8 @@ pub fn get_image_data(width: u32, height: u32, point: JSPoint) -> ImageRawData {
                 blue: 0.2,
             }),
             albedo: 0.18,
+            emission: None,
+            specular: None,
         },
     });
     scene.add_element(sphere);
@@ -105,22 +124,19 @@ pub fn get_image_data(width: u32, height: u32, point: JSPoint) -> ImageRawData {
 }
 
 pub fn render_to_image_data(scene: &Scene) -> ImageRawData {
-    let w = scene.width;
-    let h = scene.height;
-    let mut data = Vec::<u8>::with_capacity((w * h * 4) as usize);
-    for y in 0..scene.height {
-        for x in 0..scene.width {
-            let ray = Ray::create_prime(x, y, scene);
-
-            let mut color = cast_ray(scene, &ray, 0).to_vec();
-            data.append(&mut color)
-        }
-    }
+    let data = render_pixels(scene)
+        .iter()
+        .enumerate()
+        .flat_map(|(i, color)| {
+            let [red, green, blue] = encode_pixel(scene, i, color);
+            [red, green, blue, 255]
+        })
+        .collect();
 
     ImageRawData {
         data,
-        width: w as usize,
-        height: h as usize,
+        width: scene.width as usize,
+        height: scene.height as usize,
     }
 }
 
@@ -128,111 +144,573 @@ pub fn render_to_image_data(scene: &Scene) -> ImageRawData {
 pub fn render(scene: &Scene) -> DynamicImage {
     let mut image = DynamicImage::new_rgb8(scene.width, scene.height);
 
-    for x in 0..scene.width {
-        for y in 0..scene.height {
-            let ray = Ray::create_prime(x, y, scene);
+    for (i, color) in render_pixels(scene).iter().enumerate() {
+        let x = i as u32 % scene.width;
+        let y = i as u32 / scene.width;
+        let [red, green, blue] = encode_pixel(scene, i, color);
+        image.put_pixel(x, y, Rgba([red, green, blue, 255]));
+    }
+    image
+}
 
-            let color = cast_ray(scene, &ray, 0);
-            image.put_pixel(x, y, color.to_rgba());
-        }
+/// Render a scene to linear floating point colors, scaled by the exposure but not tone
+/// mapped, for high dynamic range output.
+pub fn render_hdr(scene: &Scene) -> Rgb32FImage {
+    let scale = 2f32.powf(scene.exposure);
+    let mut image = Rgb32FImage::new(scene.width, scene.height);
+    for (i, color) in render_pixels(scene).iter().enumerate() {
+        let x = i as u32 % scene.width;
+        let y = i as u32 / scene.width;
+        let color = *color * scale;
+        image.put_pixel(x, y, Rgb([color.red, color.green, color.blue]));
     }
     image
 }
 
+/// The 8 bit sRGB value of the `i`th pixel, tone mapped, and dithered if the scene
+/// asks for it.
+fn encode_pixel(scene: &Scene, i: usize, color: &Color) -> [u8; 3] {
+    let color = scene.tone_map.apply(*color, scene.exposure);
+    if !scene.dither {
+        return color.to_srgb8(None);
+    }
+    // Seeded differently from the pixel's rendering, so the noise is unrelated to it
+    let mut rng = Rng::new(!(i as u64));
+    color.to_srgb8(Some(&mut rng))
+}
+
+/// The colors of one row of pixels.
+fn render_row(scene: &Scene, y: u32) -> impl Iterator<Item = Color> + '_ {
+    (0..scene.width).map(move |x| render_pixel(scene, x, y))
+}
+
+/// The color of a pixel. With several samples per pixel, this is the average of the
+/// samples around the pixel center, weighted by the reconstruction filter.
+fn render_pixel(scene: &Scene, x: u32, y: u32) -> Color {
+    let mut rng = Rng::for_pixel(x, y);
+    if scene.samples_per_pixel <= 1 {
+        let ray = Ray::create_prime(x, y, scene);
+        return radiance(scene, &ray, &mut rng);
+    }
+
+    let radius = scene.filter.radius();
+    let mut color = BLACK;
+    let mut total_weight = 0.0;
+    for (u, v) in scene
+        .sample_pattern
+        .offsets(scene.samples_per_pixel, &mut rng)
+    {
+        // Spread the samples over the whole filter, not just the pixel
+        let dx = (u * 2.0 - 1.0) * radius;
+        let dy = (v * 2.0 - 1.0) * radius;
+        let weight = scene.filter.weight(dx, dy);
+        if weight == 0.0 {
+            continue;
+        }
+
+        let ray = Ray::create_prime_through(x as f64 + 0.5 + dx, y as f64 + 0.5 + dy, scene);
+        color = color + radiance(scene, &ray, &mut rng) * weight as f32;
+        total_weight += weight;
+    }
+
+    if total_weight <= 0.0 {
+        let ray = Ray::create_prime(x, y, scene);
+        return radiance(scene, &ray, &mut rng);
+    }
+    color * (1.0 / total_weight as f32)
+}
+
+/// The light coming back along a camera ray, computed by the scene's integrator.
+fn radiance(scene: &Scene, ray: &Ray, rng: &mut Rng) -> Color {
+    match scene.integrator {
+        Integrator::Whitted => cast_ray(scene, ray, 0, rng),
+        Integrator::PathTracing => path_tracing::trace_path(scene, ray, rng),
+    }
+}
+
+/// The colors of all pixels, row by row.
+#[cfg(not(feature = "parallel"))]
+fn render_pixels(scene: &Scene) -> Vec<Color> {
+    (0..scene.height)
+        .flat_map(|y| render_row(scene, y))
+        .collect()
+}
+
+/// The colors of all pixels, row by row. The rows are rendered in parallel.
+#[cfg(feature = "parallel")]
+fn render_pixels(scene: &Scene) -> Vec<Color> {
+    use rayon::prelude::*;
+
+    (0..scene.height)
+        .into_par_iter()
+        .flat_map_iter(|y| render_row(scene, y))
+        .collect()
+}
+
 /// Given a Scene and a ray, define its color.
-pub fn cast_ray(scene: &Scene, ray: &Ray, depth: u32) -> Color {
+pub fn cast_ray(scene: &Scene, ray: &Ray, depth: u32, rng: &mut Rng) -> Color {
     if depth >= scene.max_recursion_depth {
         return BLACK;
     }
 
-    let intersection = scene.trace(&ray);
+    let intersection = scene.trace(ray);
 
-    intersection
-        .map(|i| get_color(scene, ray, &i, depth))
-        .unwrap_or(BLACK)
+    match intersection {
+        Some(i) => get_color(scene, ray, &i, depth, rng),
+        None => scene.background.radiance(&ray.direction),
+    }
 }
 
 /// Given a scene and an intersection point with the given ray, return its color.
-fn get_color(scene: &Scene, ray: &Ray, intersection: &Intersection, depth: u32) -> Color {
+fn get_color(
+    scene: &Scene,
+    ray: &Ray,
+    intersection: &Intersection,
+    depth: u32,
+    rng: &mut Rng,
+) -> Color {
     let hit_point: Vector3 = ray.origin.as_vector() + (ray.direction * intersection.distance);
-    let surface_normal = intersection.object.surface_normal(&hit_point.as_point());
+    let surface_normal = intersection.surface_normal(&hit_point.as_point());
+    // Open surfaces like triangles can be seen from both sides, and a ray inside a
+    // refractive object hits its surface from the back.
+    let facing_normal = if ray.direction.dot(&surface_normal) > 0.0 {
+        surface_normal * -1.
+    } else {
+        surface_normal
+    };
 
-    let mut color = shade_diffuse(scene, intersection.object, hit_point, surface_normal);
+    let reflected = match intersection.object.material().surface_type {
+        SurfaceType::Diffuse => shade_diffuse(
+            scene,
+            intersection,
+            hit_point,
+            facing_normal,
+            ray.direction,
+            rng,
+        ),
+        SurfaceType::Reflective { reflectivity } => {
+            let mut color = shade_diffuse(
+                scene,
+                intersection,
+                hit_point,
+                facing_normal,
+                ray.direction,
+                rng,
+            );
+            let reflection_ray = Ray::create_reflection(
+                facing_normal,
+                &ray.direction,
+                &hit_point.as_point(),
+                scene.shadow_bias,
+            );
+            color = color * (1.0 - reflectivity);
+            color + (cast_ray(scene, &reflection_ray, depth + 1, rng) * reflectivity)
+        }
+        SurfaceType::Refractive {
+            index,
+            transparency,
+        } => {
+            let texture_coords = intersection.texture_coords(&hit_point.as_point());
+            let surface_color = intersection.object.material().color.color(&texture_coords);
+
+            // Fraction of the light that is reflected. If it is 1, we have total internal reflection.
+            let kr = fresnel(ray.direction, surface_normal, index);
+
+            let mut refraction_color = BLACK;
+            if kr < 1.0 {
+                if let Some(transmission_ray) = Ray::create_transmission(
+                    surface_normal,
+                    ray.direction,
+                    &hit_point.as_point(),
+                    scene.shadow_bias,
+                    index,
+                ) {
+                    refraction_color = cast_ray(scene, &transmission_ray, depth + 1, rng);
+                }
+            }
 
-    if let SurfaceType::Reflective { reflectivity } = intersection.object.material().surface_type {
-        let reflection_ray = Ray::create_reflection(
-            surface_normal,
-            &ray.direction,
-            &hit_point.as_point(),
-            scene.shadow_bias,
-        );
-        color = color * (1.0 - reflectivity);
-        color = color + (cast_ray(scene, &reflection_ray, depth + 1) * reflectivity);
+            let reflection_ray = Ray::create_reflection(
+                facing_normal,
+                &ray.direction,
+                &hit_point.as_point(),
+                scene.shadow_bias,
+            );
+            let reflection_color = cast_ray(scene, &reflection_ray, depth + 1, rng);
+
+            // The transparent share of the light is reflected or refracted, tinted by
+            // the surface color as it passes through. The rest is diffuse.
+            let specular = reflection_color * kr + refraction_color * (1.0 - kr) * surface_color;
+            let diffuse = shade_diffuse(
+                scene,
+                intersection,
+                hit_point,
+                facing_normal,
+                ray.direction,
+                rng,
+            );
+            specular * transparency + diffuse * (1.0 - transparency)
+        }
+        SurfaceType::Microfacet { .. } => {
+            let texture_coords = intersection.texture_coords(&hit_point.as_point());
+            let microfacet = intersection
+                .object
+                .material()
+                .microfacet(&texture_coords)
+                .expect("microfacet surface");
+            let to_viewer = ray.direction * -1.;
+            let mut color = microfacet_direct_light(
+                scene,
+                &microfacet,
+                hit_point,
+                facing_normal,
+                to_viewer,
+                rng,
+            );
+
+            // One glossy reflection ray, following the microfacet distribution
+            if let Some((direction, weight)) =
+                microfacet.sample_specular(facing_normal, to_viewer, rng)
+            {
+                let reflection_ray = Ray {
+                    origin: (hit_point + facing_normal * scene.shadow_bias).as_point(),
+                    direction,
+                };
+                color = color + cast_ray(scene, &reflection_ray, depth + 1, rng) * weight;
+            }
+            color
+        }
+    };
+
+    // Glowing surfaces add their own light
+    intersection.object.material().emitted() + reflected
+}
+
+/// Compute the fraction of light that is reflected at a boundary between air and a
+/// material with the given index of refraction. The rest is transmitted.
+///
+/// See https://www.scratchapixel.com/lessons/3d-basic-rendering/introduction-to-shading/reflection-refraction-fresnel
+fn fresnel(incident: Vector3, normal: Vector3, index: f32) -> f32 {
+    let i_dot_n = incident.dot(&normal);
+    let mut eta_i = 1.0;
+    let mut eta_t = index as f64;
+    if i_dot_n > 0.0 {
+        // Inside the surface
+        eta_i = eta_t;
+        eta_t = 1.0;
+    }
+
+    let sin_t = eta_i / eta_t * (1.0 - i_dot_n * i_dot_n).max(0.0).sqrt();
+    if sin_t > 1.0 {
+        // Total internal reflection
+        return 1.0;
     }
 
-    color
+    let cos_t = (1.0 - sin_t * sin_t).max(0.0).sqrt();
+    let cos_i = i_dot_n.abs();
+    let r_s = ((eta_t * cos_i) - (eta_i * cos_t)) / ((eta_t * cos_i) + (eta_i * cos_t));
+    let r_p = ((eta_i * cos_i) - (eta_t * cos_t)) / ((eta_i * cos_i) + (eta_t * cos_t));
+    ((r_s * r_s + r_p * r_p) / 2.0) as f32
 }
 
 pub fn shade_diffuse(
     scene: &Scene,
-    element: &Element,
+    intersection: &Intersection,
     hit_point: Vector3,
     surface_normal: Vector3,
+    incident: Vector3,
+    rng: &mut Rng,
 ) -> Color {
-    let texture_coords = element.texture_coords(&hit_point.as_point());
-    let mut color = Color {
-        red: 0.0,
-        blue: 0.0,
-        green: 0.0,
-    };
+    let brdf = surface_brdf(intersection, hit_point, surface_normal, incident * -1.);
+
+    let mut color = BLACK;
     for light in scene.lights.iter() {
-        let light_color = match light {
-            Light::Directional(l) => {
-                let direction_to_light = l.direction.normalize() * -1.; // minus??
+        color = color + direct_light(scene, light, hit_point, surface_normal, rng, &brdf);
+    }
+    color + light_from_elements(scene, hit_point, surface_normal, rng, &brdf)
+}
 
-                let shadow_ray = Ray {
-                    origin: (hit_point + surface_normal * 1e-6).as_point(),
-                    direction: direction_to_light,
-                };
-                let in_light = scene.trace(&shadow_ray).is_none();
+/// The reflectance of an opaque surface: the fraction of light arriving from a
+/// direction that is reflected towards `to_viewer`, per unit solid angle.
+///
+/// This is Lambert's diffuse reflection, plus Blinn-Phong highlights if the material
+/// has a specular color.
+fn surface_brdf(
+    intersection: &Intersection,
+    hit_point: Vector3,
+    surface_normal: Vector3,
+    to_viewer: Vector3,
+) -> impl Fn(&Vector3) -> Color {
+    let element = intersection.object;
+    let texture_coords = intersection.texture_coords(&hit_point.as_point());
+    let surface_color = element.material().color.color(&texture_coords);
+    let diffuse = surface_color * (element.albedo() / std::f32::consts::PI);
+    let specular = element.material().specular;
+
+    move |to_light: &Vector3| match specular {
+        Some(specular) => diffuse + blinn_phong(&specular, surface_normal, to_viewer, *to_light),
+        None => diffuse,
+    }
+}
 
-                let light_intensity = if in_light { l.intensity } else { 0.0 };
+/// The light from the light sources and glowing elements reflected by a microfacet
+/// surface.
+///
+/// Glowing elements can be hit by glossy reflection rays, so only the diffuse part of
+/// their light is sampled here, or their highlights would be counted twice.
+fn microfacet_direct_light(
+    scene: &Scene,
+    microfacet: &Microfacet,
+    hit_point: Vector3,
+    surface_normal: Vector3,
+    to_viewer: Vector3,
+    rng: &mut Rng,
+) -> Color {
+    let brdf = |to_light: &Vector3| microfacet.brdf(surface_normal, to_viewer, *to_light);
+    let diffuse = |to_light: &Vector3| microfacet.diffuse(surface_normal, to_viewer, *to_light);
 
-                // Lambert's cosine law
-                // https://en.wikipedia.org/wiki/Lambert%27s_cosine_law
-                let light_power =
-                    (surface_normal.dot(&direction_to_light) as f32).max(0.0) * light_intensity;
-                let light_reflected = element.albedo() / std::f32::consts::PI;
+    let mut color = BLACK;
+    for light in scene.lights.iter() {
+        color = color + direct_light(scene, light, hit_point, surface_normal, rng, &brdf);
+    }
+    color + light_from_elements(scene, hit_point, surface_normal, rng, &diffuse)
+}
 
-                l.color * light_power * light_reflected
-            }
-            Light::Spherical(l) => {
-                let direction_to_light = l.position - hit_point.as_point();
+/// Specular highlight from the half vector between the viewer and the light. Normalized
+/// so that the highlight gets smaller, but not darker overall, as shininess increases.
+///
+/// See https://en.wikipedia.org/wiki/Blinn%E2%80%93Phong_reflection_model and
+/// https://www.rorydriscoll.com/2009/01/25/energy-conservation-in-games/
+fn blinn_phong(
+    specular: &Specular,
+    surface_normal: Vector3,
+    to_viewer: Vector3,
+    to_light: Vector3,
+) -> Color {
+    let half = (to_viewer + to_light).normalize();
+    let cos_half = surface_normal.dot(&half).max(0.0) as f32;
+    if cos_half.is_nan() {
+        // Viewer and light in opposite directions
+        return BLACK;
+    }
+    let normalization = (specular.shininess + 8.0) / (8.0 * std::f32::consts::PI);
+    specular.color * (normalization * cos_half.powf(specular.shininess))
+}
 
-                let distance = direction_to_light.length() as f32;
-                let intensity = l.intensity / (4.0 * std::f32::consts::PI * distance * distance);
+/// The light from glowing elements and the background reflected by a surface with the
+/// given BRDF. Each element is sampled at one random point, the background in
+/// `background_samples` directions.
+///
+/// Unlike the light sources, these can also be hit by rays.
+fn light_from_elements(
+    scene: &Scene,
+    hit_point: Vector3,
+    surface_normal: Vector3,
+    rng: &mut Rng,
+    brdf: &dyn Fn(&Vector3) -> Color,
+) -> Color {
+    let mut color = BLACK;
+    for element in scene.elements().iter().filter(|e| e.is_light_source()) {
+        let sample = |p: &Point, r: &mut Rng| element.sample_emission(p, r);
+        color = color + area_light(scene, hit_point, surface_normal, 1, rng, brdf, sample);
+    }
+    color + background_light(scene, hit_point, surface_normal, rng, brdf)
+}
 
-                let shadow_ray = Ray {
-                    origin: (hit_point + surface_normal * 1e-6).as_point(),
-                    direction: direction_to_light.normalize(),
-                };
+/// The light from the background reflected by a surface, averaged over
+/// `background_samples` shadow rays.
+fn background_light(
+    scene: &Scene,
+    hit_point: Vector3,
+    surface_normal: Vector3,
+    rng: &mut Rng,
+    brdf: &dyn Fn(&Vector3) -> Color,
+) -> Color {
+    if scene.background.is_black() || scene.background_samples == 0 {
+        return BLACK;
+    }
 
-                let shadow_intersection = scene.trace(&shadow_ray);
-                let in_light = shadow_intersection.is_none()
-                    || shadow_intersection.unwrap().distance
-                        > l.position.distance(&hit_point.as_point());
+    let mut color = BLACK;
+    for _ in 0..scene.background_samples {
+        let sample = scene.background.sample(surface_normal, rng);
+        let cosine = surface_normal.dot(&sample.direction);
+        if cosine <= 0.0 || sample.pdf <= 0.0 {
+            continue;
+        }
+        let shadow_ray = Ray {
+            origin: (hit_point + surface_normal * scene.shadow_bias).as_point(),
+            direction: sample.direction,
+        };
+        if scene.trace(&shadow_ray).is_some() {
+            continue;
+        }
+        color = color + sample.radiance * brdf(&sample.direction) * (cosine / sample.pdf) as f32;
+    }
+    color * (1.0 / scene.background_samples as f32)
+}
 
-                let light_intensity = if in_light { intensity } else { 0.0 };
-                let light_power =
-                    (surface_normal.dot(&direction_to_light) as f32).max(0.0) * light_intensity;
-                let light_reflected = element.albedo() / std::f32::consts::PI;
+/// The light from `light` reflected at a point with the given surface normal, weighted
+/// by Lambert's cosine law. Black if something is in the way.
+///
+/// `brdf` gives the fraction of the light arriving from a direction that is reflected.
+///
+/// See https://en.wikipedia.org/wiki/Lambert%27s_cosine_law
+fn direct_light(
+    scene: &Scene,
+    light: &Light,
+    hit_point: Vector3,
+    surface_normal: Vector3,
+    rng: &mut Rng,
+    brdf: &dyn Fn(&Vector3) -> Color,
+) -> Color {
+    match light {
+        Light::Directional(l) => {
+            let direction_to_light = l.direction.normalize() * -1.;
+
+            let shadow_ray = Ray {
+                origin: (hit_point + surface_normal * scene.shadow_bias).as_point(),
+                direction: direction_to_light,
+            };
+            if scene.trace(&shadow_ray).is_some() {
+                return BLACK;
+            }
 
-                l.color * light_power * light_reflected
+            let light_power =
+                (surface_normal.dot(&direction_to_light) as f32).max(0.0) * l.intensity;
+            l.color * brdf(&direction_to_light) * light_power
+        }
+        Light::Spherical(l) => point_light(
+            scene,
+            l.position,
+            l.color * l.intensity,
+            hit_point,
+            surface_normal,
+            brdf,
+        ),
+        Light::Spot(l) => {
+            let falloff = l.falloff(&(hit_point.as_point() - l.position));
+            if falloff <= 0.0 {
+                return BLACK;
             }
+            point_light(
+                scene,
+                l.position,
+                l.color * (l.intensity * falloff),
+                hit_point,
+                surface_normal,
+                brdf,
+            )
+        }
+        Light::Rectangle(l) => {
+            let sample = |p: &Point, r: &mut Rng| l.sample(p, r);
+            area_light(
+                scene,
+                hit_point,
+                surface_normal,
+                l.samples,
+                rng,
+                brdf,
+                sample,
+            )
+        }
+        Light::Disk(l) => {
+            let sample = |p: &Point, r: &mut Rng| l.sample(p, r);
+            area_light(
+                scene,
+                hit_point,
+                surface_normal,
+                l.samples,
+                rng,
+                brdf,
+                sample,
+            )
+        }
+        Light::Sphere(l) => {
+            let sample = |p: &Point, r: &mut Rng| l.sample(p, r);
+            area_light(
+                scene,
+                hit_point,
+                surface_normal,
+                l.samples,
+                rng,
+                brdf,
+                sample,
+            )
+        }
+    }
+}
+
+/// The light reflected from a point at `position`, which would emit `power` in total
+/// if it shone equally in all directions.
+fn point_light(
+    scene: &Scene,
+    position: Point,
+    power: Color,
+    hit_point: Vector3,
+    surface_normal: Vector3,
+    brdf: &dyn Fn(&Vector3) -> Color,
+) -> Color {
+    let direction_to_light = (position - hit_point.as_point()).normalize();
+    let distance = position.distance(&hit_point.as_point());
+
+    let shadow_ray = Ray {
+        origin: (hit_point + surface_normal * scene.shadow_bias).as_point(),
+        direction: direction_to_light,
+    };
+    if let Some(shadow_intersection) = scene.trace(&shadow_ray) {
+        if shadow_intersection.distance < distance {
+            return BLACK;
+        }
+    }
+
+    let distance = distance as f32;
+    let cosine = (surface_normal.dot(&direction_to_light) as f32).max(0.0);
+    power
+        * brdf(&direction_to_light)
+        * (cosine / (4.0 * std::f32::consts::PI * distance * distance))
+}
+
+/// The light reflected from a light with an area, averaged over `samples` random
+/// points on the light. Points that are hidden from the hit point make up the penumbra.
+fn area_light(
+    scene: &Scene,
+    hit_point: Vector3,
+    surface_normal: Vector3,
+    samples: u32,
+    rng: &mut Rng,
+    brdf: &dyn Fn(&Vector3) -> Color,
+    sample: impl Fn(&Point, &mut Rng) -> Option<LightSample>,
+) -> Color {
+    let samples = samples.max(1);
+    let shadow_origin = (hit_point + surface_normal * scene.shadow_bias).as_point();
+
+    let mut color = BLACK;
+    for _ in 0..samples {
+        let light_sample = match sample(&hit_point.as_point(), rng) {
+            Some(light_sample) => light_sample,
+            None => continue,
         };
+        let cosine = surface_normal.dot(&light_sample.direction) as f32;
+        if cosine <= 0.0 {
+            continue;
+        }
 
-        color = color + element.material().color.color(&texture_coords) * (light_color);
+        let shadow_ray = Ray {
+            origin: shadow_origin,
+            direction: light_sample.direction,
+        };
+        let in_light = match scene.trace(&shadow_ray) {
+            // Glowing elements are part of the scene, so the shadow ray hits the light
+            // itself. It starts up to the shadow bias closer to the light, and rounding
+            // errors move the hit a little more.
+            Some(shadow_intersection) => {
+                shadow_intersection.distance > light_sample.distance - 2.0 * scene.shadow_bias
+            }
+            None => true,
+        };
+        if in_light {
+            color = color + light_sample.incident * brdf(&light_sample.direction) * cosine;
+        }
     }
 
-    color.clamp()
+    color * (1.0 / samples as f32)
 }
@@ -1,22 +1,137 @@
 extern crate image;
 
+use std::error::Error;
 use std::f64::consts::PI;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
-use image::ImageReader;
+use clap::{Parser, ValueEnum};
+use image::{ImageFormat, ImageReader};
 use ray_tracing::color::*;
+use ray_tracing::hdr::{self, HdrFormat};
 use ray_tracing::math::*;
+use ray_tracing::sampling::{Filter, SamplePattern};
 use ray_tracing::scene::*;
+use ray_tracing::scene_file;
 
-fn test() -> Scene {
-    let mut scene = Scene {
-        width: 2000,
-        height: 2000,
-        fov: 90.,
-        shadow_bias: 1e-6,
-        max_recursion_depth: 100,
-        lights: vec![],
-        elements: vec![],
-    };
+/// Render a scene to an image file.
+#[derive(Parser, Debug)]
+#[command(version)]
+struct Args {
+    /// Scene description file (JSON). If not given, a built-in demo scene is rendered.
+    scene: Option<PathBuf>,
+
+    /// Built-in demo scene to render when no scene file is given
+    #[arg(long, value_enum, default_value_t = Demo::Ring)]
+    demo: Demo,
+
+    /// Output image path
+    #[arg(short, long, default_value = "test.png")]
+    output: PathBuf,
+
+    /// Output image format. Guessed from the output file extension if not given.
+    /// `exr`, `hdr` and `pfm` are written as linear floating point, without tone
+    /// mapping.
+    #[arg(long)]
+    format: Option<String>,
+
+    /// Image width in pixels
+    #[arg(long)]
+    width: Option<u32>,
+
+    /// Image height in pixels
+    #[arg(long)]
+    height: Option<u32>,
+
+    /// Vertical field of view in degrees
+    #[arg(long)]
+    fov: Option<f64>,
+
+    /// Maximum recursion depth for reflection and refraction rays
+    #[arg(long)]
+    depth: Option<u32>,
+
+    /// Number of rays per pixel
+    #[arg(long)]
+    samples: Option<u32>,
+
+    /// How samples are placed within each pixel
+    #[arg(long, value_enum)]
+    pattern: Option<PatternArg>,
+
+    /// Reconstruction filter used to combine the samples of each pixel
+    #[arg(long, value_enum)]
+    filter: Option<FilterArg>,
+
+    /// How the light is computed: Whitted style ray tracing or path tracing
+    #[arg(long, value_enum)]
+    integrator: Option<IntegratorArg>,
+
+    /// Brightness adjustment in stops (EV): +1 doubles the brightness
+    #[arg(long, allow_hyphen_values = true)]
+    exposure: Option<f32>,
+
+    /// How bright light is brought into the displayable range
+    #[arg(long, value_enum)]
+    tone_map: Option<ToneMapArg>,
+
+    /// Add noise before rounding to 8 bit colors, to hide banding in smooth gradients
+    #[arg(long)]
+    dither: bool,
+
+    /// Number of threads to render with, when built with the `parallel` feature.
+    /// Defaults to one per core.
+    #[arg(long)]
+    threads: Option<usize>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Demo {
+    /// A ring of spheres above a checkerboard floor
+    Ring,
+    /// Three spheres in front of a back wall
+    Default,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum PatternArg {
+    Regular,
+    Jittered,
+    Random,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum IntegratorArg {
+    Whitted,
+    Path,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ToneMapArg {
+    Clamp,
+    Reinhard,
+    Aces,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum FilterArg {
+    Box,
+    Tent,
+    Gaussian,
+    Mitchell,
+}
+
+fn load_texture(path: &str) -> Result<Coloration, Box<dyn Error>> {
+    let image = ImageReader::open(path)
+        .map_err(|e| format!("could not open {}: {}", path, e))?
+        .decode()
+        .map_err(|e| format!("could not decode {}: {}", path, e))?;
+    Ok(Coloration::Texture(linear_rgb(&image)))
+}
+
+/// A ring of spheres of increasing size above a checkerboard floor.
+fn ring_scene() -> Result<Scene, Box<dyn Error>> {
+    let mut scene = Scene::new(2000, 2000, 90., 1e-6, 100);
 
     let lights: Vec<Light> = vec![
         Light::Spherical(SphericalLight {
@@ -66,13 +181,6 @@ fn test() -> Scene {
     ];
     let iterations = 15;
     for n in 1..(iterations + 1) {
-        let checkerboard_image = ImageReader::open("checkerboard.png")
-            .ok()
-            .unwrap()
-            .decode()
-            .ok()
-            .unwrap();
-        let texture = Coloration::Texture(checkerboard_image);
         let phi = ((2 * n) as f64) * PI / (iterations as f64);
 
         let r = 1.;
@@ -90,9 +198,10 @@ fn test() -> Scene {
             radius: 0.5 * (n as f64) / (iterations as f64),
             material: Material {
                 surface_type: SurfaceType::Reflective { reflectivity: 0.2 },
-                // color: texture,
                 color: Coloration::Color(current_color),
                 albedo: 0.18,
+                emission: None,
+                specular: None,
             },
         });
         scene.add_element(green_sphere);
@@ -102,13 +211,7 @@ fn test() -> Scene {
         scene.add_light(light);
     }
 
-    let checkerboard_image = ImageReader::open("checkerboard.png")
-        .ok()
-        .unwrap()
-        .decode()
-        .ok()
-        .unwrap();
-    let texture = Coloration::Texture(checkerboard_image);
+    let texture = load_texture("checkerboard.png")?;
 
     let bottom_plane = Element::Plane(Plane {
         normal: Vector3 {
@@ -125,33 +228,21 @@ fn test() -> Scene {
             surface_type: SurfaceType::Reflective { reflectivity: 0.3 },
             color: texture,
             albedo: 0.18,
+            emission: None,
+            specular: None,
         },
     });
 
     scene.add_element(bottom_plane);
 
-    scene
+    Ok(scene)
 }
 
-fn default_scene() -> Scene {
-    let checkerboard_image = ImageReader::open("checkerboard.png")
-        .ok()
-        .unwrap()
-        .decode()
-        .ok()
-        .unwrap();
-
-    let texture = Coloration::Texture(checkerboard_image);
-
-    let mut scene = Scene {
-        width: 800,
-        height: 600,
-        fov: 90.,
-        shadow_bias: 1e-6,
-        max_recursion_depth: 20,
-        lights: vec![],
-        elements: vec![],
-    };
+/// Three spheres in front of a back wall, above a checkerboard floor.
+fn default_scene() -> Result<Scene, Box<dyn Error>> {
+    let texture = load_texture("checkerboard.png")?;
+
+    let mut scene = Scene::new(800, 600, 90., 1e-6, 20);
 
     let mut lights = vec![
         Light::Directional(DirectionalLight {
@@ -210,6 +301,8 @@ fn default_scene() -> Scene {
                 blue: 0.2,
             }),
             albedo: 0.18,
+            emission: None,
+            specular: None,
         },
     });
 
@@ -228,6 +321,8 @@ fn default_scene() -> Scene {
                 blue: 1.,
             }),
             albedo: 0.58,
+            emission: None,
+            specular: None,
         },
     });
 
@@ -246,6 +341,8 @@ fn default_scene() -> Scene {
                 blue: 0.2,
             }),
             albedo: 0.08,
+            emission: None,
+            specular: None,
         },
     });
 
@@ -264,6 +361,8 @@ fn default_scene() -> Scene {
             surface_type: SurfaceType::Reflective { reflectivity: 0.3 },
             color: texture,
             albedo: 0.18,
+            emission: None,
+            specular: None,
         },
     });
 
@@ -286,11 +385,13 @@ fn default_scene() -> Scene {
                 blue: 1.0,
             }),
             albedo: 0.18,
+            emission: None,
+            specular: None,
         },
     });
 
-    while !lights.is_empty() {
-        scene.add_light(lights.pop().unwrap());
+    while let Some(light) = lights.pop() {
+        scene.add_light(light);
     }
 
     scene.add_element(blue_sphere);
@@ -299,13 +400,275 @@ fn default_scene() -> Scene {
     scene.add_element(bottom_plane);
     scene.add_element(back_plane);
 
-    scene
+    Ok(scene)
+}
+
+/// How the rendered image is written.
+enum OutputFormat {
+    /// 8 bit, tone mapped
+    Image(ImageFormat),
+    /// Linear floating point
+    Hdr(HdrFormat),
+}
+
+fn output_format(args: &Args) -> Result<OutputFormat, Box<dyn Error>> {
+    match &args.format {
+        Some(format) => match HdrFormat::from_extension(format) {
+            Some(hdr_format) => Ok(OutputFormat::Hdr(hdr_format)),
+            None => ImageFormat::from_extension(format)
+                .map(OutputFormat::Image)
+                .ok_or_else(|| format!("unknown image format '{}'", format).into()),
+        },
+        None => match HdrFormat::from_path(&args.output) {
+            Some(hdr_format) => Ok(OutputFormat::Hdr(hdr_format)),
+            None => ImageFormat::from_path(&args.output)
+                .map(OutputFormat::Image)
+                .map_err(|_| {
+                    format!(
+                        "cannot tell the image format of {}; use --format",
+                        args.output.display()
+                    )
+                    .into()
+                }),
+        },
+    }
+}
+
+#[cfg(feature = "parallel")]
+fn set_thread_count(threads: usize) -> Result<(), Box<dyn Error>> {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build_global()?;
+    Ok(())
+}
+
+#[cfg(not(feature = "parallel"))]
+fn set_thread_count(threads: usize) -> Result<(), Box<dyn Error>> {
+    if threads > 1 {
+        eprintln!("warning: built without the `parallel` feature, rendering on one thread");
+    }
+    Ok(())
+}
+
+fn run(args: &Args) -> Result<(), Box<dyn Error>> {
+    let format = output_format(args)?;
+
+    let mut scene = match &args.scene {
+        Some(path) => scene_file::load_scene(path)?,
+        None => match args.demo {
+            Demo::Ring => ring_scene()?,
+            Demo::Default => default_scene()?,
+        },
+    };
+    apply_overrides(args, &mut scene)?;
+    if let Some(threads) = args.threads {
+        set_thread_count(threads)?;
+    }
+
+    match format {
+        OutputFormat::Image(format) => {
+            let dyn_image = ray_tracing::render(&scene);
+            save(&dyn_image, &args.output, format)
+        }
+        OutputFormat::Hdr(format) => {
+            let image = ray_tracing::render_hdr(&scene);
+            Ok(hdr::save_hdr(&image, &args.output, format)?)
+        }
+    }
+}
+
+/// Replace the scene's settings with those given on the command line.
+fn apply_overrides(args: &Args, scene: &mut Scene) -> Result<(), Box<dyn Error>> {
+    if let Some(width) = args.width {
+        scene.width = width;
+    }
+    if let Some(height) = args.height {
+        scene.height = height;
+    }
+    if let Some(fov) = args.fov {
+        scene.camera.fov = fov;
+    }
+    if let Some(depth) = args.depth {
+        scene.max_recursion_depth = depth;
+    }
+    if let Some(samples) = args.samples {
+        scene.samples_per_pixel = samples;
+    }
+    if let Some(pattern) = args.pattern {
+        scene.sample_pattern = match pattern {
+            PatternArg::Regular => SamplePattern::Regular,
+            PatternArg::Jittered => SamplePattern::Jittered,
+            PatternArg::Random => SamplePattern::Random,
+        };
+    }
+    if let Some(integrator) = args.integrator {
+        scene.integrator = match integrator {
+            IntegratorArg::Whitted => Integrator::Whitted,
+            IntegratorArg::Path => Integrator::PathTracing,
+        };
+    }
+    if let Some(filter) = args.filter {
+        scene.filter = match filter {
+            FilterArg::Box => Filter::Box,
+            FilterArg::Tent => Filter::Tent,
+            FilterArg::Gaussian => Filter::Gaussian,
+            FilterArg::Mitchell => Filter::Mitchell,
+        };
+    }
+    if let Some(exposure) = args.exposure {
+        scene.exposure = exposure;
+    }
+    if let Some(tone_map) = args.tone_map {
+        scene.tone_map = match tone_map {
+            ToneMapArg::Clamp => ToneMap::Clamp,
+            ToneMapArg::Reinhard => ToneMap::Reinhard,
+            ToneMapArg::Aces => ToneMap::Aces,
+        };
+    }
+    if args.dither {
+        scene.dither = true;
+    }
+    if scene.width == 0 || scene.height == 0 {
+        return Err("width and height must be positive".into());
+    }
+    Ok(())
+}
+
+fn save(
+    image: &image::DynamicImage,
+    path: &Path,
+    format: ImageFormat,
+) -> Result<(), Box<dyn Error>> {
+    image
+        .save_with_format(path, format)
+        .map_err(|e| format!("could not write {}: {}", path.display(), e).into())
+}
+
+pub fn main() -> ExitCode {
+    let args = Args::parse();
+
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+fn parse(args: &[&str]) -> Args {
+    Args::try_parse_from(std::iter::once("ray_tracing").chain(args.iter().copied())).unwrap()
+}
+
+#[test]
+fn test_args() {
+    use clap::CommandFactory;
+    Args::command().debug_assert();
+
+    let args = parse(&[]);
+    assert!(args.scene.is_none());
+    assert!(matches!(args.demo, Demo::Ring));
+    assert_eq!(args.output, PathBuf::from("test.png"));
+    assert!(args.width.is_none() && args.threads.is_none() && !args.dither);
+
+    let args = parse(&[
+        "scenes/sky.json",
+        "-o",
+        "sky.exr",
+        "--samples",
+        "16",
+        "--exposure",
+        "-1.5",
+        "--tone-map",
+        "aces",
+        "--threads",
+        "4",
+    ]);
+    assert_eq!(args.scene, Some(PathBuf::from("scenes/sky.json")));
+    assert_eq!(args.output, PathBuf::from("sky.exr"));
+    assert_eq!(args.samples, Some(16));
+    assert_eq!(args.exposure, Some(-1.5));
+    assert!(matches!(args.tone_map, Some(ToneMapArg::Aces)));
+    assert_eq!(args.threads, Some(4));
+
+    assert!(Args::try_parse_from(["ray_tracing", "--width", "wide"]).is_err());
+    assert!(Args::try_parse_from(["ray_tracing", "--filter", "blurry"]).is_err());
 }
 
-pub fn main() {
-    // let scene = default_scene();
-    let scene = test();
-    let dyn_image = ray_tracing::render(&scene);
+#[test]
+fn test_output_format() {
+    let format = |args: &[&str]| output_format(&parse(args));
+    assert!(matches!(
+        format(&[]),
+        Ok(OutputFormat::Image(ImageFormat::Png))
+    ));
+    assert!(matches!(
+        format(&["-o", "out.jpg"]),
+        Ok(OutputFormat::Image(ImageFormat::Jpeg))
+    ));
+    assert!(matches!(
+        format(&["-o", "out.exr"]),
+        Ok(OutputFormat::Hdr(HdrFormat::OpenExr))
+    ));
+    // An explicit format wins over the extension
+    assert!(matches!(
+        format(&["-o", "out.png", "--format", "pfm"]),
+        Ok(OutputFormat::Hdr(HdrFormat::Pfm))
+    ));
+    assert!(format(&["--format", "doc"]).is_err());
+    assert!(format(&["-o", "out"]).is_err());
+}
+
+#[test]
+fn test_overrides() {
+    let mut scene = Scene::new(800, 600, 90., 1e-13, 10);
+    apply_overrides(&parse(&[]), &mut scene).unwrap();
+    assert_eq!((scene.width, scene.height), (800, 600));
+    assert!(matches!(scene.integrator, Integrator::Whitted));
+    assert!(!scene.dither);
+
+    let args = parse(&[
+        "--width",
+        "64",
+        "--height",
+        "48",
+        "--fov",
+        "45",
+        "--depth",
+        "3",
+        "--samples",
+        "8",
+        "--pattern",
+        "regular",
+        "--filter",
+        "mitchell",
+        "--integrator",
+        "path",
+        "--exposure",
+        "1",
+        "--tone-map",
+        "reinhard",
+        "--dither",
+    ]);
+    apply_overrides(&args, &mut scene).unwrap();
+    assert_eq!((scene.width, scene.height), (64, 48));
+    assert_eq!(scene.camera.fov, 45.);
+    assert_eq!(scene.max_recursion_depth, 3);
+    assert_eq!(scene.samples_per_pixel, 8);
+    assert!(matches!(scene.sample_pattern, SamplePattern::Regular));
+    assert!(matches!(scene.filter, Filter::Mitchell));
+    assert!(matches!(scene.integrator, Integrator::PathTracing));
+    assert_eq!(scene.exposure, 1.);
+    assert!(matches!(scene.tone_map, ToneMap::Reinhard));
+    assert!(scene.dither);
+
+    assert!(apply_overrides(&parse(&["--width", "0"]), &mut scene).is_err());
+}
 
-    dyn_image.save("test.png").unwrap();
+#[test]
+fn test_run_reports_errors() {
+    // Both fail before anything is rendered or written
+    assert!(run(&parse(&["scenes/missing.json"])).is_err());
+    assert!(run(&parse(&["scenes/default.json", "--height", "0"])).is_err());
 }
@@ -55,9 +55,9 @@ impl Vector3 {
         let b = other.y;
         let c = other.z;
         Vector3 {
-            x: self.z * b - self.y * c,
-            y: a * self.z - c * self.x,
-            z: a * self.y - b * self.x,
+            x: self.y * c - self.z * b,
+            y: self.z * a - self.x * c,
+            z: self.x * b - self.y * a,
         }
     }
 }
@@ -158,6 +158,284 @@ impl Mul<f64> for Vector3 {
     }
 }
 
+/// A 4x4 matrix of an affine transformation, in row-major order. Points and vectors
+/// are columns, multiplied from the right.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Matrix4 {
+    pub m: [[f64; 4]; 4],
+}
+
+impl Matrix4 {
+    pub fn identity() -> Matrix4 {
+        let mut m = [[0.; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            row[i] = 1.;
+        }
+        Matrix4 { m }
+    }
+
+    pub fn translation(offset: Vector3) -> Matrix4 {
+        let mut matrix = Matrix4::identity();
+        matrix.m[0][3] = offset.x;
+        matrix.m[1][3] = offset.y;
+        matrix.m[2][3] = offset.z;
+        matrix
+    }
+
+    pub fn scaling(factors: Vector3) -> Matrix4 {
+        let mut matrix = Matrix4::identity();
+        matrix.m[0][0] = factors.x;
+        matrix.m[1][1] = factors.y;
+        matrix.m[2][2] = factors.z;
+        matrix
+    }
+
+    /// Rotation by `angle` radians around the x axis, counterclockwise looking
+    /// towards the origin from positive x.
+    pub fn rotation_x(angle: f64) -> Matrix4 {
+        let (sin, cos) = angle.sin_cos();
+        let mut matrix = Matrix4::identity();
+        matrix.m[1][1] = cos;
+        matrix.m[1][2] = -sin;
+        matrix.m[2][1] = sin;
+        matrix.m[2][2] = cos;
+        matrix
+    }
+
+    pub fn rotation_y(angle: f64) -> Matrix4 {
+        let (sin, cos) = angle.sin_cos();
+        let mut matrix = Matrix4::identity();
+        matrix.m[0][0] = cos;
+        matrix.m[0][2] = sin;
+        matrix.m[2][0] = -sin;
+        matrix.m[2][2] = cos;
+        matrix
+    }
+
+    pub fn rotation_z(angle: f64) -> Matrix4 {
+        let (sin, cos) = angle.sin_cos();
+        let mut matrix = Matrix4::identity();
+        matrix.m[0][0] = cos;
+        matrix.m[0][1] = -sin;
+        matrix.m[1][0] = sin;
+        matrix.m[1][1] = cos;
+        matrix
+    }
+
+    pub fn transpose(&self) -> Matrix4 {
+        let mut m = [[0.; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.m[j][i];
+            }
+        }
+        Matrix4 { m }
+    }
+
+    /// The inverse by Gauss-Jordan elimination, or `None` if the matrix is singular,
+    /// like a scaling by zero.
+    pub fn inverse(&self) -> Option<Matrix4> {
+        let mut a = self.m;
+        let mut inverse = Matrix4::identity().m;
+        for column in 0..4 {
+            // Partial pivoting, for numerical stability
+            let pivot = (column..4)
+                .max_by(|&i, &j| a[i][column].abs().total_cmp(&a[j][column].abs()))
+                .unwrap();
+            if a[pivot][column].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(column, pivot);
+            inverse.swap(column, pivot);
+
+            let scale = 1. / a[column][column];
+            for j in 0..4 {
+                a[column][j] *= scale;
+                inverse[column][j] *= scale;
+            }
+            for row in 0..4 {
+                if row == column {
+                    continue;
+                }
+                let factor = a[row][column];
+                for j in 0..4 {
+                    a[row][j] -= factor * a[column][j];
+                    inverse[row][j] -= factor * inverse[column][j];
+                }
+            }
+        }
+        Some(Matrix4 { m: inverse })
+    }
+
+    pub fn transform_point(&self, point: &Point) -> Point {
+        let m = &self.m;
+        let (x, y, z) = (point.x, point.y, point.z);
+        Point {
+            x: m[0][0] * x + m[0][1] * y + m[0][2] * z + m[0][3],
+            y: m[1][0] * x + m[1][1] * y + m[1][2] * z + m[1][3],
+            z: m[2][0] * x + m[2][1] * y + m[2][2] * z + m[2][3],
+        }
+    }
+
+    /// Transforms a direction, which is not affected by translation.
+    pub fn transform_vector(&self, vector: &Vector3) -> Vector3 {
+        let m = &self.m;
+        let (x, y, z) = (vector.x, vector.y, vector.z);
+        Vector3 {
+            x: m[0][0] * x + m[0][1] * y + m[0][2] * z,
+            y: m[1][0] * x + m[1][1] * y + m[1][2] * z,
+            z: m[2][0] * x + m[2][1] * y + m[2][2] * z,
+        }
+    }
+}
+
+impl Mul for Matrix4 {
+    type Output = Matrix4;
+    /// The transformation applying `other` first, then `self`.
+    fn mul(self, other: Matrix4) -> Self::Output {
+        let mut m = [[0.; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[i][k] * other.m[k][j]).sum();
+            }
+        }
+        Matrix4 { m }
+    }
+}
+
+/// A transformation from an object's own space into the world, with its inverse to
+/// go back.
+#[derive(Copy, Clone, Debug)]
+pub struct Transform {
+    pub matrix: Matrix4,
+    pub inverse: Matrix4,
+}
+
+impl Transform {
+    /// `None` if the matrix can not be inverted.
+    pub fn new(matrix: Matrix4) -> Option<Transform> {
+        matrix
+            .inverse()
+            .map(|inverse| Transform { matrix, inverse })
+    }
+
+    pub fn identity() -> Transform {
+        Transform {
+            matrix: Matrix4::identity(),
+            inverse: Matrix4::identity(),
+        }
+    }
+
+    pub fn point(&self, point: &Point) -> Point {
+        self.matrix.transform_point(point)
+    }
+
+    pub fn vector(&self, vector: &Vector3) -> Vector3 {
+        self.matrix.transform_vector(vector)
+    }
+
+    /// Normals are transformed by the inverse transpose, so that they stay
+    /// perpendicular to a surface that is scaled unevenly. The result is not
+    /// normalized.
+    pub fn normal(&self, normal: &Vector3) -> Vector3 {
+        self.inverse.transpose().transform_vector(normal)
+    }
+
+    pub fn inverse_point(&self, point: &Point) -> Point {
+        self.inverse.transform_point(point)
+    }
+
+    pub fn inverse_vector(&self, vector: &Vector3) -> Vector3 {
+        self.inverse.transform_vector(vector)
+    }
+}
+
+/// The real roots `(t0, t1)` of `a t^2 + b t + c`, with `t0 <= t1`, or `None` if there
+/// are none. A linear equation gives its root twice.
+pub fn solve_quadratic(a: f64, b: f64, c: f64) -> Option<(f64, f64)> {
+    if a.abs() < 1e-12 {
+        if b == 0. {
+            return None;
+        }
+        return Some((-c / b, -c / b));
+    }
+    let discriminant = b * b - 4. * a * c;
+    if discriminant < 0. {
+        return None;
+    }
+    // Avoids cancellation when b is close to the square root
+    let q = -0.5 * (b + discriminant.sqrt().copysign(b));
+    if q == 0. {
+        return Some((0., 0.));
+    }
+    let (t0, t1) = (q / a, c / q);
+    Some((t0.min(t1), t0.max(t1)))
+}
+
+/// The real roots between `min` and `max` of the polynomial with the given
+/// coefficients, lowest power first, in increasing order.
+///
+/// The roots of the derivative split the range into pieces where the polynomial
+/// only rises or only falls, and each piece holds at most one root, which is found
+/// by bisection. Roots where the polynomial only touches zero may be missed.
+pub fn polynomial_roots(coefficients: &[f64], min: f64, max: f64) -> Vec<f64> {
+    let degree = match coefficients.iter().rposition(|&c| c != 0.) {
+        Some(degree) => degree,
+        None => return vec![],
+    };
+    let coefficients = &coefficients[..=degree];
+    if degree == 0 {
+        return vec![];
+    }
+    if degree == 1 {
+        let root = -coefficients[0] / coefficients[1];
+        return if (min..=max).contains(&root) {
+            vec![root]
+        } else {
+            vec![]
+        };
+    }
+
+    let derivative: Vec<f64> = coefficients
+        .iter()
+        .enumerate()
+        .skip(1)
+        .map(|(power, c)| c * power as f64)
+        .collect();
+    let mut bounds = vec![min];
+    bounds.extend(polynomial_roots(&derivative, min, max));
+    bounds.push(max);
+
+    let evaluate = |x: f64| coefficients.iter().rev().fold(0., |sum, c| sum * x + c);
+    let mut roots: Vec<f64> = vec![];
+    for window in bounds.windows(2) {
+        let (mut low, mut high) = (window[0], window[1]);
+        let (low_value, high_value) = (evaluate(low), evaluate(high));
+        if low_value == 0. {
+            if roots.last() != Some(&low) {
+                roots.push(low);
+            }
+            continue;
+        }
+        if low_value.signum() == high_value.signum() {
+            continue;
+        }
+        for _ in 0..100 {
+            let middle = 0.5 * (low + high);
+            if middle <= low || middle >= high {
+                break;
+            }
+            if evaluate(middle).signum() == low_value.signum() {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+        roots.push(0.5 * (low + high));
+    }
+    roots
+}
+
 #[test]
 fn test_add_vector() {
     let v1 = Vector3 {
@@ -195,3 +473,124 @@ fn test_multiply_vector_with_scalar() {
     assert!((v.y - 4.).abs() < 0.0001);
     assert!((v.z - 6.).abs() < 0.0001);
 }
+
+#[test]
+fn test_cross_product() {
+    let x = Vector3 {
+        x: 1.,
+        y: 0.,
+        z: 0.,
+    };
+    let y = Vector3 {
+        x: 0.,
+        y: 1.,
+        z: 0.,
+    };
+
+    let z = x.cross(&y);
+
+    assert!(z.x.abs() < 0.0001);
+    assert!(z.y.abs() < 0.0001);
+    assert!((z.z - 1.).abs() < 0.0001);
+}
+
+#[test]
+fn test_matrix_inverse() {
+    let matrix = Matrix4::translation(Vector3 {
+        x: 1.,
+        y: -2.,
+        z: 3.,
+    }) * Matrix4::rotation_y(0.7)
+        * Matrix4::scaling(Vector3 {
+            x: 2.,
+            y: 0.5,
+            z: 4.,
+        });
+    let product = matrix * matrix.inverse().unwrap();
+    for i in 0..4 {
+        for j in 0..4 {
+            let expected = if i == j { 1. } else { 0. };
+            assert!((product.m[i][j] - expected).abs() < 1e-9);
+        }
+    }
+
+    let flat = Matrix4::scaling(Vector3 {
+        x: 1.,
+        y: 0.,
+        z: 1.,
+    });
+    assert!(flat.inverse().is_none());
+}
+
+#[test]
+fn test_transform_points_vectors_and_normals() {
+    let transform = Transform::new(
+        Matrix4::translation(Vector3 {
+            x: 0.,
+            y: 0.,
+            z: -5.,
+        }) * Matrix4::rotation_z(std::f64::consts::FRAC_PI_2),
+    )
+    .unwrap();
+    // Rotating x onto y, then moving away
+    let p = transform.point(&Point {
+        x: 1.,
+        y: 0.,
+        z: 0.,
+    });
+    assert!(p.x.abs() < 1e-9 && (p.y - 1.).abs() < 1e-9 && (p.z + 5.).abs() < 1e-9);
+    let back = transform.inverse_point(&p);
+    assert!((back.x - 1.).abs() < 1e-9 && back.y.abs() < 1e-9 && back.z.abs() < 1e-9);
+    let v = transform.vector(&Vector3 {
+        x: 1.,
+        y: 0.,
+        z: 0.,
+    });
+    assert!(v.x.abs() < 1e-9 && (v.y - 1.).abs() < 1e-9 && v.z.abs() < 1e-9);
+
+    // A plane at 45 degrees, squashed in y, tilts its normal towards y
+    let squash = Transform::new(Matrix4::scaling(Vector3 {
+        x: 1.,
+        y: 0.5,
+        z: 1.,
+    }))
+    .unwrap();
+    let normal = squash
+        .normal(&Vector3 {
+            x: 1.,
+            y: 1.,
+            z: 0.,
+        })
+        .normalize();
+    let tangent = squash.vector(&Vector3 {
+        x: 1.,
+        y: -1.,
+        z: 0.,
+    });
+    assert!(normal.dot(&tangent).abs() < 1e-9);
+    assert!(normal.y > normal.x);
+}
+
+#[test]
+fn test_solve_quadratic() {
+    let (t0, t1) = solve_quadratic(1., -3., 2.).unwrap();
+    assert!((t0 - 1.).abs() < 1e-12 && (t1 - 2.).abs() < 1e-12);
+    let (t0, t1) = solve_quadratic(-2., 0., 8.).unwrap();
+    assert!((t0 + 2.).abs() < 1e-12 && (t1 - 2.).abs() < 1e-12);
+    assert!(solve_quadratic(1., 0., 1.).is_none());
+    assert_eq!(solve_quadratic(0., 2., -4.), Some((2., 2.)));
+}
+
+#[test]
+fn test_polynomial_roots() {
+    // (x - 1)(x - 2)(x + 3)(x - 4) = x^4 - 4x^3 - 7x^2 + 34x - 24
+    let roots = polynomial_roots(&[-24., 34., -7., -4., 1.], -10., 10.);
+    assert_eq!(roots.len(), 4);
+    for (root, expected) in roots.iter().zip([-3., 1., 2., 4.]) {
+        assert!((root - expected).abs() < 1e-9);
+    }
+    // Only the roots within the range
+    let roots = polynomial_roots(&[-24., 34., -7., -4., 1.], 0., 3.);
+    assert_eq!(roots.len(), 2);
+    assert!(polynomial_roots(&[1., 0., 1.], -10., 10.).is_empty());
+}
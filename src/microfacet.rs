@@ -0,0 +1,273 @@
+//! A physically based, metallic-roughness material.
+//!
+//! The surface is modeled as tiny mirrors (microfacets) whose orientations follow the
+//! GGX distribution, shadowing each other according to Smith's model, and reflecting
+//! according to Schlick's approximation of the Fresnel equations. Light that is not
+//! reflected by the microfacets is scattered diffusely, unless the material is a metal.
+//!
+//! See https://learnopengl.com/PBR/Theory and
+//! https://www.khronos.org/registry/glTF/specs/2.0/glTF-2.0.html#appendix-b-brdf-implementation
+
+use std::f64::consts::PI;
+
+use crate::color::{Color, BLACK};
+use crate::math::Vector3;
+use crate::sampling::{cosine_sample_hemisphere, orthonormal_basis, Rng};
+
+/// Reflectance of non-metals seen head-on, which is about 4% for most of them.
+const DIELECTRIC_REFLECTANCE: f32 = 0.04;
+
+/// The material parameters at one point of a surface.
+#[derive(Clone, Copy, Debug)]
+pub struct Microfacet {
+    pub base_color: Color,
+    pub metallic: f32,
+    pub roughness: f32,
+}
+
+impl Microfacet {
+    /// Square of the perceptual roughness, as is conventional. Kept away from zero to
+    /// avoid dividing by zero for perfectly smooth surfaces.
+    fn alpha(&self) -> f64 {
+        let roughness = self.roughness.clamp(0.0, 1.0) as f64;
+        (roughness * roughness).max(1e-4)
+    }
+
+    /// Reflectance at normal incidence: colored for metals, grey for other materials.
+    fn f0(&self) -> Color {
+        let metallic = self.metallic.clamp(0.0, 1.0);
+        let dielectric = Color {
+            red: DIELECTRIC_REFLECTANCE,
+            green: DIELECTRIC_REFLECTANCE,
+            blue: DIELECTRIC_REFLECTANCE,
+        };
+        dielectric * (1.0 - metallic) + self.base_color * metallic
+    }
+
+    /// Schlick's approximation of the Fresnel reflectance.
+    fn fresnel(&self, cos_theta: f64) -> Color {
+        let f0 = self.f0();
+        let weight = (1.0 - cos_theta).clamp(0.0, 1.0).powi(5) as f32;
+        f0 * (1.0 - weight)
+            + Color {
+                red: weight,
+                green: weight,
+                blue: weight,
+            }
+    }
+
+    /// GGX (Trowbridge-Reitz) density of microfacets facing along `half`.
+    fn distribution(&self, n_dot_h: f64) -> f64 {
+        let alpha_sq = self.alpha() * self.alpha();
+        let d = n_dot_h * n_dot_h * (alpha_sq - 1.0) + 1.0;
+        alpha_sq / (PI * d * d)
+    }
+
+    /// Smith's masking function: the fraction of microfacets visible from a direction.
+    fn masking(&self, n_dot_x: f64) -> f64 {
+        let alpha_sq = self.alpha() * self.alpha();
+        2.0 * n_dot_x / (n_dot_x + (alpha_sq + (1.0 - alpha_sq) * n_dot_x * n_dot_x).sqrt())
+    }
+
+    /// The fraction of light arriving from `to_light` that is reflected towards
+    /// `to_viewer`, per unit solid angle, by the microfacets alone.
+    pub fn specular(&self, normal: Vector3, to_viewer: Vector3, to_light: Vector3) -> Color {
+        let n_dot_l = normal.dot(&to_light);
+        let n_dot_v = normal.dot(&to_viewer);
+        if n_dot_l <= 0.0 || n_dot_v <= 0.0 {
+            return BLACK;
+        }
+
+        let half = (to_viewer + to_light).normalize();
+        let n_dot_h = normal.dot(&half).max(0.0);
+        let v_dot_h = to_viewer.dot(&half).max(0.0);
+        let d = self.distribution(n_dot_h);
+        let g = self.masking(n_dot_l) * self.masking(n_dot_v);
+        self.fresnel(v_dot_h) * (d * g / (4.0 * n_dot_l * n_dot_v)) as f32
+    }
+
+    /// The diffusely scattered part of the BRDF. Metals have none.
+    pub fn diffuse(&self, normal: Vector3, to_viewer: Vector3, to_light: Vector3) -> Color {
+        if normal.dot(&to_light) <= 0.0 || normal.dot(&to_viewer) <= 0.0 {
+            return BLACK;
+        }
+
+        let half = (to_viewer + to_light).normalize();
+        let not_reflected = one_minus(self.fresnel(to_viewer.dot(&half).max(0.0)));
+        let weight = (1.0 - self.metallic.clamp(0.0, 1.0)) / std::f32::consts::PI;
+        self.base_color * not_reflected * weight
+    }
+
+    /// The full BRDF.
+    pub fn brdf(&self, normal: Vector3, to_viewer: Vector3, to_light: Vector3) -> Color {
+        self.specular(normal, to_viewer, to_light) + self.diffuse(normal, to_viewer, to_light)
+    }
+
+    /// A random direction in which the microfacets reflect light towards the viewer,
+    /// together with `specular * cos / pdf`, the weight of the light coming from there.
+    pub fn sample_specular(
+        &self,
+        normal: Vector3,
+        to_viewer: Vector3,
+        rng: &mut Rng,
+    ) -> Option<(Vector3, Color)> {
+        let to_light = self.sample_half_vector_reflection(normal, to_viewer, rng)?;
+        let pdf = self.specular_pdf(normal, to_viewer, to_light);
+        if pdf <= 0.0 {
+            return None;
+        }
+        let cosine = normal.dot(&to_light);
+        let weight = self.specular(normal, to_viewer, to_light) * (cosine / pdf) as f32;
+        Some((to_light, weight))
+    }
+
+    /// A random, cosine weighted direction for the diffuse part, together with
+    /// `diffuse * cos / pdf`.
+    pub fn sample_diffuse(
+        &self,
+        normal: Vector3,
+        to_viewer: Vector3,
+        rng: &mut Rng,
+    ) -> Option<(Vector3, Color)> {
+        let to_light = cosine_sample_hemisphere(normal, rng);
+        let weight = self.diffuse(normal, to_viewer, to_light) * std::f32::consts::PI;
+        Some((to_light, weight))
+    }
+
+    /// Reflect the viewing direction on a microfacet normal drawn from the GGX
+    /// distribution.
+    fn sample_half_vector_reflection(
+        &self,
+        normal: Vector3,
+        to_viewer: Vector3,
+        rng: &mut Rng,
+    ) -> Option<Vector3> {
+        let alpha = self.alpha();
+        let u = rng.next_f64();
+        let phi = 2.0 * PI * rng.next_f64();
+        let tan_sq = alpha * alpha * u / (1.0 - u).max(1e-12);
+        let cos_theta = 1.0 / (1.0 + tan_sq).sqrt();
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+
+        let (tangent, bitangent) = orthonormal_basis(normal);
+        let half = tangent * (sin_theta * phi.cos())
+            + bitangent * (sin_theta * phi.sin())
+            + normal * cos_theta;
+        let v_dot_h = to_viewer.dot(&half);
+        if v_dot_h <= 0.0 {
+            return None;
+        }
+        let to_light = half * (2.0 * v_dot_h) - to_viewer;
+        if normal.dot(&to_light) <= 0.0 {
+            return None;
+        }
+        Some(to_light.normalize())
+    }
+
+    /// Probability density of `sample_half_vector_reflection` picking `to_light`.
+    fn specular_pdf(&self, normal: Vector3, to_viewer: Vector3, to_light: Vector3) -> f64 {
+        let half = (to_viewer + to_light).normalize();
+        let n_dot_h = normal.dot(&half).max(0.0);
+        let v_dot_h = to_viewer.dot(&half);
+        if v_dot_h <= 0.0 {
+            return 0.0;
+        }
+        self.distribution(n_dot_h) * n_dot_h / (4.0 * v_dot_h)
+    }
+
+    /// How often to sample the specular part rather than the diffuse part, following
+    /// how much light each of them reflects.
+    pub fn specular_probability(&self, normal: Vector3, to_viewer: Vector3) -> f64 {
+        let fresnel = self.fresnel(normal.dot(&to_viewer).max(0.0));
+        let specular = average(fresnel);
+        let diffuse =
+            average(self.base_color * one_minus(fresnel)) * (1.0 - self.metallic.clamp(0.0, 1.0));
+        if specular + diffuse <= 0.0 {
+            return 1.0;
+        }
+        (specular / (specular + diffuse)) as f64
+    }
+}
+
+fn one_minus(color: Color) -> Color {
+    Color {
+        red: 1.0 - color.red,
+        green: 1.0 - color.green,
+        blue: 1.0 - color.blue,
+    }
+}
+
+fn average(color: Color) -> f32 {
+    (color.red + color.green + color.blue) / 3.0
+}
+
+#[cfg(test)]
+fn test_vector(x: f64, y: f64, z: f64) -> Vector3 {
+    Vector3 { x, y, z }.normalize()
+}
+
+#[test]
+fn test_microfacet_brdf_is_reciprocal() {
+    let plastic = Microfacet {
+        base_color: Color {
+            red: 0.8,
+            green: 0.2,
+            blue: 0.1,
+        },
+        metallic: 0.0,
+        roughness: 0.4,
+    };
+    let normal = test_vector(0., 1., 0.);
+    let a = test_vector(1., 1., 0.3);
+    let b = test_vector(-0.2, 0.5, -1.);
+
+    let forward = plastic.brdf(normal, a, b);
+    let backward = plastic.brdf(normal, b, a);
+    assert!((forward.red - backward.red).abs() < 1e-6);
+    assert!((forward.blue - backward.blue).abs() < 1e-6);
+
+    // Nothing is reflected below the surface
+    let below = test_vector(0., -1., 0.2);
+    assert_eq!(plastic.brdf(normal, a, below).red, 0.0);
+}
+
+#[test]
+fn test_microfacet_sampling_matches_brdf() {
+    let gold = Microfacet {
+        base_color: Color {
+            red: 1.0,
+            green: 0.78,
+            blue: 0.34,
+        },
+        metallic: 1.0,
+        roughness: 0.5,
+    };
+    let normal = test_vector(0., 0., 1.);
+    let to_viewer = test_vector(0.5, 0., 1.);
+    let mut rng = Rng::new(3);
+
+    // The reflected fraction of uniform incoming light, once with cosine weighted
+    // directions and once with the importance sampled ones
+    let n = 200000;
+    let mut uniform = 0.0;
+    let mut sampled = 0.0;
+    for _ in 0..n {
+        let direction = cosine_sample_hemisphere(normal, &mut rng);
+        uniform += gold.specular(normal, to_viewer, direction).red * std::f32::consts::PI;
+        if let Some((_, weight)) = gold.sample_specular(normal, to_viewer, &mut rng) {
+            sampled += weight.red;
+        }
+    }
+    uniform /= n as f32;
+    sampled /= n as f32;
+
+    assert!(sampled <= 1.0);
+    assert!(
+        (uniform - sampled).abs() < 0.02,
+        "{} != {}",
+        uniform,
+        sampled
+    );
+    // A metal has no diffuse part
+    assert_eq!(gold.diffuse(normal, to_viewer, normal).red, 0.0);
+}
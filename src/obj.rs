@@ -0,0 +1,645 @@
+//! Loading of Wavefront OBJ files and their MTL material libraries.
+//!
+//! See http://paulbourke.net/dataformats/obj/ and http://paulbourke.net/dataformats/mtl/
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use image::ImageReader;
+
+use crate::color::{linear_rgb, Color};
+use crate::math::{Point, Vector3};
+use crate::scene::{
+    Coloration, Element, Emission, Material, Mesh, Scene, Specular, SurfaceType, TextureCoords,
+};
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+    Texture {
+        path: PathBuf,
+        error: image::ImageError,
+    },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            ObjError::Parse {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+            ObjError::Texture { path, error } => {
+                write!(f, "{}: could not load texture: {}", path.display(), error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+/// Material as described in an MTL file, before any textures are loaded.
+#[derive(Debug, Clone)]
+struct MtlMaterial {
+    diffuse: Color,
+    diffuse_map: Option<PathBuf>,
+    specular: Color,
+    emissive: Color,
+    shininess: f32,
+    illumination: u32,
+    optical_density: f32,
+    dissolve: f32,
+}
+
+impl Default for MtlMaterial {
+    fn default() -> Self {
+        MtlMaterial {
+            diffuse: Color {
+                red: 0.8,
+                green: 0.8,
+                blue: 0.8,
+            },
+            diffuse_map: None,
+            specular: Color {
+                red: 0.,
+                green: 0.,
+                blue: 0.,
+            },
+            emissive: Color {
+                red: 0.,
+                green: 0.,
+                blue: 0.,
+            },
+            shininess: 10.0,
+            illumination: 2,
+            optical_density: 1.0,
+            dissolve: 1.0,
+        }
+    }
+}
+
+impl MtlMaterial {
+    fn to_material(&self) -> Result<Material, ObjError> {
+        let color = match &self.diffuse_map {
+            Some(path) => {
+                let image = ImageReader::open(path)
+                    .map_err(|error| ObjError::Io {
+                        path: path.clone(),
+                        error,
+                    })?
+                    .decode()
+                    .map_err(|error| ObjError::Texture {
+                        path: path.clone(),
+                        error,
+                    })?;
+                Coloration::Texture(linear_rgb(&image))
+            }
+            None => Coloration::Color(self.diffuse),
+        };
+
+        let reflectivity = self
+            .specular
+            .red
+            .max(self.specular.green)
+            .max(self.specular.blue);
+        let surface_type = if self.dissolve < 1.0 {
+            SurfaceType::Refractive {
+                index: self.optical_density,
+                transparency: 1.0 - self.dissolve,
+            }
+        } else if self.illumination >= 3 && reflectivity > 0.0 {
+            SurfaceType::Reflective { reflectivity }
+        } else {
+            SurfaceType::Diffuse
+        };
+
+        let emissive = self.emissive;
+        let emission = if emissive.red > 0.0 || emissive.green > 0.0 || emissive.blue > 0.0 {
+            Some(Emission {
+                color: emissive,
+                strength: 1.0,
+            })
+        } else {
+            None
+        };
+
+        // Illumination model 2 is diffuse with highlights
+        let specular = if self.illumination == 2 && reflectivity > 0.0 {
+            Some(Specular {
+                color: self.specular,
+                shininess: self.shininess,
+            })
+        } else {
+            None
+        };
+
+        Ok(Material {
+            color,
+            albedo: 0.18,
+            emission,
+            specular,
+            surface_type,
+        })
+    }
+}
+
+/// Indices of a single face corner into the position, texture coordinate and normal lists.
+type Corner = (usize, Option<usize>, Option<usize>);
+
+/// The faces of the OBJ file sharing one material.
+#[derive(Default)]
+struct FaceGroup {
+    faces: Vec<[Corner; 3]>,
+}
+
+struct Parser<'a> {
+    path: &'a Path,
+    line: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: String) -> ObjError {
+        ObjError::Parse {
+            path: self.path.to_path_buf(),
+            line: self.line,
+            message,
+        }
+    }
+
+    fn floats<'b, I: Iterator<Item = &'b str>>(
+        &self,
+        args: I,
+        count: usize,
+    ) -> Result<Vec<f64>, ObjError> {
+        let values = args
+            .take(count)
+            .map(|arg| {
+                arg.parse::<f64>()
+                    .map_err(|_| self.error(format!("invalid number '{}'", arg)))
+            })
+            .collect::<Result<Vec<f64>, ObjError>>()?;
+
+        if values.len() < count {
+            return Err(self.error(format!(
+                "expected {} numbers, found {}",
+                count,
+                values.len()
+            )));
+        }
+        Ok(values)
+    }
+
+    /// Resolve a one-based (or negative, relative) OBJ index into a list of `len` items.
+    fn index(&self, value: &str, len: usize) -> Result<usize, ObjError> {
+        let index: i64 = value
+            .parse()
+            .map_err(|_| self.error(format!("invalid index '{}'", value)))?;
+
+        let resolved = if index < 0 {
+            len as i64 + index
+        } else {
+            index - 1
+        };
+
+        if resolved < 0 || resolved >= len as i64 {
+            return Err(self.error(format!("index {} out of range", index)));
+        }
+        Ok(resolved as usize)
+    }
+
+    fn corner(
+        &self,
+        vertex: &str,
+        positions: usize,
+        texture_coords: usize,
+        normals: usize,
+    ) -> Result<Corner, ObjError> {
+        let mut parts = vertex.split('/');
+        let position = self.index(parts.next().unwrap_or(""), positions)?;
+        let texture_coord = match parts.next() {
+            Some(t) if !t.is_empty() => Some(self.index(t, texture_coords)?),
+            _ => None,
+        };
+        let normal = match parts.next() {
+            Some(n) if !n.is_empty() => Some(self.index(n, normals)?),
+            _ => None,
+        };
+        Ok((position, texture_coord, normal))
+    }
+}
+
+/// Load an OBJ file and add its faces to the scene, one mesh per material.
+///
+/// Material libraries and textures are resolved relative to the directory of the OBJ file.
+pub fn load_obj<P: AsRef<Path>>(path: P, scene: &mut Scene) -> Result<(), ObjError> {
+    for element in read_obj(path)? {
+        scene.add_element(element);
+    }
+    Ok(())
+}
+
+/// Load an OBJ file into mesh elements, one per material, without adding them to a
+/// scene.
+pub fn read_obj<P: AsRef<Path>>(path: P) -> Result<Vec<Element>, ObjError> {
+    let path = path.as_ref();
+    let file = File::open(path).map_err(|error| ObjError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+
+    parse_obj(BufReader::new(file), path)
+}
+
+/// Parse an OBJ file into mesh elements. `path` is used for error messages and to
+/// resolve material libraries.
+pub fn parse_obj<R: BufRead>(reader: R, path: &Path) -> Result<Vec<Element>, ObjError> {
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut parser = Parser { path, line: 0 };
+
+    let mut positions: Vec<Point> = vec![];
+    let mut texture_coords: Vec<TextureCoords> = vec![];
+    let mut normals: Vec<Vector3> = vec![];
+
+    let mut materials: HashMap<String, MtlMaterial> = HashMap::new();
+    let mut groups: Vec<(Option<String>, FaceGroup)> = vec![(None, FaceGroup::default())];
+    let mut current_group = 0;
+
+    for (number, line) in reader.lines().enumerate() {
+        parser.line = number + 1;
+        let line = line.map_err(|error| ObjError::Io {
+            path: path.to_path_buf(),
+            error,
+        })?;
+        let mut args = line.split_whitespace();
+
+        match args.next() {
+            Some("v") => {
+                let v = parser.floats(args, 3)?;
+                positions.push(Point::from_list([v[0], v[1], v[2]]));
+            }
+            Some("vt") => {
+                let t = parser.floats(args, 2)?;
+                texture_coords.push(TextureCoords {
+                    x: t[0] as f32,
+                    // Image rows go downwards, OBJ texture coordinates go upwards
+                    y: 1.0 - t[1] as f32,
+                });
+            }
+            Some("vn") => {
+                let n = parser.floats(args, 3)?;
+                normals.push(
+                    Vector3 {
+                        x: n[0],
+                        y: n[1],
+                        z: n[2],
+                    }
+                    .normalize(),
+                );
+            }
+            Some("f") => {
+                let corners = args
+                    .map(|vertex| {
+                        parser.corner(vertex, positions.len(), texture_coords.len(), normals.len())
+                    })
+                    .collect::<Result<Vec<Corner>, ObjError>>()?;
+
+                if corners.len() < 3 {
+                    return Err(parser.error("a face needs at least three vertices".to_string()));
+                }
+
+                // Triangulate polygons as a fan around the first vertex
+                for i in 1..(corners.len() - 1) {
+                    groups[current_group]
+                        .1
+                        .faces
+                        .push([corners[0], corners[i], corners[i + 1]]);
+                }
+            }
+            Some("mtllib") => {
+                for library in args {
+                    let library_path = base_dir.join(library);
+                    let file = File::open(&library_path).map_err(|error| ObjError::Io {
+                        path: library_path.clone(),
+                        error,
+                    })?;
+                    materials.extend(parse_mtl(BufReader::new(file), &library_path)?);
+                }
+            }
+            Some("usemtl") => {
+                let name = args.collect::<Vec<&str>>().join(" ");
+                if !materials.contains_key(&name) {
+                    return Err(parser.error(format!("unknown material '{}'", name)));
+                }
+                current_group = match groups.iter().position(|(n, _)| n.as_ref() == Some(&name)) {
+                    Some(index) => index,
+                    None => {
+                        groups.push((Some(name), FaceGroup::default()));
+                        groups.len() - 1
+                    }
+                };
+            }
+            // Comments, object and group names, smoothing groups and other statements
+            // do not affect how we render the mesh.
+            _ => {}
+        }
+    }
+
+    let mut elements = vec![];
+    for (name, group) in groups {
+        if group.faces.is_empty() {
+            continue;
+        }
+
+        let description = name
+            .and_then(|n| materials.get(&n).cloned())
+            .unwrap_or_default();
+        let material = description.to_material()?;
+        elements.push(Element::Mesh(build_mesh(
+            &group,
+            &positions,
+            &texture_coords,
+            &normals,
+            material,
+        )));
+    }
+
+    Ok(elements)
+}
+
+/// Build a mesh where every distinct combination of position, texture coordinate
+/// and normal becomes a vertex.
+fn build_mesh(
+    group: &FaceGroup,
+    positions: &[Point],
+    texture_coords: &[TextureCoords],
+    normals: &[Vector3],
+    material: Material,
+) -> Mesh {
+    let mut vertex_indices: HashMap<Corner, usize> = HashMap::new();
+    let mut corners: Vec<Corner> = vec![];
+    let mut indices = Vec::with_capacity(group.faces.len());
+
+    for face in &group.faces {
+        let mut triangle = [0; 3];
+        for (i, corner) in face.iter().enumerate() {
+            triangle[i] = *vertex_indices.entry(*corner).or_insert_with(|| {
+                corners.push(*corner);
+                corners.len() - 1
+            });
+        }
+        indices.push(triangle);
+    }
+
+    // Normals and texture coordinates are only used if every vertex has one
+    let mesh_normals = if corners.iter().all(|(_, _, n)| n.is_some()) {
+        corners
+            .iter()
+            .map(|(_, _, n)| normals[n.unwrap()])
+            .collect()
+    } else {
+        vec![]
+    };
+    let mesh_texture_coords = if corners.iter().all(|(_, t, _)| t.is_some()) {
+        corners
+            .iter()
+            .map(|(_, t, _)| texture_coords[t.unwrap()])
+            .collect()
+    } else {
+        vec![]
+    };
+
+    Mesh::new(
+        corners.iter().map(|(p, _, _)| positions[*p]).collect(),
+        mesh_normals,
+        mesh_texture_coords,
+        indices,
+        material,
+    )
+}
+
+/// Parse an MTL material library. Texture paths are resolved relative to the library.
+fn parse_mtl<R: BufRead>(reader: R, path: &Path) -> Result<HashMap<String, MtlMaterial>, ObjError> {
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut parser = Parser { path, line: 0 };
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlMaterial)> = None;
+
+    for (number, line) in reader.lines().enumerate() {
+        parser.line = number + 1;
+        let line = line.map_err(|error| ObjError::Io {
+            path: path.to_path_buf(),
+            error,
+        })?;
+        let mut args = line.split_whitespace();
+        let statement = match args.next() {
+            Some(s) => s,
+            None => continue,
+        };
+
+        if statement == "newmtl" {
+            if let Some((name, material)) = current.take() {
+                materials.insert(name, material);
+            }
+            let name = args.collect::<Vec<&str>>().join(" ");
+            current = Some((name, MtlMaterial::default()));
+            continue;
+        }
+
+        if statement.starts_with('#') {
+            continue;
+        }
+
+        let material = match current.as_mut() {
+            Some((_, material)) => material,
+            None => {
+                return Err(parser.error(format!("'{}' before any 'newmtl'", statement)));
+            }
+        };
+
+        match statement {
+            "Kd" => {
+                let c = parser.floats(args, 3)?;
+                material.diffuse = Color {
+                    red: c[0] as f32,
+                    green: c[1] as f32,
+                    blue: c[2] as f32,
+                };
+            }
+            "Ns" => material.shininess = parser.floats(args, 1)?[0] as f32,
+            "Ke" => {
+                let c = parser.floats(args, 3)?;
+                material.emissive = Color {
+                    red: c[0] as f32,
+                    green: c[1] as f32,
+                    blue: c[2] as f32,
+                };
+            }
+            "Ks" => {
+                let c = parser.floats(args, 3)?;
+                material.specular = Color {
+                    red: c[0] as f32,
+                    green: c[1] as f32,
+                    blue: c[2] as f32,
+                };
+            }
+            "map_Kd" => {
+                // Options like -s or -o come before the file name, which is last
+                let file = args
+                    .last()
+                    .ok_or_else(|| parser.error("missing texture file name".to_string()))?;
+                material.diffuse_map = Some(base_dir.join(file));
+            }
+            "illum" => {
+                let value = args.next().unwrap_or("");
+                material.illumination = value
+                    .parse()
+                    .map_err(|_| parser.error(format!("invalid illumination model '{}'", value)))?;
+            }
+            "Ni" => material.optical_density = parser.floats(args, 1)?[0] as f32,
+            "d" => material.dissolve = parser.floats(args, 1)?[0] as f32,
+            "Tr" => material.dissolve = 1.0 - parser.floats(args, 1)?[0] as f32,
+            _ => {}
+        }
+    }
+
+    if let Some((name, material)) = current {
+        materials.insert(name, material);
+    }
+    Ok(materials)
+}
+
+#[test]
+fn test_parse_obj_triangulates_quads() {
+    let obj = "\
+# a unit square
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+f 1/1 2/2 3/3 4/4
+";
+    let elements = parse_obj(obj.as_bytes(), Path::new("square.obj")).unwrap();
+    assert_eq!(elements.len(), 1);
+
+    match &elements[0] {
+        Element::Mesh(mesh) => {
+            assert_eq!(mesh.indices().len(), 2);
+            assert_eq!(mesh.vertices().len(), 4);
+            assert_eq!(mesh.vertex_texture_coords().len(), 4);
+            assert!(mesh.normals().is_empty());
+        }
+        _ => panic!("expected a mesh"),
+    }
+}
+
+#[test]
+fn test_parse_obj_negative_indices() {
+    let obj = "\
+v 0 0 0
+v 1 0 0
+v 0 1 0
+vn 0 0 1
+f -3//-1 -2//-1 -1//-1
+";
+    let elements = parse_obj(obj.as_bytes(), Path::new("triangle.obj")).unwrap();
+
+    match &elements[0] {
+        Element::Mesh(mesh) => {
+            assert_eq!(mesh.indices(), &[[0, 1, 2]]);
+            assert_eq!(mesh.normals().len(), 3);
+        }
+        _ => panic!("expected a mesh"),
+    }
+}
+
+#[test]
+fn test_parse_obj_reports_line_numbers() {
+    let obj = "\
+v 0 0 0
+v 1 0 zero
+";
+    let error = parse_obj(obj.as_bytes(), Path::new("broken.obj"))
+        .err()
+        .unwrap();
+    assert_eq!(error.to_string(), "broken.obj:2: invalid number 'zero'");
+
+    let obj = "\
+v 0 0 0
+v 1 0 0
+f 1 2 3
+";
+    let error = parse_obj(obj.as_bytes(), Path::new("broken.obj"))
+        .err()
+        .unwrap();
+    assert_eq!(error.to_string(), "broken.obj:3: index 3 out of range");
+}
+
+#[test]
+fn test_parse_mtl() {
+    let mtl = "\
+newmtl glass
+Kd 1 1 1
+Ni 1.5
+d 0.1
+
+newmtl mirror
+Kd 0.2 0.2 0.2
+Ks 0.8 0.8 0.8
+illum 3
+
+newmtl lamp
+Kd 1 1 1
+Ke 4 3 2
+
+newmtl plastic
+Kd 1 0 0
+Ks 0.5 0.5 0.5
+Ns 50
+illum 2
+";
+    let materials = parse_mtl(mtl.as_bytes(), Path::new("materials.mtl")).unwrap();
+
+    match materials["glass"].to_material().unwrap().surface_type {
+        SurfaceType::Refractive {
+            index,
+            transparency,
+        } => {
+            assert!((index - 1.5).abs() < 0.0001);
+            assert!((transparency - 0.9).abs() < 0.0001);
+        }
+        _ => panic!("expected glass to be refractive"),
+    }
+
+    match materials["mirror"].to_material().unwrap().surface_type {
+        SurfaceType::Reflective { reflectivity } => assert!((reflectivity - 0.8).abs() < 0.0001),
+        _ => panic!("expected mirror to be reflective"),
+    }
+
+    assert!(materials["mirror"]
+        .to_material()
+        .unwrap()
+        .emission
+        .is_none());
+    let plastic = materials["plastic"].to_material().unwrap();
+    assert!(matches!(plastic.surface_type, SurfaceType::Diffuse));
+    assert!((plastic.specular.unwrap().shininess - 50.).abs() < 0.0001);
+    let lamp = materials["lamp"].to_material().unwrap();
+    assert!((lamp.emitted().green - 3.).abs() < 0.0001);
+}
@@ -0,0 +1,257 @@
+//! A Monte Carlo path tracer.
+//!
+//! Unlike `cast_ray`, which only follows mirror and glass paths, light also bounces
+//! between diffuse surfaces, so objects are lit indirectly and pick up the color of
+//! their surroundings.
+//!
+//! At every diffuse bounce, the light sources, glowing elements and background are
+//! sampled directly (next event estimation) and the path continues in a cosine weighted
+//! random direction. Paths are ended at random by Russian roulette once they carry
+//! little light, and always after `max_recursion_depth` bounces.
+//!
+//! See https://pbr-book.org/3ed-2018/Light_Transport_I_Surface_Reflection/Path_Tracing
+
+use crate::color::{Color, BLACK, WHITE};
+use crate::math::Vector3;
+use crate::rendering::Ray;
+use crate::sampling::{cosine_sample_hemisphere, Rng};
+use crate::scene::{Intersection, Scene, SurfaceType};
+use crate::{direct_light, fresnel, light_from_elements, microfacet_direct_light, surface_brdf};
+
+/// Paths are never ended by Russian roulette before this many bounces.
+const MIN_BOUNCES: u32 = 3;
+
+/// The light coming back along `ray`, estimated by following a single random path.
+pub fn trace_path(scene: &Scene, ray: &Ray, rng: &mut Rng) -> Color {
+    let mut radiance = BLACK;
+    // How much of the light arriving at the current vertex makes it back to the camera
+    let mut throughput = WHITE;
+    let mut ray = *ray;
+    // Light sources are sampled at every diffuse bounce. Hitting one with the next ray
+    // would count its light twice.
+    let mut count_emission = true;
+
+    for bounce in 0..scene.max_recursion_depth {
+        let intersection = match scene.trace(&ray) {
+            Some(intersection) => intersection,
+            None => {
+                // Like glowing elements, the background has been sampled already if
+                // the path comes from a diffuse bounce, unless it takes no samples
+                if count_emission || scene.background_samples == 0 {
+                    radiance = radiance + throughput * scene.background.radiance(&ray.direction);
+                }
+                break;
+            }
+        };
+        let element = intersection.object;
+        let hit_point = ray.origin.as_vector() + (ray.direction * intersection.distance);
+        let surface_normal = intersection.surface_normal(&hit_point.as_point());
+        let facing_normal = if ray.direction.dot(&surface_normal) > 0.0 {
+            surface_normal * -1.
+        } else {
+            surface_normal
+        };
+
+        if count_emission || !element.is_light_source() {
+            radiance = radiance + throughput * element.material().emitted();
+        }
+
+        let next = match element.material().surface_type {
+            SurfaceType::Diffuse => {
+                diffuse_bounce(scene, &intersection, hit_point, facing_normal, &ray, rng)
+            }
+            SurfaceType::Reflective { reflectivity } => {
+                // Pick one of the two layers, so that each is followed with the
+                // probability of its weight
+                if (rng.next_f64() as f32) < reflectivity {
+                    let reflection_ray = Ray::create_reflection(
+                        facing_normal,
+                        &ray.direction,
+                        &hit_point.as_point(),
+                        scene.shadow_bias,
+                    );
+                    Bounce::specular(WHITE, reflection_ray)
+                } else {
+                    diffuse_bounce(scene, &intersection, hit_point, facing_normal, &ray, rng)
+                }
+            }
+            SurfaceType::Refractive {
+                index,
+                transparency,
+            } => {
+                // Like reflective surfaces, the transparent layer is picked with the
+                // probability of its weight, and the rest of the time the diffuse one
+                if (rng.next_f64() as f32) < transparency {
+                    let texture_coords = intersection.texture_coords(&hit_point.as_point());
+                    let surface_color = element.material().color.color(&texture_coords);
+
+                    let kr = fresnel(ray.direction, surface_normal, index);
+                    let transmission_ray = if (rng.next_f64() as f32) < kr {
+                        None
+                    } else {
+                        Ray::create_transmission(
+                            surface_normal,
+                            ray.direction,
+                            &hit_point.as_point(),
+                            scene.shadow_bias,
+                            index,
+                        )
+                    };
+                    match transmission_ray {
+                        // Light passing through is tinted by the surface color
+                        Some(transmission_ray) => Bounce::specular(surface_color, transmission_ray),
+                        None => Bounce::specular(
+                            WHITE,
+                            Ray::create_reflection(
+                                facing_normal,
+                                &ray.direction,
+                                &hit_point.as_point(),
+                                scene.shadow_bias,
+                            ),
+                        ),
+                    }
+                } else {
+                    diffuse_bounce(scene, &intersection, hit_point, facing_normal, &ray, rng)
+                }
+            }
+            SurfaceType::Microfacet { .. } => {
+                microfacet_bounce(scene, &intersection, hit_point, facing_normal, &ray, rng)
+            }
+        };
+
+        radiance = radiance + throughput * next.direct;
+        throughput = throughput * next.weight;
+        count_emission = next.is_specular;
+        ray = match next.ray {
+            Some(ray) => ray,
+            None => break,
+        };
+
+        if bounce + 1 >= MIN_BOUNCES {
+            let survival = throughput
+                .red
+                .max(throughput.green)
+                .max(throughput.blue)
+                .min(0.95);
+            if (rng.next_f64() as f32) >= survival {
+                break;
+            }
+            throughput = throughput * (1.0 / survival);
+        }
+    }
+
+    radiance
+}
+
+/// What happens to a path at a surface.
+struct Bounce {
+    /// Light reaching the surface directly from the light sources, and reflected
+    /// along the path
+    direct: Color,
+    /// Factor for the light that comes along `ray`
+    weight: Color,
+    /// None if the path ends here
+    ray: Option<Ray>,
+    /// Whether the path continues in a mirror, glass or glossy direction, for which
+    /// the glowing elements are not sampled.
+    is_specular: bool,
+}
+
+impl Bounce {
+    fn specular(weight: Color, ray: Ray) -> Bounce {
+        Bounce {
+            direct: BLACK,
+            weight,
+            ray: Some(ray),
+            is_specular: true,
+        }
+    }
+}
+
+/// Sample the direct light at a diffuse surface, and continue the path in a cosine
+/// weighted random direction.
+///
+/// With the sampling density `cos / pi`, the weight of the next ray is `brdf * pi`. For
+/// a purely Lambertian surface, that is `color * albedo`.
+fn diffuse_bounce(
+    scene: &Scene,
+    intersection: &Intersection,
+    hit_point: Vector3,
+    normal: Vector3,
+    ray: &Ray,
+    rng: &mut Rng,
+) -> Bounce {
+    let brdf = surface_brdf(intersection, hit_point, normal, ray.direction * -1.);
+
+    let mut direct = BLACK;
+    for light in scene.lights.iter() {
+        direct = direct + direct_light(scene, light, hit_point, normal, rng, &brdf);
+    }
+    direct = direct + light_from_elements(scene, hit_point, normal, rng, &brdf);
+
+    let direction = cosine_sample_hemisphere(normal, rng);
+    Bounce {
+        direct,
+        weight: brdf(&direction) * std::f32::consts::PI,
+        ray: Some(Ray {
+            origin: (hit_point + normal * scene.shadow_bias).as_point(),
+            direction,
+        }),
+        is_specular: false,
+    }
+}
+
+/// Sample the direct light at a microfacet surface, and continue the path along either
+/// the glossy or the diffuse part of the BRDF, picked at random.
+///
+/// Glowing elements are only sampled for the diffuse part; the glossy part finds them
+/// by hitting them.
+fn microfacet_bounce(
+    scene: &Scene,
+    intersection: &Intersection,
+    hit_point: Vector3,
+    normal: Vector3,
+    ray: &Ray,
+    rng: &mut Rng,
+) -> Bounce {
+    let texture_coords = intersection.texture_coords(&hit_point.as_point());
+    let microfacet = intersection
+        .object
+        .material()
+        .microfacet(&texture_coords)
+        .expect("microfacet surface");
+    let to_viewer = ray.direction * -1.;
+    let direct = microfacet_direct_light(scene, &microfacet, hit_point, normal, to_viewer, rng);
+
+    let specular_probability = microfacet.specular_probability(normal, to_viewer);
+    let is_specular = rng.next_f64() < specular_probability;
+    let (sample, probability) = if is_specular {
+        (
+            microfacet.sample_specular(normal, to_viewer, rng),
+            specular_probability,
+        )
+    } else {
+        (
+            microfacet.sample_diffuse(normal, to_viewer, rng),
+            1.0 - specular_probability,
+        )
+    };
+
+    match sample {
+        Some((direction, weight)) => Bounce {
+            direct,
+            weight: weight * (1.0 / probability) as f32,
+            ray: Some(Ray {
+                origin: (hit_point + normal * scene.shadow_bias).as_point(),
+                direction,
+            }),
+            is_specular,
+        },
+        None => Bounce {
+            direct,
+            weight: BLACK,
+            ray: None,
+            is_specular,
+        },
+    }
+}
@@ -11,11 +11,17 @@ pub struct Ray {
 impl Ray {
     /// Should always create a normalized vector
     pub fn create_prime(x: u32, y: u32, scene: &Scene) -> Ray {
-        let world_point = scene.pixel_to_world_coordinates(x, y, -1.0);
+        Ray::create_prime_through(x as f64 + 0.5, y as f64 + 0.5, scene)
+    }
+
+    /// Create a ray through any point on the image plane, given in pixel units.
+    pub fn create_prime_through(image_x: f64, image_y: f64, scene: &Scene) -> Ray {
+        let world_point = scene.image_to_world_coordinates(image_x, image_y, -1.0);
+        let origin = scene.camera.position;
 
         Ray {
-            origin: Point::zero(),
-            direction: world_point.as_vector().normalize(),
+            origin,
+            direction: (world_point - origin).normalize(),
         }
     }
 
@@ -39,7 +45,9 @@ impl Ray {
         }
     }
 
-    /// Create transmission (what is that??)
+    /// Create a transmission ray, i.e. the ray refracted through the surface.
+    ///
+    /// Returns `None` if there is total internal reflection.
     pub fn create_transmission(
         normal: Vector3,
         incident: Vector3,
@@ -0,0 +1,281 @@
+//! Random numbers, sample placement for anti-aliasing and direction sampling.
+
+use std::f64::consts::PI;
+
+use serde::Deserialize;
+
+use crate::math::Vector3;
+
+/// A small, fast pseudo random number generator (SplitMix64).
+///
+/// We seed one generator per pixel, so that the image does not depend on the order
+/// pixels are rendered in.
+///
+/// See https://prng.di.unimi.it/splitmix64.c
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    /// A generator for the given pixel.
+    pub fn for_pixel(x: u32, y: u32) -> Rng {
+        let mut seeder = Rng::new(((y as u64) << 32) | x as u64);
+        Rng::new(seeder.next_u64())
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// A uniformly distributed number in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+    }
+}
+
+/// How the samples are placed within a pixel.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SamplePattern {
+    /// The centers of a square grid. The number of samples is rounded down to a square.
+    Regular,
+    /// One random point in each cell of a square grid (stratified sampling). Samples
+    /// that do not fill a square grid are placed uniformly at random.
+    Jittered,
+    /// Uniformly random points.
+    Random,
+}
+
+impl SamplePattern {
+    /// Sample positions in the unit square.
+    pub fn offsets(&self, samples: u32, rng: &mut Rng) -> Vec<(f64, f64)> {
+        let grid_size = (samples as f64).sqrt().floor().max(1.0) as u32;
+        let cell = 1.0 / grid_size as f64;
+        let grid = (0..grid_size).flat_map(|i| (0..grid_size).map(move |j| (i, j)));
+
+        match self {
+            SamplePattern::Regular => grid
+                .map(|(i, j)| ((i as f64 + 0.5) * cell, (j as f64 + 0.5) * cell))
+                .collect(),
+            SamplePattern::Jittered => {
+                let mut offsets: Vec<(f64, f64)> = grid
+                    .map(|(i, j)| {
+                        (
+                            (i as f64 + rng.next_f64()) * cell,
+                            (j as f64 + rng.next_f64()) * cell,
+                        )
+                    })
+                    .collect();
+                while offsets.len() < samples as usize {
+                    offsets.push((rng.next_f64(), rng.next_f64()));
+                }
+                offsets
+            }
+            SamplePattern::Random => (0..samples.max(1))
+                .map(|_| (rng.next_f64(), rng.next_f64()))
+                .collect(),
+        }
+    }
+}
+
+/// Reconstruction filter, used to weigh the samples around a pixel center.
+///
+/// See https://pbr-book.org/3ed-2018/Sampling_and_Reconstruction/Image_Reconstruction
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Filter {
+    /// Every sample within the pixel counts the same
+    Box,
+    /// Weight falls off linearly, reaching zero one pixel from the center
+    Tent,
+    /// Gaussian with standard deviation of half a pixel, cut off at 1.5 pixels
+    Gaussian,
+    /// Mitchell-Netravali with B = C = 1/3, reaching zero two pixels from the center
+    Mitchell,
+}
+
+impl Filter {
+    /// How far from the pixel center, in pixels, the filter is non-zero.
+    pub fn radius(&self) -> f64 {
+        match self {
+            Filter::Box => 0.5,
+            Filter::Tent => 1.0,
+            Filter::Gaussian => 1.5,
+            Filter::Mitchell => 2.0,
+        }
+    }
+
+    /// Weight of a sample at the given offset from the pixel center.
+    pub fn weight(&self, dx: f64, dy: f64) -> f64 {
+        self.weight_1d(dx) * self.weight_1d(dy)
+    }
+
+    fn weight_1d(&self, d: f64) -> f64 {
+        let d = d.abs();
+        let radius = self.radius();
+        if d > radius {
+            return 0.0;
+        }
+
+        match self {
+            Filter::Box => 1.0,
+            Filter::Tent => radius - d,
+            Filter::Gaussian => {
+                let sigma = 0.5;
+                let gaussian = |x: f64| (-x * x / (2.0 * sigma * sigma)).exp();
+                // Subtract the value at the radius so the filter goes smoothly to zero
+                (gaussian(d) - gaussian(radius)) / (sigma * (2.0 * PI).sqrt())
+            }
+            Filter::Mitchell => {
+                let (b, c) = (1.0 / 3.0, 1.0 / 3.0);
+                // The standard formula is defined on [-2, 2]
+                let x = d * 2.0 / radius;
+                if x < 1.0 {
+                    ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+                        + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                        + (6.0 - 2.0 * b))
+                        / 6.0
+                } else {
+                    ((-b - 6.0 * c) * x * x * x
+                        + (6.0 * b + 30.0 * c) * x * x
+                        + (-12.0 * b - 48.0 * c) * x
+                        + (8.0 * b + 24.0 * c))
+                        / 6.0
+                }
+            }
+        }
+    }
+}
+
+/// A random direction in the hemisphere around `normal`, with probability density
+/// proportional to the cosine of the angle to the normal (cos / pi).
+///
+/// Uses Malley's method: points uniform on the unit disk, projected up onto the hemisphere.
+pub fn cosine_sample_hemisphere(normal: Vector3, rng: &mut Rng) -> Vector3 {
+    let (x, y) = sample_disk(rng);
+    let z = (1.0 - x * x - y * y).max(0.0).sqrt();
+
+    let (tangent, bitangent) = orthonormal_basis(normal);
+    (tangent * x + bitangent * y + normal * z).normalize()
+}
+
+/// A uniformly distributed point on the unit disk.
+pub fn sample_disk(rng: &mut Rng) -> (f64, f64) {
+    let r = rng.next_f64().sqrt();
+    let phi = 2.0 * PI * rng.next_f64();
+    (r * phi.cos(), r * phi.sin())
+}
+
+/// A uniformly distributed direction within the cone around `axis` whose half angle
+/// has cosine `cos_max`.
+pub fn uniform_sample_cone(axis: Vector3, cos_max: f64, rng: &mut Rng) -> Vector3 {
+    let cos_theta = 1.0 - rng.next_f64() * (1.0 - cos_max);
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * rng.next_f64();
+
+    let (tangent, bitangent) = orthonormal_basis(axis);
+    (tangent * (sin_theta * phi.cos()) + bitangent * (sin_theta * phi.sin()) + axis * cos_theta)
+        .normalize()
+}
+
+/// Two unit vectors that are perpendicular to each other and to `normal`.
+pub fn orthonormal_basis(normal: Vector3) -> (Vector3, Vector3) {
+    let helper = if normal.x.abs() > 0.9 {
+        Vector3 {
+            x: 0.,
+            y: 1.,
+            z: 0.,
+        }
+    } else {
+        Vector3 {
+            x: 1.,
+            y: 0.,
+            z: 0.,
+        }
+    };
+    let tangent = helper.cross(&normal).normalize();
+    let bitangent = normal.cross(&tangent);
+    (tangent, bitangent)
+}
+
+#[test]
+fn test_rng_is_deterministic_and_in_range() {
+    let mut a = Rng::for_pixel(3, 7);
+    let mut b = Rng::for_pixel(3, 7);
+    for _ in 0..1000 {
+        let x = a.next_f64();
+        assert_eq!(x.to_bits(), b.next_f64().to_bits());
+        assert!((0.0..1.0).contains(&x));
+    }
+
+    assert_ne!(
+        Rng::for_pixel(3, 7).next_u64(),
+        Rng::for_pixel(7, 3).next_u64()
+    );
+}
+
+#[test]
+fn test_jittered_samples_are_stratified() {
+    let mut rng = Rng::new(1);
+    let offsets = SamplePattern::Jittered.offsets(16, &mut rng);
+    assert_eq!(offsets.len(), 16);
+
+    // Each cell of the 4x4 grid gets exactly one sample
+    let mut cells = offsets
+        .iter()
+        .map(|(u, v)| ((u * 4.0) as u32, (v * 4.0) as u32))
+        .collect::<Vec<(u32, u32)>>();
+    cells.sort();
+    cells.dedup();
+    assert_eq!(cells.len(), 16);
+
+    assert_eq!(SamplePattern::Jittered.offsets(5, &mut rng).len(), 5);
+    assert_eq!(SamplePattern::Regular.offsets(5, &mut rng).len(), 4);
+}
+
+#[test]
+fn test_filters_peak_at_center_and_vanish_outside_radius() {
+    for filter in [
+        Filter::Box,
+        Filter::Tent,
+        Filter::Gaussian,
+        Filter::Mitchell,
+    ] {
+        let center = filter.weight(0.0, 0.0);
+        assert!(center > 0.0);
+        assert!(filter.weight(0.25, 0.1) <= center);
+        assert_eq!(filter.weight(filter.radius() + 0.01, 0.0), 0.0);
+    }
+}
+
+#[test]
+fn test_cosine_samples_are_in_the_hemisphere() {
+    let normal = Vector3 {
+        x: 1.,
+        y: 2.,
+        z: -0.5,
+    }
+    .normalize();
+    let mut rng = Rng::new(2);
+
+    let n = 10000;
+    let mut mean_cosine = 0.0;
+    for _ in 0..n {
+        let direction = cosine_sample_hemisphere(normal, &mut rng);
+        assert!((direction.length() - 1.0).abs() < 1e-9);
+        assert!(direction.dot(&normal) >= 0.0);
+        mean_cosine += direction.dot(&normal) / n as f64;
+    }
+
+    // The expected cosine under a cos / pi density is 2/3
+    assert!((mean_cosine - 2.0 / 3.0).abs() < 0.01);
+}
@@ -1,11 +1,27 @@
-use image::DynamicImage;
+use std::f64::consts::PI;
+use std::sync::{Arc, OnceLock};
+
 use image::GenericImageView;
+use image::{DynamicImage, Rgb32FImage};
+use serde::Deserialize;
 
-use crate::color::Color;
-use crate::math::Point;
+use crate::background::Background;
+use crate::bvh::{BoundingBox, Bvh};
+use crate::camera::Camera;
+use crate::color::{Color, ToneMap, BLACK};
+use crate::csg::{clip, first_boundary, Csg, Interval};
+use crate::heightfield::Heightfield;
+use crate::ies::IesProfile;
 use crate::math::Vector3;
+use crate::math::{polynomial_roots, solve_quadratic, Point, Transform};
+use crate::microfacet::Microfacet;
 use crate::rendering::Ray;
+use crate::sampling::{
+    orthonormal_basis, sample_disk, uniform_sample_cone, Filter, Rng, SamplePattern,
+};
+use crate::sdf::DistanceField;
 
+#[derive(Clone, Copy, Debug)]
 pub struct TextureCoords {
     pub x: f32,
     pub y: f32,
@@ -16,9 +32,11 @@ pub struct TextureCoords {
 
 // }
 
+/// A color, either constant or read from a texture. Texture pixels are linear, see
+/// `linear_rgb`.
 pub enum Coloration {
     Color(Color),
-    Texture(DynamicImage),
+    Texture(Rgb32FImage),
 }
 
 fn wrap(val: f32, bound: u32) -> u32 {
@@ -40,7 +58,33 @@ impl Coloration {
                 let tex_x = wrap(texture_coords.x, texture.width());
                 let tex_y = wrap(texture_coords.y, texture.height());
 
-                Color::from_rgba(&texture.get_pixel(tex_x, tex_y))
+                let pixel = texture.get_pixel(tex_x, tex_y);
+                Color {
+                    red: pixel[0],
+                    green: pixel[1],
+                    blue: pixel[2],
+                }
+            }
+        }
+    }
+}
+
+/// A material parameter between 0 and 1, either constant or read from the red channel
+/// of a grayscale texture.
+pub enum Scalar {
+    Value(f32),
+    Texture(DynamicImage),
+}
+
+impl Scalar {
+    pub fn value(&self, texture_coords: &TextureCoords) -> f32 {
+        match &self {
+            Scalar::Value(v) => *v,
+            Scalar::Texture(texture) => {
+                let tex_x = wrap(texture_coords.x, texture.width());
+                let tex_y = wrap(texture_coords.y, texture.height());
+
+                texture.get_pixel(tex_x, tex_y)[0] as f32 / 255.0
             }
         }
     }
@@ -48,14 +92,71 @@ impl Coloration {
 
 pub enum SurfaceType {
     Diffuse,
-    Reflective { reflectivity: f32 },
-    Refractive { index: f32, transparency: f32 },
+    Reflective {
+        reflectivity: f32,
+    },
+    Refractive {
+        index: f32,
+        transparency: f32,
+    },
+    /// A physically based metallic-roughness surface. The material color is the base
+    /// color; the albedo is not used.
+    Microfacet {
+        metallic: Scalar,
+        roughness: Scalar,
+    },
+}
+
+/// Light given off by a glowing surface.
+#[derive(Clone, Copy, Debug)]
+pub struct Emission {
+    pub color: Color,
+    pub strength: f32,
+}
+
+/// Highlights on a glossy surface.
+#[derive(Clone, Copy, Debug)]
+pub struct Specular {
+    pub color: Color,
+    /// The higher, the smaller and sharper the highlights
+    pub shininess: f32,
 }
 
 pub struct Material {
     pub color: Coloration,
     pub albedo: f32,
     pub surface_type: SurfaceType,
+    /// Makes the element a light source. Spheres, triangles and meshes light up the
+    /// rest of the scene. A glowing plane is infinite, so it can not be aimed at, and is
+    /// only seen directly and in reflections.
+    pub emission: Option<Emission>,
+    /// Adds Blinn-Phong highlights to diffuse surfaces
+    pub specular: Option<Specular>,
+}
+
+impl Material {
+    /// The radiance given off by the surface.
+    pub fn emitted(&self) -> Color {
+        match self.emission {
+            Some(emission) => emission.color * emission.strength,
+            None => BLACK,
+        }
+    }
+
+    /// The microfacet parameters at a point, if the surface is a microfacet one.
+    pub fn microfacet(&self, texture_coords: &TextureCoords) -> Option<Microfacet> {
+        match &self.surface_type {
+            SurfaceType::Microfacet {
+                metallic,
+                roughness,
+            } => Some(Microfacet {
+                base_color: self.color.color(texture_coords),
+                metallic: metallic.value(texture_coords),
+                roughness: roughness.value(texture_coords),
+            }),
+            _ => None,
+        }
+    }
 }
 
 pub struct Sphere {
@@ -71,9 +172,110 @@ pub struct Plane {
     pub material: Material,
 }
 
+/// A single triangle. The front face is the one where the vertices appear in
+/// counter-clockwise order.
+pub struct Triangle {
+    pub v0: Point,
+    pub v1: Point,
+    pub v2: Point,
+    pub material: Material,
+}
+
+/// A triangle mesh with shared vertex buffers.
+///
+/// Each entry in `indices` is a face, given as three indices into `vertices`. If
+/// `normals` is non-empty it holds one normal per vertex, which are interpolated
+/// across each face for smooth shading. Likewise `texture_coords` holds one UV
+/// coordinate per vertex.
+///
+/// The geometry can only be read after `Mesh::new`, since the BVH and face areas are
+/// built from it.
+pub struct Mesh {
+    vertices: Vec<Point>,
+    normals: Vec<Vector3>,
+    texture_coords: Vec<TextureCoords>,
+    indices: Vec<[usize; 3]>,
+    pub material: Material,
+    bvh: Bvh,
+    /// Total area of the faces up to and including each face, to pick faces by area
+    cumulative_areas: Vec<f64>,
+}
+
+/// A box with its faces parallel to the axes, between the corners `min` and `max`.
+pub struct Cuboid {
+    pub min: Point,
+    pub max: Point,
+    pub material: Material,
+}
+
+/// A solid cylinder standing on a disk around `base`, reaching `height` along `axis`,
+/// closed by flat caps at both ends.
+pub struct Cylinder {
+    pub base: Point,
+    /// Unit vector from the base towards the top
+    pub axis: Vector3,
+    pub radius: f64,
+    pub height: f64,
+    pub material: Material,
+}
+
+/// A solid cone with a flat disk of `radius` around `base`, narrowing to its tip
+/// `height` along `axis`.
+pub struct Cone {
+    pub base: Point,
+    /// Unit vector from the base towards the tip
+    pub axis: Vector3,
+    pub radius: f64,
+    pub height: f64,
+    pub material: Material,
+}
+
+/// A flat, round disk, seen from both sides.
+pub struct Disk {
+    pub center: Point,
+    /// Unit vector towards the front side
+    pub normal: Vector3,
+    pub radius: f64,
+    pub material: Material,
+}
+
+/// A ring shaped like a doughnut: a tube of `minor_radius` swept around `axis` in a
+/// circle of `major_radius`.
+pub struct Torus {
+    pub center: Point,
+    /// Unit vector through the hole
+    pub axis: Vector3,
+    pub major_radius: f64,
+    pub minor_radius: f64,
+    pub material: Material,
+}
+
+/// A shared element placed in the scene with a transform, so that one mesh can be
+/// drawn many times while it is stored only once.
+///
+/// Rays are moved into the object's own space to be intersected, and normals are
+/// brought back out. Instances still glow when rays hit them, but are not sampled as
+/// lights.
+pub struct Instance {
+    pub object: Arc<Element>,
+    /// From the object's space into the world
+    pub transform: Transform,
+}
+
 pub enum Element {
     Sphere(Sphere),
     Plane(Plane),
+    Triangle(Triangle),
+    Mesh(Mesh),
+    Cuboid(Cuboid),
+    Cylinder(Cylinder),
+    Cone(Cone),
+    Disk(Disk),
+    Torus(Torus),
+    Instance(Instance),
+    Csg(Csg),
+    DistanceField(DistanceField),
+    Heightfield(Heightfield),
 }
 
 impl Element {
@@ -81,6 +283,17 @@ impl Element {
         match *self {
             Element::Sphere(ref s) => &s.material,
             Element::Plane(ref p) => &p.material,
+            Element::Triangle(ref t) => &t.material,
+            Element::Mesh(ref m) => &m.material,
+            Element::Cuboid(ref b) => &b.material,
+            Element::Cylinder(ref c) => &c.material,
+            Element::Cone(ref c) => &c.material,
+            Element::Disk(ref d) => &d.material,
+            Element::Torus(ref t) => &t.material,
+            Element::Instance(ref i) => i.object.material(),
+            Element::Csg(ref c) => c.material(),
+            Element::DistanceField(ref d) => &d.material,
+            Element::Heightfield(ref h) => &h.material,
         }
     }
 
@@ -88,6 +301,76 @@ impl Element {
         match *self {
             Element::Sphere(ref s) => s.surface_normal(point),
             Element::Plane(ref p) => p.surface_normal(point),
+            Element::Triangle(ref t) => t.surface_normal(point),
+            Element::Mesh(ref m) => m.surface_normal(point),
+            Element::Cuboid(ref b) => b.surface_normal(point),
+            Element::Cylinder(ref c) => c.surface_normal(point),
+            Element::Cone(ref c) => c.surface_normal(point),
+            Element::Disk(ref d) => d.surface_normal(point),
+            Element::Torus(ref t) => t.surface_normal(point),
+            Element::Instance(ref i) => i.surface_normal(point),
+            Element::Csg(ref c) => c.surface_normal(point),
+            Element::DistanceField(ref d) => d.surface_normal(point),
+            Element::Heightfield(ref h) => h.surface_normal(point),
+        }
+    }
+
+    /// Like `intersect`, but also gives the face that was hit, for meshes.
+    pub fn intersect_face(&self, ray: &Ray) -> Option<(f64, Option<FaceHit>)> {
+        match *self {
+            Element::Mesh(ref m) => m.intersect_face(ray).map(|(d, face)| (d, Some(face))),
+            Element::Instance(ref i) => i.intersect_face(ray),
+            _ => self.intersect(ray).map(|d| (d, None)),
+        }
+    }
+
+    /// The normal where a ray hit the element, using the face from `intersect_face`.
+    pub fn hit_normal(&self, point: &Point, face: Option<FaceHit>) -> Vector3 {
+        match (self, face) {
+            (Element::Mesh(m), Some(face)) => m.face_hit_normal(&face),
+            (Element::Instance(i), Some(face)) => i.hit_normal(point, face),
+            _ => self.normal(point),
+        }
+    }
+
+    /// The texture coordinates where a ray hit the element, using the face from
+    /// `intersect_face`.
+    pub fn hit_texture_coords(&self, point: &Point, face: Option<FaceHit>) -> TextureCoords {
+        match (self, face) {
+            (Element::Mesh(m), Some(face)) => m.face_hit_texture_coords(&face),
+            (Element::Instance(i), Some(face)) => i
+                .object
+                .hit_texture_coords(&i.transform.inverse_point(point), Some(face)),
+            _ => self.texture_coords(point),
+        }
+    }
+
+    /// Bounding box of the element, or `None` if it is unbounded.
+    pub fn bounding_box(&self) -> Option<BoundingBox> {
+        match *self {
+            Element::Sphere(ref s) => {
+                let r = Vector3 {
+                    x: s.radius,
+                    y: s.radius,
+                    z: s.radius,
+                };
+                Some(BoundingBox {
+                    min: (s.center.as_vector() - r).as_point(),
+                    max: (s.center.as_vector() + r).as_point(),
+                })
+            }
+            Element::Plane(_) => None,
+            Element::Triangle(ref t) => Some(BoundingBox::from_points(&[t.v0, t.v1, t.v2])),
+            Element::Mesh(ref m) => Some(m.bounding_box()),
+            Element::Cuboid(ref b) => Some(b.bounding_box()),
+            Element::Cylinder(ref c) => Some(c.bounding_box()),
+            Element::Cone(ref c) => Some(c.bounding_box()),
+            Element::Disk(ref d) => Some(d.bounding_box()),
+            Element::Torus(ref t) => Some(t.bounding_box()),
+            Element::Instance(ref i) => i.bounding_box(),
+            Element::Csg(ref c) => c.bounding_box(),
+            Element::DistanceField(ref d) => d.bounding_box(),
+            Element::Heightfield(ref h) => Some(h.bounding_box()),
         }
     }
 
@@ -95,18 +378,152 @@ impl Element {
         match *self {
             Element::Sphere(ref s) => s.material.albedo,
             Element::Plane(ref p) => p.material.albedo,
+            Element::Triangle(ref t) => t.material.albedo,
+            Element::Mesh(ref m) => m.material.albedo,
+            Element::Cuboid(ref b) => b.material.albedo,
+            Element::Cylinder(ref c) => c.material.albedo,
+            Element::Cone(ref c) => c.material.albedo,
+            Element::Disk(ref d) => d.material.albedo,
+            Element::Torus(ref t) => t.material.albedo,
+            Element::Instance(ref i) => i.object.albedo(),
+            Element::Csg(ref c) => c.material().albedo,
+            Element::DistanceField(ref d) => d.material.albedo,
+            Element::Heightfield(ref h) => h.material.albedo,
+        }
+    }
+
+    /// Whether the element glows and can be sampled with `sample_emission`.
+    pub fn is_light_source(&self) -> bool {
+        match *self {
+            Element::Plane(_)
+            | Element::Instance(_)
+            | Element::Csg(_)
+            | Element::DistanceField(_)
+            | Element::Heightfield(_) => false,
+            _ => self.material().emission.is_some(),
+        }
+    }
+
+    /// Whether the element encloses a solid, so that it can be combined with others by
+    /// a `Csg`. Planes are solid: everything behind them is inside.
+    pub fn is_solid(&self) -> bool {
+        match *self {
+            Element::Triangle(_)
+            | Element::Mesh(_)
+            | Element::Disk(_)
+            | Element::DistanceField(_)
+            | Element::Heightfield(_) => false,
+            Element::Instance(ref i) => i.object.is_solid(),
+            _ => true,
         }
     }
+
+    /// The stretches of the whole line through the ray that are inside the element,
+    /// in order. Empty for elements that are not solid.
+    pub fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        match *self {
+            Element::Sphere(ref s) => s.intervals(ray),
+            Element::Plane(ref p) => p.intervals(ray),
+            Element::Triangle(_)
+            | Element::Mesh(_)
+            | Element::Disk(_)
+            | Element::DistanceField(_)
+            | Element::Heightfield(_) => vec![],
+            Element::Cuboid(ref b) => b.intervals(ray),
+            Element::Cylinder(ref c) => c.intervals(ray),
+            Element::Cone(ref c) => c.intervals(ray),
+            Element::Torus(ref t) => t.intervals(ray),
+            Element::Instance(ref i) => i.intervals(ray),
+            Element::Csg(ref c) => c.intervals(ray),
+        }
+    }
+
+    /// A random point on a glowing element, as seen from `from`. `None` if the
+    /// element does not glow, or can not be sampled.
+    pub fn sample_emission(&self, from: &Point, rng: &mut Rng) -> Option<LightSample> {
+        if !self.is_light_source() {
+            return None;
+        }
+        let radiance = self.material().emitted();
+
+        match *self {
+            Element::Sphere(ref s) => sample_sphere(from, &s.center, s.radius, radiance, rng),
+            Element::Plane(_)
+            | Element::Instance(_)
+            | Element::Csg(_)
+            | Element::DistanceField(_)
+            | Element::Heightfield(_) => None,
+            Element::Triangle(ref t) => {
+                let normal = (t.v1 - t.v0).cross(&(t.v2 - t.v0));
+                let area = normal.length() / 2.0;
+                let point = sample_triangle(&t.v0, &t.v1, &t.v2, rng);
+                sample_area(from, point, normal.normalize(), area, radiance, true)
+            }
+            Element::Mesh(ref m) => {
+                let total_area = *m.cumulative_areas.last()?;
+                let target = rng.next_f64() * total_area;
+                let face = m
+                    .cumulative_areas
+                    .partition_point(|&area| area <= target)
+                    .min(m.indices.len() - 1);
+                let (v0, v1, v2) = m.face_vertices(face);
+                let point = sample_triangle(v0, v1, v2, rng);
+                sample_area(from, point, m.face_normal(face), total_area, radiance, true)
+            }
+            Element::Disk(ref d) => {
+                let (point, normal) = d.sample_surface(rng);
+                sample_area(from, point, normal, d.area(), radiance, true)
+            }
+            Element::Cuboid(ref b) => {
+                let (point, normal) = b.sample_surface(rng);
+                sample_area(from, point, normal, b.area(), radiance, false)
+            }
+            Element::Cylinder(ref c) => {
+                let (point, normal) = c.sample_surface(rng);
+                sample_area(from, point, normal, c.area(), radiance, false)
+            }
+            Element::Cone(ref c) => {
+                let (point, normal) = c.sample_surface(rng);
+                sample_area(from, point, normal, c.area(), radiance, false)
+            }
+            Element::Torus(ref t) => {
+                let (point, normal) = t.sample_surface(rng);
+                sample_area(from, point, normal, t.area(), radiance, false)
+            }
+        }
+    }
+}
+
+/// The face of a mesh that a ray hit, and the barycentric coordinates `(w, u, v)` of
+/// the hit on it.
+#[derive(Clone, Copy, Debug)]
+pub struct FaceHit {
+    pub face: usize,
+    pub barycentric: (f64, f64, f64),
 }
 
 pub struct Intersection<'a> {
     pub distance: f64,
     pub object: &'a Element,
+    /// The face that was hit, if the object is a mesh or an instance of one
+    pub face: Option<FaceHit>,
 }
 
 impl<'a> Intersection<'a> {
     pub fn new<'b>(distance: f64, object: &'b Element) -> Intersection<'b> {
-        Intersection { distance, object }
+        Intersection {
+            distance,
+            object,
+            face: None,
+        }
+    }
+
+    pub fn surface_normal(&self, hit_point: &Point) -> Vector3 {
+        self.object.hit_normal(hit_point, self.face)
+    }
+
+    pub fn texture_coords(&self, hit_point: &Point) -> TextureCoords {
+        self.object.hit_texture_coords(hit_point, self.face)
     }
 }
 
@@ -121,6 +538,17 @@ impl Intersectable for Element {
         match *self {
             Element::Sphere(ref s) => s.intersect(ray),
             Element::Plane(ref p) => p.intersect(ray),
+            Element::Triangle(ref t) => t.intersect(ray),
+            Element::Mesh(ref m) => m.intersect(ray),
+            Element::Cuboid(ref b) => b.intersect(ray),
+            Element::Cylinder(ref c) => c.intersect(ray),
+            Element::Cone(ref c) => c.intersect(ray),
+            Element::Disk(ref d) => d.intersect(ray),
+            Element::Torus(ref t) => t.intersect(ray),
+            Element::Instance(ref i) => i.intersect(ray),
+            Element::Csg(ref c) => c.intersect(ray),
+            Element::DistanceField(ref d) => d.intersect(ray),
+            Element::Heightfield(ref h) => h.intersect(ray),
         }
     }
 
@@ -128,6 +556,17 @@ impl Intersectable for Element {
         match *self {
             Element::Sphere(ref s) => s.surface_normal(hit_point),
             Element::Plane(ref p) => p.surface_normal(hit_point),
+            Element::Triangle(ref t) => t.surface_normal(hit_point),
+            Element::Mesh(ref m) => m.surface_normal(hit_point),
+            Element::Cuboid(ref b) => b.surface_normal(hit_point),
+            Element::Cylinder(ref c) => c.surface_normal(hit_point),
+            Element::Cone(ref c) => c.surface_normal(hit_point),
+            Element::Disk(ref d) => d.surface_normal(hit_point),
+            Element::Torus(ref t) => t.surface_normal(hit_point),
+            Element::Instance(ref i) => i.surface_normal(hit_point),
+            Element::Csg(ref c) => c.surface_normal(hit_point),
+            Element::DistanceField(ref d) => d.surface_normal(hit_point),
+            Element::Heightfield(ref h) => h.surface_normal(hit_point),
         }
     }
 
@@ -135,6 +574,17 @@ impl Intersectable for Element {
         match *self {
             Element::Sphere(ref s) => s.texture_coords(hit_point),
             Element::Plane(ref p) => p.texture_coords(hit_point),
+            Element::Triangle(ref t) => t.texture_coords(hit_point),
+            Element::Mesh(ref m) => m.texture_coords(hit_point),
+            Element::Cuboid(ref b) => b.texture_coords(hit_point),
+            Element::Cylinder(ref c) => c.texture_coords(hit_point),
+            Element::Cone(ref c) => c.texture_coords(hit_point),
+            Element::Disk(ref d) => d.texture_coords(hit_point),
+            Element::Torus(ref t) => t.texture_coords(hit_point),
+            Element::Instance(ref i) => i.texture_coords(hit_point),
+            Element::Csg(ref c) => c.texture_coords(hit_point),
+            Element::DistanceField(ref d) => d.texture_coords(hit_point),
+            Element::Heightfield(ref h) => h.texture_coords(hit_point),
         }
     }
 }
@@ -145,26 +595,324 @@ pub struct DirectionalLight {
     pub intensity: f32,
 }
 
+/// A point light, shining equally in all directions. Casts hard shadows.
 pub struct SphericalLight {
     pub position: Point,
     pub color: Color,
     pub intensity: f32,
 }
 
+/// A point light that only shines in a cone around `direction`.
+///
+/// Within `inner_angle` of the direction, it is as bright as a `SphericalLight` of the
+/// same intensity. Between the inner and outer angles it fades out smoothly. Angles are
+/// in degrees, measured from the direction to the edge of the cone.
+pub struct SpotLight {
+    pub position: Point,
+    pub direction: Vector3,
+    pub inner_angle: f64,
+    pub outer_angle: f64,
+    pub color: Color,
+    pub intensity: f32,
+    /// A measured light distribution to use instead of the cone. The brightest direction
+    /// of the profile gets the full intensity of the light.
+    pub profile: Option<IesProfile>,
+}
+
+impl SpotLight {
+    /// How much of the light's intensity is sent in the given direction, between 0 and 1.
+    pub fn falloff(&self, direction: &Vector3) -> f32 {
+        let axis = self.direction.normalize();
+        let direction = direction.normalize();
+        let cos_angle = direction.dot(&axis);
+
+        if let Some(profile) = &self.profile {
+            // The profile's horizontal angles go around the axis, starting at the
+            // same tangent that the rest of the renderer uses for the axis.
+            let (tangent, bitangent) = orthonormal_basis(axis);
+            let vertical = cos_angle.clamp(-1.0, 1.0).acos().to_degrees();
+            let horizontal = direction
+                .dot(&bitangent)
+                .atan2(direction.dot(&tangent))
+                .to_degrees();
+            return profile.relative_intensity(vertical, horizontal) as f32;
+        }
+
+        let cos_inner = self.inner_angle.to_radians().cos();
+        let cos_outer = self.outer_angle.to_radians().cos();
+        if cos_angle >= cos_inner {
+            1.0
+        } else if cos_angle <= cos_outer {
+            0.0
+        } else {
+            // Smoothstep
+            let t = (cos_angle - cos_outer) / (cos_inner - cos_outer);
+            (t * t * (3.0 - 2.0 * t)) as f32
+        }
+    }
+}
+
+/// A parallelogram spanned by `edge1` and `edge2` from `corner`. It only shines to the
+/// side that `edge1 x edge2` points to.
+///
+/// Like `SphericalLight`, `intensity` is the total power emitted. Lights with an area
+/// cast soft shadows, estimated by tracing `samples` shadow rays to random points on
+/// the light.
+pub struct RectangleLight {
+    pub corner: Point,
+    pub edge1: Vector3,
+    pub edge2: Vector3,
+    pub color: Color,
+    pub intensity: f32,
+    pub samples: u32,
+}
+
+/// A disk shining to the side its normal points to.
+pub struct DiskLight {
+    pub center: Point,
+    pub normal: Vector3,
+    pub radius: f64,
+    pub color: Color,
+    pub intensity: f32,
+    pub samples: u32,
+}
+
+/// A glowing ball. Unlike `SphericalLight`, it has a size, so its shadows are soft.
+pub struct SphereLight {
+    pub center: Point,
+    pub radius: f64,
+    pub color: Color,
+    pub intensity: f32,
+    pub samples: u32,
+}
+
 pub enum Light {
     Directional(DirectionalLight),
     Spherical(SphericalLight),
+    Spot(SpotLight),
+    Rectangle(RectangleLight),
+    Disk(DiskLight),
+    Sphere(SphereLight),
+}
+
+/// A random point on a light, as seen from a point in the scene.
+pub struct LightSample {
+    /// Unit vector towards the point on the light
+    pub direction: Vector3,
+    pub distance: f64,
+    /// Light arriving along `direction`, divided by the probability density of picking
+    /// this direction. Averaging it over many samples gives the light arriving from the
+    /// whole light, before the cosine at the receiving surface is applied.
+    pub incident: Color,
+}
+
+impl RectangleLight {
+    pub fn sample(&self, from: &Point, rng: &mut Rng) -> Option<LightSample> {
+        let point =
+            self.corner.as_vector() + self.edge1 * rng.next_f64() + self.edge2 * rng.next_f64();
+        let normal = self.edge1.cross(&self.edge2);
+        let area = normal.length();
+        let radiance = flat_light_radiance(self.color, self.intensity, area);
+        sample_area(
+            from,
+            point.as_point(),
+            normal * (1.0 / area),
+            area,
+            radiance,
+            false,
+        )
+    }
+}
+
+impl DiskLight {
+    pub fn sample(&self, from: &Point, rng: &mut Rng) -> Option<LightSample> {
+        let normal = self.normal.normalize();
+        let (tangent, bitangent) = orthonormal_basis(normal);
+        let (x, y) = sample_disk(rng);
+        let point =
+            self.center.as_vector() + tangent * (x * self.radius) + bitangent * (y * self.radius);
+        let area = std::f64::consts::PI * self.radius * self.radius;
+        let radiance = flat_light_radiance(self.color, self.intensity, area);
+        sample_area(from, point.as_point(), normal, area, radiance, false)
+    }
+}
+
+impl SphereLight {
+    pub fn sample(&self, from: &Point, rng: &mut Rng) -> Option<LightSample> {
+        let area = 4.0 * std::f64::consts::PI * self.radius * self.radius;
+        let radiance = flat_light_radiance(self.color, self.intensity, area);
+        sample_sphere(from, &self.center, self.radius, radiance, rng)
+    }
+}
+
+/// Radiance of a surface that emits `intensity` in total from the given area.
+fn flat_light_radiance(color: Color, intensity: f32, area: f64) -> Color {
+    color * (intensity / (std::f64::consts::PI * area) as f32)
+}
+
+/// Sample a flat light from a point uniformly chosen on its surface.
+fn sample_area(
+    from: &Point,
+    point: Point,
+    normal: Vector3,
+    area: f64,
+    radiance: Color,
+    two_sided: bool,
+) -> Option<LightSample> {
+    let to_light = point - *from;
+    let distance = to_light.length();
+    let direction = to_light * (1.0 / distance);
+    let mut cos_light = -direction.dot(&normal);
+    if two_sided {
+        cos_light = cos_light.abs();
+    }
+    if cos_light <= 0.0 || distance <= 0.0 {
+        return None;
+    }
+
+    // Converting the density 1 / area on the surface to directions gives
+    // radiance * cos_light * area / distance^2.
+    let weight = cos_light * area / (distance * distance);
+    Some(LightSample {
+        direction,
+        distance,
+        incident: radiance * weight as f32,
+    })
+}
+
+/// Sample a glowing sphere from the cone of directions in which it is seen, so that no
+/// samples are wasted on its far side.
+fn sample_sphere(
+    from: &Point,
+    center: &Point,
+    radius: f64,
+    radiance: Color,
+    rng: &mut Rng,
+) -> Option<LightSample> {
+    let to_center = *center - *from;
+    let center_distance = to_center.length();
+    if center_distance <= radius {
+        return None;
+    }
+
+    let sin_max = radius / center_distance;
+    let cos_max = (1.0 - sin_max * sin_max).max(0.0).sqrt();
+    let axis = to_center * (1.0 / center_distance);
+    let direction = uniform_sample_cone(axis, cos_max, rng);
+
+    // Distance to the near side of the sphere along the sampled direction
+    let along = to_center.dot(&direction);
+    let perpendicular_sq = (center_distance * center_distance - along * along).max(0.0);
+    let distance = along - (radius * radius - perpendicular_sq).max(0.0).sqrt();
+
+    let solid_angle = 2.0 * std::f64::consts::PI * (1.0 - cos_max);
+    Some(LightSample {
+        direction,
+        distance,
+        incident: radiance * solid_angle as f32,
+    })
+}
+
+/// A uniformly distributed point on a triangle.
+fn sample_triangle(v0: &Point, v1: &Point, v2: &Point, rng: &mut Rng) -> Point {
+    let su = rng.next_f64().sqrt();
+    let b0 = 1.0 - su;
+    let b1 = rng.next_f64() * su;
+    (v0.as_vector() * b0 + v1.as_vector() * b1 + v2.as_vector() * (1.0 - b0 - b1)).as_point()
+}
+
+/// Acceleration structure over the elements of a scene. Elements without a bounding
+/// box, like planes, are kept in a separate list and always tested.
+pub struct SceneBvh {
+    bvh: Bvh,
+    unbounded: Vec<usize>,
+}
+
+impl SceneBvh {
+    pub fn build(elements: &[Element]) -> SceneBvh {
+        let mut bounded = vec![];
+        let mut unbounded = vec![];
+        for (index, element) in elements.iter().enumerate() {
+            match element.bounding_box() {
+                Some(bounds) => bounded.push((index, bounds)),
+                None => unbounded.push(index),
+            }
+        }
+
+        SceneBvh {
+            bvh: Bvh::build(bounded),
+            unbounded,
+        }
+    }
+
+    fn trace<'a>(&self, elements: &'a [Element], ray: &Ray) -> Option<Intersection<'a>> {
+        let closest_unbounded = self
+            .unbounded
+            .iter()
+            .filter_map(|&i| {
+                elements[i]
+                    .intersect_face(ray)
+                    .map(|(d, face)| (i, d, face))
+            })
+            .min_by(|(_, d1, _), (_, d2, _)| d1.partial_cmp(d2).unwrap());
+        let max_distance = closest_unbounded.map_or(f64::INFINITY, |(_, d, _)| d);
+
+        let closest_bounded = self.bvh.trace(ray, |i| {
+            elements[i]
+                .intersect_face(ray)
+                .filter(|(d, _)| *d < max_distance)
+        });
+
+        closest_bounded
+            .or(closest_unbounded)
+            .map(|(i, distance, face)| Intersection {
+                distance,
+                object: &elements[i],
+                face,
+            })
+    }
+}
+
+/// How the light reaching the camera is computed.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Integrator {
+    /// Direct light from every light source, plus perfect reflection and refraction.
+    /// Fast and free of noise, but there is no light bouncing between diffuse surfaces.
+    Whitted,
+    /// Monte Carlo path tracing, including indirect light. Needs many samples per pixel
+    /// to converge.
+    PathTracing,
 }
 
 pub struct Scene {
     pub width: u32,
     pub height: u32,
-    pub fov: f64,
+    pub camera: Camera,
     pub lights: Vec<Light>,
-    pub elements: Vec<Element>,
+    /// Changed only through `add_element` and `elements_mut`, which discard `bvh`
+    elements: Vec<Element>,
 
     pub shadow_bias: f64,
     pub max_recursion_depth: u32,
+    /// Number of rays per pixel. With a single sample, the ray goes through the pixel center.
+    pub samples_per_pixel: u32,
+    pub sample_pattern: SamplePattern,
+    pub filter: Filter,
+    pub integrator: Integrator,
+    /// Seen by rays that miss every element, and lighting the scene
+    pub background: Background,
+    /// Number of shadow rays towards the background per shaded point. With none, the
+    /// background only lights surfaces through the paths that hit it.
+    pub background_samples: u32,
+    /// Brightness adjustment in stops, applied before tone mapping
+    pub exposure: f32,
+    pub tone_map: ToneMap,
+    /// Add noise before rounding colors to 8 bits, to avoid banding
+    pub dither: bool,
+
+    /// The bounding volume hierarchy used by `trace`, built by the first call
+    bvh: OnceLock<SceneBvh>,
 }
 
 impl Scene {
@@ -178,37 +926,73 @@ impl Scene {
         Scene {
             width,
             height,
-            fov,
+            camera: Camera::new(fov),
             lights: vec![],
             elements: vec![],
             shadow_bias,
             max_recursion_depth,
+            samples_per_pixel: 1,
+            sample_pattern: SamplePattern::Jittered,
+            filter: Filter::Box,
+            integrator: Integrator::Whitted,
+            background: Background::default(),
+            background_samples: 16,
+            exposure: 0.0,
+            tone_map: ToneMap::Clamp,
+            dither: false,
+            bvh: OnceLock::new(),
         }
     }
 
+    /// The point seen through the center of the pixel, at distance `-z` in front of the
+    /// camera. For the default camera, looking down the negative z axis from the origin,
+    /// this is the point with the given `z` coordinate.
     pub fn pixel_to_world_coordinates(&self, px: u32, py: u32, z: f64) -> Point {
-        let fov_adjustment = (self.fov.to_radians() / 2.0).tan();
-        let aspect_ratio = (self.width as f64) / (self.height as f64);
-        let sensor_x =
-            ((((px as f64 + 0.5) / self.width as f64) * 2.0 - 1.0) * aspect_ratio) * fov_adjustment;
-        let sensor_y = (1.0 - ((py as f64 + 0.5) / self.height as f64) * 2.0) * fov_adjustment;
+        self.image_to_world_coordinates(px as f64 + 0.5, py as f64 + 0.5, z)
+    }
 
-        Point {
-            x: sensor_x,
-            y: sensor_y,
-            z,
-        }
+    /// Like `pixel_to_world_coordinates`, but for any point on the image plane, not
+    /// just pixel centers. Pixel `(x, y)` covers `[x, x + 1) × [y, y + 1)`.
+    pub fn image_to_world_coordinates(&self, image_x: f64, image_y: f64, z: f64) -> Point {
+        self.camera
+            .image_to_world(image_x, image_y, self.width, self.height, -z)
     }
 
-    pub fn trace(&self, ray: &Ray) -> Option<Intersection> {
+    pub fn trace(&self, ray: &Ray) -> Option<Intersection<'_>> {
+        self.bvh
+            .get_or_init(|| SceneBvh::build(&self.elements))
+            .trace(&self.elements, ray)
+    }
+
+    /// Like `trace`, but testing every element against the ray instead of using the
+    /// bounding volume hierarchy.
+    pub fn trace_linear(&self, ray: &Ray) -> Option<Intersection<'_>> {
         self.elements
             .iter()
-            .filter_map(|s| s.intersect(ray).map(|d| Intersection::new(d, s)))
+            .filter_map(|s| {
+                s.intersect_face(ray).map(|(distance, face)| Intersection {
+                    distance,
+                    object: s,
+                    face,
+                })
+            })
             .min_by(|i1, i2| i1.distance.partial_cmp(&i2.distance).unwrap())
     }
 
+    pub fn elements(&self) -> &[Element] {
+        &self.elements
+    }
+
+    /// Add an element. This discards the bounding volume hierarchy, if any.
     pub fn add_element(&mut self, element: Element) {
-        self.elements.push(element);
+        self.elements_mut().push(element);
+    }
+
+    /// The elements, to change them. This discards the bounding volume hierarchy, if
+    /// any, which the next `trace` builds again.
+    pub fn elements_mut(&mut self) -> &mut Vec<Element> {
+        self.bvh = OnceLock::new();
+        &mut self.elements
     }
 
     pub fn add_light(&mut self, light: Light) {
@@ -216,6 +1000,17 @@ impl Scene {
     }
 }
 
+impl Sphere {
+    pub fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        let l = ray.origin - self.center;
+        quadratic_intervals(
+            ray.direction.sq_length(),
+            2. * l.dot(&ray.direction),
+            l.sq_length() - self.radius * self.radius,
+        )
+    }
+}
+
 impl Intersectable for Sphere {
     fn intersect(&self, ray: &Ray) -> Option<f64> {
         let ray_origin = ray.origin;
@@ -226,7 +1021,7 @@ impl Intersectable for Sphere {
         let l = sphere_center - ray_origin;
         let hyp_sq = l.sq_length();
 
-        let a = l.dot(&ray_direction);
+        let a = l.dot(ray_direction);
 
         let d2 = hyp_sq - a * a;
         let r2 = self.radius * self.radius;
@@ -245,12 +1040,13 @@ impl Intersectable for Sphere {
             return None;
         }
 
-        Some(t0.min(t1))
-
-        // TODO: handle the case when the sphere is behind the camera
-        // See here https://www.scratchapixel.com/lessons/3d-basic-rendering/minimal-ray-tracer-rendering-simple-shapes/ray-sphere-intersection
+        // If the ray starts inside the sphere (as transmission rays do), the nearest
+        // intersection in front of it is the far one.
+        if t0 < 0. {
+            return Some(t1);
+        }
 
-        // Some(a - q2.sqrt())
+        Some(t0)
     }
 
     fn surface_normal(&self, point: &Point) -> Vector3 {
@@ -266,6 +1062,33 @@ impl Intersectable for Sphere {
     }
 }
 
+impl Plane {
+    /// The solid behind the plane: the side its `normal` points to.
+    pub fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        let along = self.normal.dot(&ray.direction);
+        let depth = self.normal.dot(&(ray.origin - self.p0));
+        if along.abs() < 1e-12 {
+            return if depth > 0. {
+                vec![Interval::everywhere()]
+            } else {
+                vec![]
+            };
+        }
+        let t = -depth / along;
+        if along > 0. {
+            vec![Interval {
+                enter: t,
+                exit: f64::INFINITY,
+            }]
+        } else {
+            vec![Interval {
+                enter: f64::NEG_INFINITY,
+                exit: t,
+            }]
+        }
+    }
+}
+
 impl Intersectable for Plane {
     fn intersect(&self, ray: &Ray) -> Option<f64> {
         let n = self.normal;
@@ -314,3 +1137,1012 @@ impl Intersectable for Plane {
         }
     }
 }
+
+/// Intersect a ray with the triangle spanned by the three points, using the
+/// Möller–Trumbore algorithm.
+///
+/// See https://www.scratchapixel.com/lessons/3d-basic-rendering/ray-tracing-rendering-a-triangle/moller-trumbore-ray-triangle-intersection
+pub(crate) fn intersect_triangle(ray: &Ray, v0: &Point, v1: &Point, v2: &Point) -> Option<f64> {
+    intersect_triangle_barycentric(ray, v0, v1, v2).map(|(dist, _)| dist)
+}
+
+/// Like `intersect_triangle`, but also gives the barycentric coordinates `(w, u, v)` of
+/// the hit, as `barycentric` does.
+fn intersect_triangle_barycentric(
+    ray: &Ray,
+    v0: &Point,
+    v1: &Point,
+    v2: &Point,
+) -> Option<(f64, (f64, f64, f64))> {
+    let edge1 = *v1 - *v0;
+    let edge2 = *v2 - *v0;
+    let p = ray.direction.cross(&edge2);
+    let det = edge1.dot(&p);
+
+    // The ray is parallel to the triangle
+    if det.abs() < 1e-12 {
+        return None;
+    }
+
+    let inv_det = 1.0 / det;
+    let t_vec = ray.origin - *v0;
+    let u = t_vec.dot(&p) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let q = t_vec.cross(&edge1);
+    let v = ray.direction.dot(&q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let dist = edge2.dot(&q) * inv_det;
+    if dist > 0.0 {
+        Some((dist, (1.0 - u - v, u, v)))
+    } else {
+        None
+    }
+}
+
+/// Barycentric coordinates `(w, u, v)` of the point with respect to the triangle, so
+/// that the point equals `w * v0 + u * v1 + v * v2` when it lies in the triangle plane.
+fn barycentric(point: &Point, v0: &Point, v1: &Point, v2: &Point) -> (f64, f64, f64) {
+    let edge1 = *v1 - *v0;
+    let edge2 = *v2 - *v0;
+    let to_point = *point - *v0;
+
+    let d11 = edge1.dot(&edge1);
+    let d12 = edge1.dot(&edge2);
+    let d22 = edge2.dot(&edge2);
+    let dp1 = to_point.dot(&edge1);
+    let dp2 = to_point.dot(&edge2);
+
+    let denominator = d11 * d22 - d12 * d12;
+    let u = (d22 * dp1 - d12 * dp2) / denominator;
+    let v = (d11 * dp2 - d12 * dp1) / denominator;
+    (1.0 - u - v, u, v)
+}
+
+impl Intersectable for Triangle {
+    fn intersect(&self, ray: &Ray) -> Option<f64> {
+        intersect_triangle(ray, &self.v0, &self.v1, &self.v2)
+    }
+
+    fn surface_normal(&self, _hit_point: &Point) -> Vector3 {
+        (self.v1 - self.v0).cross(&(self.v2 - self.v0)).normalize()
+    }
+
+    fn texture_coords(&self, hit_point: &Point) -> TextureCoords {
+        let (_, u, v) = barycentric(hit_point, &self.v0, &self.v1, &self.v2);
+        TextureCoords {
+            x: u as f32,
+            y: v as f32,
+        }
+    }
+}
+
+impl Mesh {
+    pub fn new(
+        vertices: Vec<Point>,
+        normals: Vec<Vector3>,
+        texture_coords: Vec<TextureCoords>,
+        indices: Vec<[usize; 3]>,
+        material: Material,
+    ) -> Mesh {
+        let faces = indices
+            .iter()
+            .enumerate()
+            .map(|(face, [a, b, c])| {
+                (
+                    face,
+                    BoundingBox::from_points(&[vertices[*a], vertices[*b], vertices[*c]]),
+                )
+            })
+            .collect();
+
+        let mut total_area = 0.0;
+        let cumulative_areas = indices
+            .iter()
+            .map(|[a, b, c]| {
+                let (v0, v1, v2) = (vertices[*a], vertices[*b], vertices[*c]);
+                total_area += (v1 - v0).cross(&(v2 - v0)).length() / 2.0;
+                total_area
+            })
+            .collect();
+
+        Mesh {
+            vertices,
+            normals,
+            texture_coords,
+            indices,
+            material,
+            bvh: Bvh::build(faces),
+            cumulative_areas,
+        }
+    }
+
+    pub fn vertices(&self) -> &[Point] {
+        &self.vertices
+    }
+
+    pub fn normals(&self) -> &[Vector3] {
+        &self.normals
+    }
+
+    pub fn vertex_texture_coords(&self) -> &[TextureCoords] {
+        &self.texture_coords
+    }
+
+    pub fn indices(&self) -> &[[usize; 3]] {
+        &self.indices
+    }
+
+    pub fn bounding_box(&self) -> BoundingBox {
+        self.bvh.bounds()
+    }
+
+    fn face_vertices(&self, face: usize) -> (&Point, &Point, &Point) {
+        let [a, b, c] = self.indices[face];
+        (&self.vertices[a], &self.vertices[b], &self.vertices[c])
+    }
+
+    fn face_normal(&self, face: usize) -> Vector3 {
+        let (v0, v1, v2) = self.face_vertices(face);
+        (*v1 - *v0).cross(&(*v2 - *v0)).normalize()
+    }
+
+    /// How far the point is from lying on the face, together with its barycentric
+    /// coordinates on the face.
+    fn face_distance(&self, face: usize, point: &Point) -> Option<(f64, (f64, f64, f64))> {
+        let (v0, v1, v2) = self.face_vertices(face);
+        let distance_to_plane = self.face_normal(face).dot(&(*point - *v0)).abs();
+        if distance_to_plane.is_nan() {
+            // Degenerate face
+            return None;
+        }
+
+        let (w, u, v) = barycentric(point, v0, v1, v2);
+        let outside = (-w).max(-u).max(-v).max(0.0);
+        let scale = (*v1 - *v0).length().max((*v2 - *v0).length());
+        Some((distance_to_plane + outside * scale, (w, u, v)))
+    }
+
+    /// Find the face a point on the surface of the mesh lies on, together with its
+    /// barycentric coordinates on that face. Only the faces whose bounding boxes are
+    /// near the point are checked, so points off the mesh may not be found; see
+    /// `nearest_face` for those.
+    ///
+    /// This is for callers that only have the point. Traced rays already know the face
+    /// they hit, see `intersect_face`.
+    ///
+    /// Since normals and texture coordinates are interpolated from values shared
+    /// between neighbouring faces, it does not matter which face we pick for a point
+    /// on an edge.
+    fn locate(&self, point: &Point) -> Option<(usize, (f64, f64, f64))> {
+        let bounds = self.bvh.bounds();
+        let epsilon = 1e-6 * (1.0 + bounds.max.distance(&bounds.min));
+        let mut candidates = vec![];
+        self.bvh
+            .query_point(point, epsilon, |face| candidates.push(face));
+
+        candidates
+            .into_iter()
+            .filter_map(|face| {
+                self.face_distance(face, point)
+                    .map(|(score, coords)| (score, face, coords))
+            })
+            .min_by(|(s1, _, _), (s2, _, _)| s1.partial_cmp(s2).unwrap())
+            .map(|(_, face, coords)| (face, coords))
+    }
+
+    /// The face nearest to a point off the mesh, with its barycentric coordinates
+    /// clamped onto the face. Unlike `locate` this checks every face, so it is only
+    /// meant as a fallback when `locate` finds nothing.
+    fn nearest_face(&self, point: &Point) -> (usize, (f64, f64, f64)) {
+        let (face, (w, u, v)) = (0..self.indices.len())
+            .filter_map(|face| {
+                self.face_distance(face, point)
+                    .map(|(score, coords)| (score, face, coords))
+            })
+            .min_by(|(s1, _, _), (s2, _, _)| s1.partial_cmp(s2).unwrap())
+            .map(|(_, face, coords)| (face, coords))
+            .unwrap_or((0, (1.0, 0.0, 0.0)));
+
+        let (w, u, v) = (w.max(0.0), u.max(0.0), v.max(0.0));
+        let total = w + u + v;
+        (face, (w / total, u / total, v / total))
+    }
+
+    /// The distance to the nearest face the ray hits, and where on it.
+    pub fn intersect_face(&self, ray: &Ray) -> Option<(f64, FaceHit)> {
+        self.bvh
+            .trace(ray, |face| {
+                let (v0, v1, v2) = self.face_vertices(face);
+                intersect_triangle_barycentric(ray, v0, v1, v2)
+            })
+            .map(|(face, distance, barycentric)| (distance, FaceHit { face, barycentric }))
+    }
+
+    /// The normal interpolated from the vertex normals at the hit, or the flat normal
+    /// of the face if the mesh has none.
+    pub fn face_hit_normal(&self, hit: &FaceHit) -> Vector3 {
+        if self.normals.is_empty() {
+            return self.face_normal(hit.face);
+        }
+
+        let [a, b, c] = self.indices[hit.face];
+        let (w, u, v) = hit.barycentric;
+        (self.normals[a] * w + self.normals[b] * u + self.normals[c] * v).normalize()
+    }
+
+    /// The texture coordinates interpolated from the vertices at the hit, or the
+    /// barycentric coordinates if the mesh has none.
+    pub fn face_hit_texture_coords(&self, hit: &FaceHit) -> TextureCoords {
+        let (face, (w, u, v)) = (hit.face, hit.barycentric);
+
+        if self.texture_coords.is_empty() {
+            return TextureCoords {
+                x: u as f32,
+                y: v as f32,
+            };
+        }
+
+        let [a, b, c] = self.indices[face];
+        let (ta, tb, tc) = (
+            self.texture_coords[a],
+            self.texture_coords[b],
+            self.texture_coords[c],
+        );
+        let (w, u, v) = (w as f32, u as f32, v as f32);
+        TextureCoords {
+            x: ta.x * w + tb.x * u + tc.x * v,
+            y: ta.y * w + tb.y * u + tc.y * v,
+        }
+    }
+}
+
+impl Intersectable for Mesh {
+    fn intersect(&self, ray: &Ray) -> Option<f64> {
+        self.intersect_face(ray).map(|(distance, _)| distance)
+    }
+
+    fn surface_normal(&self, hit_point: &Point) -> Vector3 {
+        let (face, barycentric) = self
+            .locate(hit_point)
+            .unwrap_or_else(|| self.nearest_face(hit_point));
+        self.face_hit_normal(&FaceHit { face, barycentric })
+    }
+
+    fn texture_coords(&self, hit_point: &Point) -> TextureCoords {
+        let (face, barycentric) = self
+            .locate(hit_point)
+            .unwrap_or_else(|| self.nearest_face(hit_point));
+        self.face_hit_texture_coords(&FaceHit { face, barycentric })
+    }
+}
+
+/// Coordinates for shapes that are round around an axis: `y` runs along the axis from
+/// `origin`, `x` and `z` across it.
+struct AxisFrame {
+    origin: Point,
+    tangent: Vector3,
+    axis: Vector3,
+    bitangent: Vector3,
+}
+
+impl AxisFrame {
+    fn new(origin: Point, axis: Vector3) -> AxisFrame {
+        let axis = axis.normalize();
+        let (tangent, bitangent) = orthonormal_basis(axis);
+        AxisFrame {
+            origin,
+            tangent,
+            axis,
+            bitangent,
+        }
+    }
+
+    fn local_vector(&self, vector: &Vector3) -> Vector3 {
+        Vector3 {
+            x: vector.dot(&self.tangent),
+            y: vector.dot(&self.axis),
+            z: vector.dot(&self.bitangent),
+        }
+    }
+
+    fn local_point(&self, point: &Point) -> Point {
+        self.local_vector(&(*point - self.origin)).as_point()
+    }
+
+    fn local_ray(&self, ray: &Ray) -> Ray {
+        Ray {
+            origin: self.local_point(&ray.origin),
+            direction: self.local_vector(&ray.direction),
+        }
+    }
+
+    fn world_vector(&self, vector: &Vector3) -> Vector3 {
+        self.tangent * vector.x + self.axis * vector.y + self.bitangent * vector.z
+    }
+
+    fn world_point(&self, point: &Point) -> Point {
+        (self.origin.as_vector() + self.world_vector(&point.as_vector())).as_point()
+    }
+}
+
+/// Where `a t^2 + b t + c <= 0`, the inside of a quadric surface along a ray.
+fn quadratic_intervals(a: f64, b: f64, c: f64) -> Vec<Interval> {
+    if a.abs() < 1e-12 {
+        if b.abs() < 1e-12 {
+            return if c <= 0. {
+                vec![Interval::everywhere()]
+            } else {
+                vec![]
+            };
+        }
+        let t = -c / b;
+        return if b > 0. {
+            vec![Interval {
+                enter: f64::NEG_INFINITY,
+                exit: t,
+            }]
+        } else {
+            vec![Interval {
+                enter: t,
+                exit: f64::INFINITY,
+            }]
+        };
+    }
+    match solve_quadratic(a, b, c) {
+        Some((t0, t1)) if a > 0. => vec![Interval {
+            enter: t0,
+            exit: t1,
+        }],
+        Some((t0, t1)) => vec![
+            Interval {
+                enter: f64::NEG_INFINITY,
+                exit: t0,
+            },
+            Interval {
+                enter: t1,
+                exit: f64::INFINITY,
+            },
+        ],
+        None if a > 0. => vec![],
+        None => vec![Interval::everywhere()],
+    }
+}
+
+/// Where a ray in an `AxisFrame` is between heights 0 and `height`, or `None` if it
+/// never is.
+fn height_range(ray: &Ray, height: f64) -> Option<(f64, f64)> {
+    let (o, d) = (ray.origin.y, ray.direction.y);
+    if d.abs() < 1e-12 {
+        return if (0.0..=height).contains(&o) {
+            Some((f64::NEG_INFINITY, f64::INFINITY))
+        } else {
+            None
+        };
+    }
+    let (t0, t1) = (-o / d, (height - o) / d);
+    Some((t0.min(t1), t0.max(t1)))
+}
+
+/// The bounding box of a disk, which reaches less far along the axes its normal
+/// leans towards.
+fn disk_bounds(center: &Point, normal: &Vector3, radius: f64) -> BoundingBox {
+    let normal = normal.normalize();
+    let extent = |n: f64| radius * (1. - n * n).max(0.).sqrt();
+    let r = Vector3 {
+        x: extent(normal.x),
+        y: extent(normal.y),
+        z: extent(normal.z),
+    };
+    BoundingBox {
+        min: (center.as_vector() - r).as_point(),
+        max: (center.as_vector() + r).as_point(),
+    }
+}
+
+/// Texture coordinates going once around the axis in `x`, and from 0 to 1 along
+/// `height` in `y`.
+fn around_axis_coords(local: &Point, height: f64) -> TextureCoords {
+    TextureCoords {
+        x: (0.5 + local.z.atan2(local.x) / (2. * PI)) as f32,
+        y: (local.y / height) as f32,
+    }
+}
+
+/// Texture coordinates of a point on a cap of `radius`, so that the cap fills the
+/// unit square.
+fn cap_coords(local: &Point, radius: f64) -> TextureCoords {
+    TextureCoords {
+        x: (0.5 + 0.5 * local.x / radius) as f32,
+        y: (0.5 + 0.5 * local.z / radius) as f32,
+    }
+}
+
+/// A uniformly distributed point on a cap of `radius` at height `y`.
+fn sample_cap(y: f64, radius: f64, rng: &mut Rng) -> Point {
+    let (x, z) = sample_disk(rng);
+    Point {
+        x: x * radius,
+        y,
+        z: z * radius,
+    }
+}
+
+impl Cuboid {
+    pub fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        match self.bounding_box().span(ray) {
+            Some((enter, exit)) => vec![Interval { enter, exit }],
+            None => vec![],
+        }
+    }
+
+    pub fn bounding_box(&self) -> BoundingBox {
+        BoundingBox {
+            min: self.min,
+            max: self.max,
+        }
+    }
+
+    fn size(&self) -> [f64; 3] {
+        let size = self.max - self.min;
+        [size.x, size.y, size.z]
+    }
+
+    pub fn area(&self) -> f64 {
+        let [x, y, z] = self.size();
+        2. * (x * y + y * z + z * x)
+    }
+
+    /// The axis the face nearest to the point is perpendicular to, and whether it is
+    /// the face at `max`.
+    fn nearest_face(&self, point: &Point) -> (usize, bool) {
+        let p = [point.x, point.y, point.z];
+        let min = [self.min.x, self.min.y, self.min.z];
+        let max = [self.max.x, self.max.y, self.max.z];
+        let mut nearest = (0, false);
+        let mut nearest_distance = f64::INFINITY;
+        for axis in 0..3 {
+            for (at_max, bound) in [(false, min[axis]), (true, max[axis])] {
+                let distance = (p[axis] - bound).abs();
+                if distance < nearest_distance {
+                    nearest_distance = distance;
+                    nearest = (axis, at_max);
+                }
+            }
+        }
+        nearest
+    }
+
+    fn face_normal(axis: usize, at_max: bool) -> Vector3 {
+        let mut n = [0.; 3];
+        n[axis] = if at_max { 1. } else { -1. };
+        Vector3 {
+            x: n[0],
+            y: n[1],
+            z: n[2],
+        }
+    }
+
+    /// A uniformly distributed point on the surface, with the normal there.
+    pub fn sample_surface(&self, rng: &mut Rng) -> (Point, Vector3) {
+        let size = self.size();
+        let face_areas = [size[1] * size[2], size[2] * size[0], size[0] * size[1]];
+        let mut target = rng.next_f64() * face_areas.iter().sum::<f64>();
+        let mut axis = 0;
+        while axis < 2 && target >= face_areas[axis] {
+            target -= face_areas[axis];
+            axis += 1;
+        }
+        let at_max = rng.next_f64() < 0.5;
+
+        let min = [self.min.x, self.min.y, self.min.z];
+        let mut p = [0.; 3];
+        for (i, value) in p.iter_mut().enumerate() {
+            *value = if i == axis {
+                min[i] + if at_max { size[i] } else { 0. }
+            } else {
+                min[i] + rng.next_f64() * size[i]
+            };
+        }
+        (Point::from_list(p), Cuboid::face_normal(axis, at_max))
+    }
+}
+
+impl Intersectable for Cuboid {
+    fn intersect(&self, ray: &Ray) -> Option<f64> {
+        first_boundary(&self.intervals(ray))
+    }
+
+    fn surface_normal(&self, hit_point: &Point) -> Vector3 {
+        let (axis, at_max) = self.nearest_face(hit_point);
+        Cuboid::face_normal(axis, at_max)
+    }
+
+    /// Each face is mapped onto the unit square.
+    fn texture_coords(&self, hit_point: &Point) -> TextureCoords {
+        let (axis, _) = self.nearest_face(hit_point);
+        let p = [hit_point.x, hit_point.y, hit_point.z];
+        let min = [self.min.x, self.min.y, self.min.z];
+        let size = self.size();
+        let along = |i: usize| ((p[i] - min[i]) / size[i]) as f32;
+        let (u, v) = match axis {
+            0 => (2, 1),
+            1 => (0, 2),
+            _ => (0, 1),
+        };
+        TextureCoords {
+            x: along(u),
+            y: along(v),
+        }
+    }
+}
+
+impl Cylinder {
+    fn frame(&self) -> AxisFrame {
+        AxisFrame::new(self.base, self.axis)
+    }
+
+    pub fn bounding_box(&self) -> BoundingBox {
+        let top = (self.base.as_vector() + self.axis.normalize() * self.height).as_point();
+        disk_bounds(&self.base, &self.axis, self.radius).union(&disk_bounds(
+            &top,
+            &self.axis,
+            self.radius,
+        ))
+    }
+
+    pub fn area(&self) -> f64 {
+        2. * PI * self.radius * (self.height + self.radius)
+    }
+
+    /// Inside the infinitely long tube, and between the caps.
+    pub fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        let ray = self.frame().local_ray(ray);
+        let (o, d) = (ray.origin, ray.direction);
+        let tube = quadratic_intervals(
+            d.x * d.x + d.z * d.z,
+            2. * (o.x * d.x + o.z * d.z),
+            o.x * o.x + o.z * o.z - self.radius * self.radius,
+        );
+        match height_range(&ray, self.height) {
+            Some((min, max)) => clip(&tube, min, max),
+            None => vec![],
+        }
+    }
+
+    /// The part of the surface a local point is on: the side, or the bottom or top
+    /// cap.
+    fn on_cap(&self, local: &Point) -> Option<bool> {
+        let side = ((local.x * local.x + local.z * local.z).sqrt() - self.radius).abs();
+        let bottom = local.y.abs();
+        let top = (local.y - self.height).abs();
+        if side <= bottom && side <= top {
+            None
+        } else {
+            Some(top < bottom)
+        }
+    }
+
+    pub fn sample_surface(&self, rng: &mut Rng) -> (Point, Vector3) {
+        let side_area = 2. * PI * self.radius * self.height;
+        let target = rng.next_f64() * self.area();
+        let (point, normal) = if target < side_area {
+            let phi = 2. * PI * rng.next_f64();
+            let (x, z) = (phi.cos(), phi.sin());
+            let point = Point {
+                x: x * self.radius,
+                y: rng.next_f64() * self.height,
+                z: z * self.radius,
+            };
+            (point, Vector3 { x, y: 0., z })
+        } else {
+            let top = target - side_area < PI * self.radius * self.radius;
+            let y = if top { self.height } else { 0. };
+            let normal = Vector3 {
+                x: 0.,
+                y: if top { 1. } else { -1. },
+                z: 0.,
+            };
+            (sample_cap(y, self.radius, rng), normal)
+        };
+        let frame = self.frame();
+        (frame.world_point(&point), frame.world_vector(&normal))
+    }
+}
+
+impl Intersectable for Cylinder {
+    fn intersect(&self, ray: &Ray) -> Option<f64> {
+        first_boundary(&self.intervals(ray))
+    }
+
+    fn surface_normal(&self, hit_point: &Point) -> Vector3 {
+        let frame = self.frame();
+        let local = frame.local_point(hit_point);
+        let normal = match self.on_cap(&local) {
+            None => Vector3 {
+                x: local.x,
+                y: 0.,
+                z: local.z,
+            }
+            .normalize(),
+            Some(top) => Vector3 {
+                x: 0.,
+                y: if top { 1. } else { -1. },
+                z: 0.,
+            },
+        };
+        frame.world_vector(&normal)
+    }
+
+    /// The side wraps once around `x` and runs up `y`; each cap fills the unit
+    /// square.
+    fn texture_coords(&self, hit_point: &Point) -> TextureCoords {
+        let local = self.frame().local_point(hit_point);
+        match self.on_cap(&local) {
+            None => around_axis_coords(&local, self.height),
+            Some(_) => cap_coords(&local, self.radius),
+        }
+    }
+}
+
+impl Cone {
+    fn frame(&self) -> AxisFrame {
+        AxisFrame::new(self.base, self.axis)
+    }
+
+    pub fn bounding_box(&self) -> BoundingBox {
+        let tip = (self.base.as_vector() + self.axis.normalize() * self.height).as_point();
+        disk_bounds(&self.base, &self.axis, self.radius).grow(&tip)
+    }
+
+    fn side_area(&self) -> f64 {
+        PI * self.radius * self.radius.hypot(self.height)
+    }
+
+    pub fn area(&self) -> f64 {
+        self.side_area() + PI * self.radius * self.radius
+    }
+
+    /// Inside the double cone through the tip, and between the base and the tip.
+    pub fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        let ray = self.frame().local_ray(ray);
+        let (o, d) = (ray.origin, ray.direction);
+        // x^2 + z^2 = (k (height - y))^2, with k the radius per unit of height
+        let k2 = (self.radius / self.height).powi(2);
+        let h = self.height - o.y;
+        let double_cone = quadratic_intervals(
+            d.x * d.x + d.z * d.z - k2 * d.y * d.y,
+            2. * (o.x * d.x + o.z * d.z + k2 * h * d.y),
+            o.x * o.x + o.z * o.z - k2 * h * h,
+        );
+        match height_range(&ray, self.height) {
+            Some((min, max)) => clip(&double_cone, min, max),
+            None => vec![],
+        }
+    }
+
+    /// Radius of the cone at local height `y`.
+    fn radius_at(&self, y: f64) -> f64 {
+        self.radius * (1. - y / self.height)
+    }
+
+    fn on_base(&self, local: &Point) -> bool {
+        let side = (local.x.hypot(local.z) - self.radius_at(local.y)).abs();
+        local.y.abs() < side
+    }
+
+    /// The outward normal on the side, in local coordinates. It leans towards the tip,
+    /// the more so the flatter the cone is.
+    fn side_normal(&self, local: &Point) -> Vector3 {
+        let distance = local.x.hypot(local.z);
+        if distance <= 0. {
+            return Vector3 {
+                x: 0.,
+                y: 1.,
+                z: 0.,
+            };
+        }
+        Vector3 {
+            x: local.x / distance * self.height,
+            y: self.radius,
+            z: local.z / distance * self.height,
+        }
+        .normalize()
+    }
+
+    pub fn sample_surface(&self, rng: &mut Rng) -> (Point, Vector3) {
+        let (point, normal) = if rng.next_f64() * self.area() < self.side_area() {
+            // The side is wider further from the tip
+            let from_tip = rng.next_f64().sqrt();
+            let phi = 2. * PI * rng.next_f64();
+            let point = Point {
+                x: phi.cos() * self.radius * from_tip,
+                y: self.height * (1. - from_tip),
+                z: phi.sin() * self.radius * from_tip,
+            };
+            (point, self.side_normal(&point))
+        } else {
+            let normal = Vector3 {
+                x: 0.,
+                y: -1.,
+                z: 0.,
+            };
+            (sample_cap(0., self.radius, rng), normal)
+        };
+        let frame = self.frame();
+        (frame.world_point(&point), frame.world_vector(&normal))
+    }
+}
+
+impl Intersectable for Cone {
+    fn intersect(&self, ray: &Ray) -> Option<f64> {
+        first_boundary(&self.intervals(ray))
+    }
+
+    fn surface_normal(&self, hit_point: &Point) -> Vector3 {
+        let frame = self.frame();
+        let local = frame.local_point(hit_point);
+        if self.on_base(&local) {
+            frame.axis * -1.
+        } else {
+            frame.world_vector(&self.side_normal(&local))
+        }
+    }
+
+    /// The side wraps once around `x` and runs up to the tip in `y`; the base fills
+    /// the unit square.
+    fn texture_coords(&self, hit_point: &Point) -> TextureCoords {
+        let local = self.frame().local_point(hit_point);
+        if self.on_base(&local) {
+            cap_coords(&local, self.radius)
+        } else {
+            around_axis_coords(&local, self.height)
+        }
+    }
+}
+
+impl Disk {
+    fn frame(&self) -> AxisFrame {
+        AxisFrame::new(self.center, self.normal)
+    }
+
+    pub fn bounding_box(&self) -> BoundingBox {
+        disk_bounds(&self.center, &self.normal, self.radius)
+    }
+
+    pub fn area(&self) -> f64 {
+        PI * self.radius * self.radius
+    }
+
+    pub fn sample_surface(&self, rng: &mut Rng) -> (Point, Vector3) {
+        let frame = self.frame();
+        let point = sample_cap(0., self.radius, rng);
+        (frame.world_point(&point), frame.axis)
+    }
+}
+
+impl Intersectable for Disk {
+    fn intersect(&self, ray: &Ray) -> Option<f64> {
+        let ray = self.frame().local_ray(ray);
+        if ray.direction.y.abs() < 1e-12 {
+            return None;
+        }
+        let t = -ray.origin.y / ray.direction.y;
+        let x = ray.origin.x + t * ray.direction.x;
+        let z = ray.origin.z + t * ray.direction.z;
+        if t > 0. && x * x + z * z <= self.radius * self.radius {
+            Some(t)
+        } else {
+            None
+        }
+    }
+
+    fn surface_normal(&self, _hit_point: &Point) -> Vector3 {
+        self.normal.normalize()
+    }
+
+    /// The disk fills the unit square.
+    fn texture_coords(&self, hit_point: &Point) -> TextureCoords {
+        cap_coords(&self.frame().local_point(hit_point), self.radius)
+    }
+}
+
+impl Torus {
+    fn frame(&self) -> AxisFrame {
+        AxisFrame::new(self.center, self.axis)
+    }
+
+    pub fn bounding_box(&self) -> BoundingBox {
+        let bounds = disk_bounds(&self.center, &self.axis, self.major_radius);
+        let r = Vector3 {
+            x: self.minor_radius,
+            y: self.minor_radius,
+            z: self.minor_radius,
+        };
+        BoundingBox {
+            min: (bounds.min.as_vector() - r).as_point(),
+            max: (bounds.max.as_vector() + r).as_point(),
+        }
+    }
+
+    pub fn area(&self) -> f64 {
+        4. * PI * PI * self.major_radius * self.minor_radius
+    }
+
+    pub fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        let ray = self.frame().local_ray(ray);
+        // Only look for hits within the sphere around the torus. Starting from where
+        // the ray enters it also keeps the numbers small, which the quartic needs.
+        let outer = self.major_radius + self.minor_radius;
+        let (enter, leave) = match solve_quadratic(
+            1.,
+            2. * ray.origin.as_vector().dot(&ray.direction),
+            ray.origin.as_vector().sq_length() - outer * outer,
+        ) {
+            Some(range) => range,
+            None => return vec![],
+        };
+        let o = (ray.origin.as_vector() + ray.direction * enter).as_point();
+        let d = ray.direction;
+
+        // (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + z^2), along p = o + t d with |d| = 1
+        let r2 = self.major_radius * self.major_radius;
+        let n = o.as_vector().dot(&d);
+        let q = o.as_vector().sq_length() + r2 - self.minor_radius * self.minor_radius;
+        let coefficients = [
+            q * q - 4. * r2 * (o.x * o.x + o.z * o.z),
+            4. * n * q - 8. * r2 * (o.x * d.x + o.z * d.z),
+            4. * n * n + 2. * q - 4. * r2 * (d.x * d.x + d.z * d.z),
+            4. * n,
+            1.,
+        ];
+        // The ray starts outside the torus, so it goes in and out at every other root
+        polynomial_roots(&coefficients, 0., leave - enter)
+            .chunks_exact(2)
+            .map(|pair| Interval {
+                enter: pair[0] + enter,
+                exit: pair[1] + enter,
+            })
+            .collect()
+    }
+
+    /// The point on the circle through the middle of the tube that is nearest to a
+    /// local point.
+    fn ring_point(&self, local: &Point) -> Vector3 {
+        let distance = local.x.hypot(local.z);
+        if distance <= 0. {
+            return Vector3::zero();
+        }
+        Vector3 {
+            x: local.x / distance * self.major_radius,
+            y: 0.,
+            z: local.z / distance * self.major_radius,
+        }
+    }
+
+    pub fn sample_surface(&self, rng: &mut Rng) -> (Point, Vector3) {
+        let (big, small) = (self.major_radius, self.minor_radius);
+        // The outside of the ring has more area than the inside, so points on the
+        // inside are rejected more often
+        loop {
+            let phi = 2. * PI * rng.next_f64();
+            let theta = 2. * PI * rng.next_f64();
+            if rng.next_f64() * (big + small) > big + small * theta.cos() {
+                continue;
+            }
+            let normal = Vector3 {
+                x: theta.cos() * phi.cos(),
+                y: theta.sin(),
+                z: theta.cos() * phi.sin(),
+            };
+            let point = (Vector3 {
+                x: big * phi.cos(),
+                y: 0.,
+                z: big * phi.sin(),
+            } + normal * small)
+                .as_point();
+            let frame = self.frame();
+            return (frame.world_point(&point), frame.world_vector(&normal));
+        }
+    }
+}
+
+impl Intersectable for Torus {
+    fn intersect(&self, ray: &Ray) -> Option<f64> {
+        first_boundary(&self.intervals(ray))
+    }
+
+    fn surface_normal(&self, hit_point: &Point) -> Vector3 {
+        let frame = self.frame();
+        let local = frame.local_point(hit_point);
+        let normal = (local.as_vector() - self.ring_point(&local)).normalize();
+        frame.world_vector(&normal)
+    }
+
+    /// `x` goes once around the ring, `y` once around the tube.
+    fn texture_coords(&self, hit_point: &Point) -> TextureCoords {
+        let local = self.frame().local_point(hit_point);
+        let across = local.x.hypot(local.z) - self.major_radius;
+        TextureCoords {
+            x: (0.5 + local.z.atan2(local.x) / (2. * PI)) as f32,
+            y: (0.5 + local.y.atan2(across) / (2. * PI)) as f32,
+        }
+    }
+}
+
+impl Instance {
+    /// The object's bounding box moved into the world, or `None` if the object is
+    /// unbounded.
+    pub fn bounding_box(&self) -> Option<BoundingBox> {
+        let bounds = self.object.bounding_box()?;
+        let corners: Vec<Point> = bounds
+            .corners()
+            .iter()
+            .map(|corner| self.transform.point(corner))
+            .collect();
+        Some(BoundingBox::from_points(&corners))
+    }
+
+    /// The ray in the object's space, and how much longer distances are there than
+    /// in the world.
+    fn object_ray(&self, ray: &Ray) -> (Ray, f64) {
+        let direction = self.transform.inverse_vector(&ray.direction);
+        let scale = direction.length();
+        let object_ray = Ray {
+            origin: self.transform.inverse_point(&ray.origin),
+            direction: direction * (1.0 / scale),
+        };
+        (object_ray, scale)
+    }
+
+    /// Like `intersect`, but also gives the face that was hit, if the object is a mesh.
+    pub fn intersect_face(&self, ray: &Ray) -> Option<(f64, Option<FaceHit>)> {
+        let (object_ray, scale) = self.object_ray(ray);
+        self.object
+            .intersect_face(&object_ray)
+            .map(|(distance, face)| (distance / scale, face))
+    }
+
+    fn hit_normal(&self, hit_point: &Point, face: FaceHit) -> Vector3 {
+        let object_point = self.transform.inverse_point(hit_point);
+        let normal = self.object.hit_normal(&object_point, Some(face));
+        self.transform.normal(&normal).normalize()
+    }
+
+    pub fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        let (object_ray, scale) = self.object_ray(ray);
+        self.object
+            .intervals(&object_ray)
+            .into_iter()
+            .map(|interval| Interval {
+                enter: interval.enter / scale,
+                exit: interval.exit / scale,
+            })
+            .collect()
+    }
+}
+
+impl Intersectable for Instance {
+    fn intersect(&self, ray: &Ray) -> Option<f64> {
+        let (object_ray, scale) = self.object_ray(ray);
+        self.object
+            .intersect(&object_ray)
+            .map(|distance| distance / scale)
+    }
+
+    fn surface_normal(&self, hit_point: &Point) -> Vector3 {
+        let object_point = self.transform.inverse_point(hit_point);
+        let normal = self.object.surface_normal(&object_point);
+        self.transform.normal(&normal).normalize()
+    }
+
+    fn texture_coords(&self, hit_point: &Point) -> TextureCoords {
+        self.object
+            .texture_coords(&self.transform.inverse_point(hit_point))
+    }
+}
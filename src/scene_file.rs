@@ -0,0 +1,1735 @@
+//! Loading scenes from JSON files.
+//!
+//! A scene file describes the scene settings, lights and elements. Textures and OBJ
+//! meshes are referenced by paths relative to the scene file. See `scenes/` for examples.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use image::{DynamicImage, ImageReader};
+use serde::Deserialize;
+
+use crate::background::{Background, EnvironmentMap};
+use crate::camera::Camera;
+use crate::color::{linear_rgb, Color, ToneMap};
+use crate::csg::{Csg, CsgOperation};
+use crate::heightfield::Heightfield;
+use crate::ies::{self, IesError};
+use crate::math::{Matrix4, Point, Transform, Vector3};
+use crate::obj::{self, ObjError};
+use crate::sampling::{Filter, SamplePattern};
+use crate::scene::{
+    Coloration, Cone, Cuboid, Cylinder, DirectionalLight, Disk, DiskLight, Element, Emission,
+    Instance, Integrator, Light, Material, Plane, RectangleLight, Scalar, Scene, Specular, Sphere,
+    SphereLight, SphericalLight, SpotLight, SurfaceType, Torus, Triangle,
+};
+use crate::sdf::{DistanceField, Sdf};
+use crate::sky::{self, Sky};
+
+#[derive(Debug)]
+pub enum SceneFileError {
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    Json {
+        path: PathBuf,
+        error: serde_json::Error,
+    },
+    Texture {
+        path: PathBuf,
+        error: image::ImageError,
+    },
+    Obj(ObjError),
+    Ies(IesError),
+    /// An instance of an object that is not in `objects`
+    UnknownObject(String),
+    /// An object in `objects` that is an instance itself
+    NestedInstance(String),
+    /// An instance of the named object with a transform that can not be inverted
+    SingularTransform(String),
+    /// A CSG element without children
+    EmptyCsg,
+    /// A CSG child, of the given type, that does not enclose a solid
+    NotSolid(&'static str),
+    /// A smooth union of signed distance functions without shapes
+    EmptySmoothUnion,
+    /// A signed distance function scaled by a factor that is not positive
+    NonPositiveScale(f64),
+    /// A Mandelbulb with a power below 2
+    MandelbulbPower(f64),
+    /// A heightmap image less than two pixels wide or high
+    HeightmapTooSmall(PathBuf),
+    /// An element, light or camera of the given type, whose `field` leaves it without
+    /// size or direction
+    Degenerate {
+        kind: &'static str,
+        field: &'static str,
+    },
+    /// An environment image without pixels
+    EmptyEnvironment(PathBuf),
+}
+
+impl fmt::Display for SceneFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneFileError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            SceneFileError::Json { path, error } => write!(f, "{}: {}", path.display(), error),
+            SceneFileError::Texture { path, error } => {
+                write!(f, "{}: could not load texture: {}", path.display(), error)
+            }
+            SceneFileError::Obj(error) => write!(f, "{}", error),
+            SceneFileError::Ies(error) => write!(f, "{}", error),
+            SceneFileError::UnknownObject(name) => write!(f, "unknown object '{}'", name),
+            SceneFileError::NestedInstance(name) => {
+                write!(f, "object '{}' can not be an instance", name)
+            }
+            SceneFileError::SingularTransform(name) => {
+                write!(
+                    f,
+                    "instance of '{}' has a transform that squashes it flat",
+                    name
+                )
+            }
+            SceneFileError::EmptyCsg => write!(f, "csg element has no children"),
+            SceneFileError::NotSolid(kind) => {
+                write!(
+                    f,
+                    "{} is not a solid and can not be part of a csg element",
+                    kind
+                )
+            }
+            SceneFileError::EmptySmoothUnion => write!(f, "smooth_union has no shapes"),
+            SceneFileError::NonPositiveScale(factor) => {
+                write!(f, "scale factor must be positive, not {}", factor)
+            }
+            SceneFileError::MandelbulbPower(power) => {
+                write!(f, "mandelbulb power must be at least 2, not {}", power)
+            }
+            SceneFileError::HeightmapTooSmall(path) => write!(
+                f,
+                "{}: a heightmap needs at least 2 by 2 pixels",
+                path.display()
+            ),
+            SceneFileError::Degenerate { kind, field } => {
+                write!(f, "{} has a degenerate `{}`", kind, field)
+            }
+            SceneFileError::EmptyEnvironment(path) => {
+                write!(f, "{}: environment image is empty", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for SceneFileError {}
+
+fn default_shadow_bias() -> f64 {
+    1e-6
+}
+
+fn default_max_recursion_depth() -> u32 {
+    10
+}
+
+fn default_samples_per_pixel() -> u32 {
+    1
+}
+
+fn default_sample_pattern() -> SamplePattern {
+    SamplePattern::Jittered
+}
+
+/// Shapes stand upright unless told otherwise.
+fn default_axis() -> [f64; 3] {
+    [0., 1., 0.]
+}
+
+fn default_mandelbulb_power() -> f64 {
+    8.
+}
+
+fn default_mandelbulb_iterations() -> u32 {
+    10
+}
+
+fn default_filter() -> Filter {
+    Filter::Box
+}
+
+fn default_integrator() -> Integrator {
+    Integrator::Whitted
+}
+
+fn default_light_samples() -> u32 {
+    16
+}
+
+fn default_background() -> BackgroundDescription {
+    BackgroundDescription::Color { color: [0.0; 3] }
+}
+
+fn default_background_strength() -> f32 {
+    1.0
+}
+
+fn default_turbidity() -> f32 {
+    3.0
+}
+
+fn default_ground_albedo() -> [f32; 3] {
+    [0.3, 0.3, 0.3]
+}
+
+fn default_emission_strength() -> f32 {
+    1.0
+}
+
+fn default_albedo() -> f32 {
+    0.18
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SceneDescription {
+    pub width: u32,
+    pub height: u32,
+    #[serde(default)]
+    pub camera: CameraDescription,
+    #[serde(default = "default_shadow_bias")]
+    pub shadow_bias: f64,
+    #[serde(default = "default_max_recursion_depth")]
+    pub max_recursion_depth: u32,
+    #[serde(default = "default_samples_per_pixel")]
+    pub samples_per_pixel: u32,
+    #[serde(default = "default_sample_pattern")]
+    pub sample_pattern: SamplePattern,
+    #[serde(default = "default_filter")]
+    pub filter: Filter,
+    #[serde(default = "default_integrator")]
+    pub integrator: Integrator,
+    #[serde(default = "default_background")]
+    pub background: BackgroundDescription,
+    #[serde(default = "default_light_samples")]
+    pub background_samples: u32,
+    #[serde(default)]
+    pub exposure: f32,
+    #[serde(default)]
+    pub tone_map: ToneMap,
+    #[serde(default)]
+    pub dither: bool,
+    #[serde(default)]
+    pub lights: Vec<LightDescription>,
+    /// Named elements that are only drawn through instances
+    #[serde(default)]
+    pub objects: HashMap<String, ElementDescription>,
+    #[serde(default)]
+    pub elements: Vec<ElementDescription>,
+}
+
+/// What rays that miss every element see. Defaults to black.
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum BackgroundDescription {
+    Color {
+        color: [f32; 3],
+    },
+    Gradient {
+        bottom: [f32; 3],
+        top: [f32; 3],
+    },
+    /// An equirectangular image, preferably high dynamic range (`.hdr` or `.exr`)
+    Environment {
+        path: PathBuf,
+        #[serde(default = "default_background_strength")]
+        strength: f32,
+    },
+    /// A daylight sky. Adds a directional light for the sun, shining along
+    /// `sun_direction` with `sun_intensity` before passing through the atmosphere.
+    Sky {
+        sun_direction: [f64; 3],
+        sun_intensity: f32,
+        #[serde(default = "default_turbidity")]
+        turbidity: f32,
+        #[serde(default = "default_ground_albedo")]
+        ground_albedo: [f32; 3],
+    },
+}
+
+/// Defaults to a camera at the origin looking down the negative z axis.
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct CameraDescription {
+    pub position: [f64; 3],
+    pub look_at: [f64; 3],
+    pub up: [f64; 3],
+    pub fov: f64,
+    pub aspect: Option<f64>,
+}
+
+impl CameraDescription {
+    /// The field that leaves the camera without a view direction or a way up, if any.
+    fn degenerate_field(&self) -> Option<&'static str> {
+        let view = Point::from_list(self.look_at) - Point::from_list(self.position);
+        if view.length() == 0. {
+            Some("look_at")
+        } else if vector(self.up).cross(&view).length() == 0. {
+            Some("up")
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for CameraDescription {
+    fn default() -> Self {
+        CameraDescription {
+            position: [0., 0., 0.],
+            look_at: [0., 0., -1.],
+            up: [0., 1., 0.],
+            fov: 90.,
+            aspect: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum LightDescription {
+    Directional {
+        direction: [f64; 3],
+        color: [f32; 3],
+        intensity: f32,
+    },
+    Spherical {
+        position: [f64; 3],
+        color: [f32; 3],
+        intensity: f32,
+    },
+    Spot {
+        position: [f64; 3],
+        direction: [f64; 3],
+        #[serde(default)]
+        inner_angle: f64,
+        outer_angle: f64,
+        color: [f32; 3],
+        intensity: f32,
+        /// Path to an IES profile, used instead of the cone
+        ies: Option<PathBuf>,
+    },
+    Rectangle {
+        corner: [f64; 3],
+        edge1: [f64; 3],
+        edge2: [f64; 3],
+        color: [f32; 3],
+        intensity: f32,
+        #[serde(default = "default_light_samples")]
+        samples: u32,
+    },
+    Disk {
+        center: [f64; 3],
+        normal: [f64; 3],
+        radius: f64,
+        color: [f32; 3],
+        intensity: f32,
+        #[serde(default = "default_light_samples")]
+        samples: u32,
+    },
+    Sphere {
+        center: [f64; 3],
+        radius: f64,
+        color: [f32; 3],
+        intensity: f32,
+        #[serde(default = "default_light_samples")]
+        samples: u32,
+    },
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ElementDescription {
+    Sphere {
+        center: [f64; 3],
+        radius: f64,
+        material: MaterialDescription,
+    },
+    Plane {
+        p0: [f64; 3],
+        normal: [f64; 3],
+        material: MaterialDescription,
+    },
+    Triangle {
+        vertices: [[f64; 3]; 3],
+        material: MaterialDescription,
+    },
+    /// An axis-aligned box between two opposite corners
+    Box {
+        min: [f64; 3],
+        max: [f64; 3],
+        material: MaterialDescription,
+    },
+    /// A capped cylinder standing on `base`
+    Cylinder {
+        base: [f64; 3],
+        #[serde(default = "default_axis")]
+        axis: [f64; 3],
+        radius: f64,
+        height: f64,
+        material: MaterialDescription,
+    },
+    /// A cone standing on its round `base`, with its tip `height` along `axis`
+    Cone {
+        base: [f64; 3],
+        #[serde(default = "default_axis")]
+        axis: [f64; 3],
+        radius: f64,
+        height: f64,
+        material: MaterialDescription,
+    },
+    Disk {
+        center: [f64; 3],
+        normal: [f64; 3],
+        radius: f64,
+        material: MaterialDescription,
+    },
+    /// A ring around `axis`, whose tube of `minor_radius` runs in a circle of
+    /// `major_radius`
+    Torus {
+        center: [f64; 3],
+        #[serde(default = "default_axis")]
+        axis: [f64; 3],
+        major_radius: f64,
+        minor_radius: f64,
+        material: MaterialDescription,
+    },
+    /// All meshes of an OBJ file, with the materials from its MTL files.
+    Obj { path: PathBuf },
+    /// Solids combined in order: the union or intersection of all children, or the
+    /// first child with all the others cut away. Made of the first child's material.
+    Csg {
+        operation: CsgOperation,
+        children: Vec<ElementDescription>,
+    },
+    /// A copy of one of the scene's `objects`, placed by transforms applied in order.
+    Instance {
+        object: String,
+        #[serde(default)]
+        transform: Vec<TransformDescription>,
+    },
+    /// A shape given by a signed distance function
+    Sdf {
+        shape: SdfDescription,
+        material: MaterialDescription,
+    },
+    /// Terrain from a grayscale heightmap image, over the rectangle reaching `size`
+    /// along x and z from `min`, and raised by up to `size` along y where it is white
+    Heightfield {
+        path: PathBuf,
+        min: [f64; 3],
+        size: [f64; 3],
+        material: MaterialDescription,
+    },
+}
+
+/// A signed distance function, see `Sdf`. Angles are in degrees.
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum SdfDescription {
+    Sphere {
+        radius: f64,
+    },
+    /// A box centered on the origin, with edges rounded off by `rounding` within `size`
+    Box {
+        size: [f64; 3],
+        #[serde(default)]
+        rounding: f64,
+    },
+    Torus {
+        major_radius: f64,
+        minor_radius: f64,
+    },
+    Mandelbulb {
+        #[serde(default = "default_mandelbulb_power")]
+        power: f64,
+        #[serde(default = "default_mandelbulb_iterations")]
+        iterations: u32,
+    },
+    Translate {
+        offset: [f64; 3],
+        shape: Box<SdfDescription>,
+    },
+    Scale {
+        factor: f64,
+        shape: Box<SdfDescription>,
+    },
+    /// All shapes melted together where they are closer than `smoothness`
+    SmoothUnion {
+        shapes: Vec<SdfDescription>,
+        #[serde(default)]
+        smoothness: f64,
+    },
+    /// Twists the shape around the y axis, by `angle` degrees per unit of height
+    Twist {
+        angle: f64,
+        shape: Box<SdfDescription>,
+    },
+    /// Repeats the shape every `period` along each axis, or not along axes with 0
+    Repeat {
+        period: [f64; 3],
+        shape: Box<SdfDescription>,
+    },
+}
+
+/// One step of an instance's transform. Angles are in degrees.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum TransformDescription {
+    Translate([f64; 3]),
+    Scale([f64; 3]),
+    RotateX(f64),
+    RotateY(f64),
+    RotateZ(f64),
+    /// A row-major matrix
+    Matrix([[f64; 4]; 4]),
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct MaterialDescription {
+    pub color: ColorationDescription,
+    #[serde(default = "default_albedo")]
+    pub albedo: f32,
+    #[serde(default)]
+    pub surface: SurfaceDescription,
+    /// Makes the element glow
+    pub emission: Option<EmissionDescription>,
+    /// Adds highlights
+    pub specular: Option<SpecularDescription>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SpecularDescription {
+    pub color: [f32; 3],
+    pub shininess: f32,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct EmissionDescription {
+    pub color: [f32; 3],
+    #[serde(default = "default_emission_strength")]
+    pub strength: f32,
+}
+
+/// Either `[r, g, b]` or `{ "texture": "path/to/image.png" }`.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum ColorationDescription {
+    Color([f32; 3]),
+    Texture { texture: PathBuf },
+}
+
+/// Either a number or `{ "texture": "path/to/image.png" }`, read from a grayscale image.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum ScalarDescription {
+    Value(f32),
+    Texture { texture: PathBuf },
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum SurfaceDescription {
+    #[default]
+    Diffuse,
+    Reflective {
+        reflectivity: f32,
+    },
+    Refractive {
+        index: f32,
+        transparency: f32,
+    },
+    /// The base color is the material color.
+    Microfacet {
+        #[serde(default = "default_metallic")]
+        metallic: ScalarDescription,
+        roughness: ScalarDescription,
+    },
+}
+
+fn default_metallic() -> ScalarDescription {
+    ScalarDescription::Value(0.0)
+}
+
+fn color(c: [f32; 3]) -> Color {
+    Color {
+        red: c[0],
+        green: c[1],
+        blue: c[2],
+    }
+}
+
+fn vector(v: [f64; 3]) -> Vector3 {
+    Vector3 {
+        x: v[0],
+        y: v[1],
+        z: v[2],
+    }
+}
+
+fn is_zero(v: &[f64; 3]) -> bool {
+    v.iter().all(|x| *x == 0.)
+}
+
+/// Load a scene from a JSON file.
+pub fn load_scene<P: AsRef<Path>>(path: P) -> Result<Scene, SceneFileError> {
+    let path = path.as_ref();
+    let json = fs::read_to_string(path).map_err(|error| SceneFileError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+    parse_scene(&json, path)
+}
+
+/// Parse a scene from JSON. `path` is used for error messages and to resolve the
+/// paths of textures and meshes.
+pub fn parse_scene(json: &str, path: &Path) -> Result<Scene, SceneFileError> {
+    let description: SceneDescription =
+        serde_json::from_str(json).map_err(|error| SceneFileError::Json {
+            path: path.to_path_buf(),
+            error,
+        })?;
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    description.into_scene(base_dir)
+}
+
+impl SceneDescription {
+    /// Build the scene, resolving relative paths against `base_dir`.
+    pub fn into_scene(self, base_dir: &Path) -> Result<Scene, SceneFileError> {
+        if let Some(field) = self.camera.degenerate_field() {
+            return Err(SceneFileError::Degenerate {
+                kind: "camera",
+                field,
+            });
+        }
+        let mut scene = Scene::new(
+            self.width,
+            self.height,
+            self.camera.fov,
+            self.shadow_bias,
+            self.max_recursion_depth,
+        );
+        scene.camera = Camera {
+            position: Point::from_list(self.camera.position),
+            look_at: Point::from_list(self.camera.look_at),
+            up: vector(self.camera.up),
+            fov: self.camera.fov,
+            aspect: self.camera.aspect,
+        };
+        scene.samples_per_pixel = self.samples_per_pixel;
+        scene.sample_pattern = self.sample_pattern;
+        scene.filter = self.filter;
+        scene.integrator = self.integrator;
+        let (background, sun) = self.background.into_background(base_dir)?;
+        scene.background = background;
+        if let Some(sun) = sun {
+            scene.add_light(Light::Directional(sun));
+        }
+        scene.background_samples = self.background_samples;
+        scene.exposure = self.exposure;
+        scene.tone_map = self.tone_map;
+        scene.dither = self.dither;
+
+        for light in self.lights {
+            scene.add_light(light.into_light(base_dir)?);
+        }
+
+        let mut objects = HashMap::new();
+        for (name, object) in self.objects {
+            if let ElementDescription::Instance { .. } = object {
+                return Err(SceneFileError::NestedInstance(name));
+            }
+            let elements = object.into_elements(base_dir, &HashMap::new())?;
+            objects.insert(name, elements.into_iter().map(Arc::new).collect());
+        }
+
+        for element in self.elements {
+            for element in element.into_elements(base_dir, &objects)? {
+                scene.add_element(element);
+            }
+        }
+
+        Ok(scene)
+    }
+}
+
+impl ElementDescription {
+    /// The elements described, usually just one. `objects` are the shared elements
+    /// that instances refer to by name.
+    fn into_elements(
+        self,
+        base_dir: &Path,
+        objects: &HashMap<String, Vec<Arc<Element>>>,
+    ) -> Result<Vec<Element>, SceneFileError> {
+        if let Some(field) = self.degenerate_field() {
+            return Err(SceneFileError::Degenerate {
+                kind: self.kind(),
+                field,
+            });
+        }
+        let element = match self {
+            ElementDescription::Sphere {
+                center,
+                radius,
+                material,
+            } => Element::Sphere(Sphere {
+                center: Point::from_list(center),
+                radius,
+                material: material.into_material(base_dir)?,
+            }),
+            ElementDescription::Plane {
+                p0,
+                normal,
+                material,
+            } => Element::Plane(Plane {
+                p0: Point::from_list(p0),
+                normal: vector(normal).normalize(),
+                material: material.into_material(base_dir)?,
+            }),
+            ElementDescription::Triangle { vertices, material } => Element::Triangle(Triangle {
+                v0: Point::from_list(vertices[0]),
+                v1: Point::from_list(vertices[1]),
+                v2: Point::from_list(vertices[2]),
+                material: material.into_material(base_dir)?,
+            }),
+            ElementDescription::Box { min, max, material } => Element::Cuboid(Cuboid {
+                min: Point::from_list(min),
+                max: Point::from_list(max),
+                material: material.into_material(base_dir)?,
+            }),
+            ElementDescription::Cylinder {
+                base,
+                axis,
+                radius,
+                height,
+                material,
+            } => Element::Cylinder(Cylinder {
+                base: Point::from_list(base),
+                axis: vector(axis).normalize(),
+                radius,
+                height,
+                material: material.into_material(base_dir)?,
+            }),
+            ElementDescription::Cone {
+                base,
+                axis,
+                radius,
+                height,
+                material,
+            } => Element::Cone(Cone {
+                base: Point::from_list(base),
+                axis: vector(axis).normalize(),
+                radius,
+                height,
+                material: material.into_material(base_dir)?,
+            }),
+            ElementDescription::Disk {
+                center,
+                normal,
+                radius,
+                material,
+            } => Element::Disk(Disk {
+                center: Point::from_list(center),
+                normal: vector(normal).normalize(),
+                radius,
+                material: material.into_material(base_dir)?,
+            }),
+            ElementDescription::Torus {
+                center,
+                axis,
+                major_radius,
+                minor_radius,
+                material,
+            } => Element::Torus(Torus {
+                center: Point::from_list(center),
+                axis: vector(axis).normalize(),
+                major_radius,
+                minor_radius,
+                material: material.into_material(base_dir)?,
+            }),
+            ElementDescription::Obj { path } => {
+                return obj::read_obj(base_dir.join(path)).map_err(SceneFileError::Obj);
+            }
+            ElementDescription::Csg {
+                operation,
+                children,
+            } => {
+                let mut solids = vec![];
+                for child in children {
+                    let kind = child.kind();
+                    let elements = child.into_elements(base_dir, objects)?;
+                    if elements.iter().any(|element| !element.is_solid()) {
+                        return Err(SceneFileError::NotSolid(kind));
+                    }
+                    // A child made of several elements, like an instance of an OBJ
+                    // file, is their union
+                    solids.extend(
+                        elements
+                            .into_iter()
+                            .reduce(|left, right| combine(CsgOperation::Union, left, right)),
+                    );
+                }
+                solids
+                    .into_iter()
+                    .reduce(|left, right| combine(operation, left, right))
+                    .ok_or(SceneFileError::EmptyCsg)?
+            }
+            ElementDescription::Instance { object, transform } => {
+                let elements = objects
+                    .get(&object)
+                    .ok_or_else(|| SceneFileError::UnknownObject(object.clone()))?;
+                let matrix = transform
+                    .iter()
+                    .fold(Matrix4::identity(), |matrix, step| step.matrix() * matrix);
+                let transform =
+                    Transform::new(matrix).ok_or(SceneFileError::SingularTransform(object))?;
+                return Ok(elements
+                    .iter()
+                    .map(|element| {
+                        Element::Instance(Instance {
+                            object: Arc::clone(element),
+                            transform,
+                        })
+                    })
+                    .collect());
+            }
+            ElementDescription::Sdf { shape, material } => Element::DistanceField(DistanceField {
+                sdf: shape.into_sdf()?,
+                material: material.into_material(base_dir)?,
+            }),
+            ElementDescription::Heightfield {
+                path,
+                min,
+                size,
+                material,
+            } => {
+                let path = base_dir.join(path);
+                let heightfield = Heightfield::from_image(
+                    &load_texture(&path)?,
+                    Point::from_list(min),
+                    vector(size),
+                    material.into_material(base_dir)?,
+                )
+                .ok_or(SceneFileError::HeightmapTooSmall(path))?;
+                Element::Heightfield(heightfield)
+            }
+        };
+        Ok(vec![element])
+    }
+
+    /// The field that leaves the element without size or direction, which its
+    /// intersection tests and normals divide by, if any.
+    fn degenerate_field(&self) -> Option<&'static str> {
+        match self {
+            ElementDescription::Sphere { radius, .. } if *radius <= 0. => Some("radius"),
+            ElementDescription::Plane { normal, .. } if is_zero(normal) => Some("normal"),
+            ElementDescription::Box { min, max, .. } if (0..3).any(|i| min[i] >= max[i]) => {
+                Some("max")
+            }
+            ElementDescription::Cylinder {
+                axis,
+                radius,
+                height,
+                ..
+            }
+            | ElementDescription::Cone {
+                axis,
+                radius,
+                height,
+                ..
+            } => {
+                if *radius <= 0. {
+                    Some("radius")
+                } else if *height <= 0. {
+                    Some("height")
+                } else if is_zero(axis) {
+                    Some("axis")
+                } else {
+                    None
+                }
+            }
+            ElementDescription::Disk { normal, radius, .. } => {
+                if *radius <= 0. {
+                    Some("radius")
+                } else if is_zero(normal) {
+                    Some("normal")
+                } else {
+                    None
+                }
+            }
+            ElementDescription::Torus {
+                axis,
+                major_radius,
+                minor_radius,
+                ..
+            } => {
+                if *major_radius <= 0. {
+                    Some("major_radius")
+                } else if *minor_radius <= 0. {
+                    Some("minor_radius")
+                } else if is_zero(axis) {
+                    Some("axis")
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// The element's `type` in a scene file, for error messages.
+    fn kind(&self) -> &'static str {
+        match self {
+            ElementDescription::Sphere { .. } => "sphere",
+            ElementDescription::Plane { .. } => "plane",
+            ElementDescription::Triangle { .. } => "triangle",
+            ElementDescription::Box { .. } => "box",
+            ElementDescription::Cylinder { .. } => "cylinder",
+            ElementDescription::Cone { .. } => "cone",
+            ElementDescription::Disk { .. } => "disk",
+            ElementDescription::Torus { .. } => "torus",
+            ElementDescription::Obj { .. } => "obj",
+            ElementDescription::Csg { .. } => "csg",
+            ElementDescription::Instance { .. } => "instance",
+            ElementDescription::Sdf { .. } => "sdf",
+            ElementDescription::Heightfield { .. } => "heightfield",
+        }
+    }
+}
+
+fn combine(operation: CsgOperation, left: Element, right: Element) -> Element {
+    Element::Csg(Csg {
+        operation,
+        left: Box::new(left),
+        right: Box::new(right),
+    })
+}
+
+impl SdfDescription {
+    fn into_sdf(self) -> Result<Sdf, SceneFileError> {
+        let boxed = |shape: Box<SdfDescription>| shape.into_sdf().map(Box::new);
+        Ok(match self {
+            SdfDescription::Sphere { radius } => Sdf::Sphere { radius },
+            SdfDescription::Box { size, rounding } => Sdf::Box {
+                half_size: vector(size) * 0.5,
+                rounding,
+            },
+            SdfDescription::Torus {
+                major_radius,
+                minor_radius,
+            } => Sdf::Torus {
+                major_radius,
+                minor_radius,
+            },
+            SdfDescription::Mandelbulb { power, iterations } => {
+                if power < 2. {
+                    return Err(SceneFileError::MandelbulbPower(power));
+                }
+                Sdf::Mandelbulb { power, iterations }
+            }
+            SdfDescription::Translate { offset, shape } => Sdf::Translate {
+                offset: vector(offset),
+                shape: boxed(shape)?,
+            },
+            SdfDescription::Scale { factor, shape } => {
+                if factor <= 0. {
+                    return Err(SceneFileError::NonPositiveScale(factor));
+                }
+                Sdf::Scale {
+                    factor,
+                    shape: boxed(shape)?,
+                }
+            }
+            SdfDescription::SmoothUnion { shapes, smoothness } => {
+                let mut sdfs = vec![];
+                for shape in shapes {
+                    sdfs.push(shape.into_sdf()?);
+                }
+                sdfs.into_iter()
+                    .reduce(|left, right| Sdf::SmoothUnion {
+                        left: Box::new(left),
+                        right: Box::new(right),
+                        smoothness,
+                    })
+                    .ok_or(SceneFileError::EmptySmoothUnion)?
+            }
+            SdfDescription::Twist { angle, shape } => Sdf::Twist {
+                amount: angle.to_radians(),
+                shape: boxed(shape)?,
+            },
+            SdfDescription::Repeat { period, shape } => Sdf::Repeat {
+                period: vector(period),
+                shape: boxed(shape)?,
+            },
+        })
+    }
+}
+
+impl TransformDescription {
+    fn matrix(&self) -> Matrix4 {
+        match *self {
+            TransformDescription::Translate(offset) => Matrix4::translation(vector(offset)),
+            TransformDescription::Scale(factors) => Matrix4::scaling(vector(factors)),
+            TransformDescription::RotateX(angle) => Matrix4::rotation_x(angle.to_radians()),
+            TransformDescription::RotateY(angle) => Matrix4::rotation_y(angle.to_radians()),
+            TransformDescription::RotateZ(angle) => Matrix4::rotation_z(angle.to_radians()),
+            TransformDescription::Matrix(m) => Matrix4 { m },
+        }
+    }
+}
+
+impl LightDescription {
+    fn into_light(self, base_dir: &Path) -> Result<Light, SceneFileError> {
+        if let Some(field) = self.degenerate_field() {
+            return Err(SceneFileError::Degenerate {
+                kind: self.kind(),
+                field,
+            });
+        }
+        let light = match self {
+            LightDescription::Directional {
+                direction,
+                color: c,
+                intensity,
+            } => Light::Directional(DirectionalLight {
+                direction: vector(direction),
+                color: color(c),
+                intensity,
+            }),
+            LightDescription::Spherical {
+                position,
+                color: c,
+                intensity,
+            } => Light::Spherical(SphericalLight {
+                position: Point::from_list(position),
+                color: color(c),
+                intensity,
+            }),
+            LightDescription::Spot {
+                position,
+                direction,
+                inner_angle,
+                outer_angle,
+                color: c,
+                intensity,
+                ies,
+            } => {
+                let profile = match ies {
+                    Some(path) => {
+                        Some(ies::load_ies(base_dir.join(path)).map_err(SceneFileError::Ies)?)
+                    }
+                    None => None,
+                };
+                Light::Spot(SpotLight {
+                    position: Point::from_list(position),
+                    direction: vector(direction),
+                    inner_angle,
+                    outer_angle,
+                    color: color(c),
+                    intensity,
+                    profile,
+                })
+            }
+            LightDescription::Rectangle {
+                corner,
+                edge1,
+                edge2,
+                color: c,
+                intensity,
+                samples,
+            } => Light::Rectangle(RectangleLight {
+                corner: Point::from_list(corner),
+                edge1: vector(edge1),
+                edge2: vector(edge2),
+                color: color(c),
+                intensity,
+                samples,
+            }),
+            LightDescription::Disk {
+                center,
+                normal,
+                radius,
+                color: c,
+                intensity,
+                samples,
+            } => Light::Disk(DiskLight {
+                center: Point::from_list(center),
+                normal: vector(normal).normalize(),
+                radius,
+                color: color(c),
+                intensity,
+                samples,
+            }),
+            LightDescription::Sphere {
+                center,
+                radius,
+                color: c,
+                intensity,
+                samples,
+            } => Light::Sphere(SphereLight {
+                center: Point::from_list(center),
+                radius,
+                color: color(c),
+                intensity,
+                samples,
+            }),
+        };
+        Ok(light)
+    }
+
+    /// The field that leaves the light without size or direction, if any.
+    fn degenerate_field(&self) -> Option<&'static str> {
+        match self {
+            LightDescription::Directional { direction, .. }
+            | LightDescription::Spot { direction, .. }
+                if is_zero(direction) =>
+            {
+                Some("direction")
+            }
+            LightDescription::Rectangle { edge1, edge2, .. } => {
+                if is_zero(edge1) {
+                    Some("edge1")
+                } else if vector(*edge1).cross(&vector(*edge2)).length() == 0. {
+                    Some("edge2")
+                } else {
+                    None
+                }
+            }
+            LightDescription::Disk { normal, radius, .. } => {
+                if *radius <= 0. {
+                    Some("radius")
+                } else if is_zero(normal) {
+                    Some("normal")
+                } else {
+                    None
+                }
+            }
+            LightDescription::Sphere { radius, .. } if *radius <= 0. => Some("radius"),
+            _ => None,
+        }
+    }
+
+    /// The light's `type` in a scene file, for error messages.
+    fn kind(&self) -> &'static str {
+        match self {
+            LightDescription::Directional { .. } => "directional light",
+            LightDescription::Spherical { .. } => "spherical light",
+            LightDescription::Spot { .. } => "spot light",
+            LightDescription::Rectangle { .. } => "rectangle light",
+            LightDescription::Disk { .. } => "disk light",
+            LightDescription::Sphere { .. } => "sphere light",
+        }
+    }
+}
+
+impl BackgroundDescription {
+    /// The background, and the sun if it is a sky.
+    fn into_background(
+        self,
+        base_dir: &Path,
+    ) -> Result<(Background, Option<DirectionalLight>), SceneFileError> {
+        let background = match self {
+            BackgroundDescription::Color { color: c } => (Background::Color(color(c)), None),
+            BackgroundDescription::Gradient { bottom, top } => (
+                Background::Gradient {
+                    bottom: color(bottom),
+                    top: color(top),
+                },
+                None,
+            ),
+            BackgroundDescription::Environment { path, strength } => {
+                let path = base_dir.join(path);
+                let map = EnvironmentMap::new(&load_texture(&path)?, strength)
+                    .ok_or(SceneFileError::EmptyEnvironment(path))?;
+                (Background::Environment(map), None)
+            }
+            BackgroundDescription::Sky {
+                sun_direction,
+                sun_intensity,
+                turbidity,
+                ground_albedo,
+            } => {
+                let sun = sky::sun_light(vector(sun_direction), sun_intensity, turbidity);
+                let sky = Sky::new(&sun, turbidity, color(ground_albedo));
+                (Background::Sky(sky), Some(sun))
+            }
+        };
+        Ok(background)
+    }
+}
+
+impl MaterialDescription {
+    fn into_material(self, base_dir: &Path) -> Result<Material, SceneFileError> {
+        let coloration = match self.color {
+            ColorationDescription::Color(c) => Coloration::Color(color(c)),
+            ColorationDescription::Texture { texture } => {
+                Coloration::Texture(linear_rgb(&load_texture(&base_dir.join(texture))?))
+            }
+        };
+        let scalar = |description: ScalarDescription| match description {
+            ScalarDescription::Value(value) => Ok(Scalar::Value(value)),
+            ScalarDescription::Texture { texture } => {
+                load_texture(&base_dir.join(texture)).map(Scalar::Texture)
+            }
+        };
+
+        let surface_type = match self.surface {
+            SurfaceDescription::Diffuse => SurfaceType::Diffuse,
+            SurfaceDescription::Reflective { reflectivity } => {
+                SurfaceType::Reflective { reflectivity }
+            }
+            SurfaceDescription::Refractive {
+                index,
+                transparency,
+            } => SurfaceType::Refractive {
+                index,
+                transparency,
+            },
+            SurfaceDescription::Microfacet {
+                metallic,
+                roughness,
+            } => SurfaceType::Microfacet {
+                metallic: scalar(metallic)?,
+                roughness: scalar(roughness)?,
+            },
+        };
+
+        Ok(Material {
+            color: coloration,
+            albedo: self.albedo,
+            emission: self.emission.map(|emission| Emission {
+                color: color(emission.color),
+                strength: emission.strength,
+            }),
+            specular: self.specular.map(|specular| Specular {
+                color: color(specular.color),
+                shininess: specular.shininess,
+            }),
+            surface_type,
+        })
+    }
+}
+
+fn load_texture(path: &Path) -> Result<DynamicImage, SceneFileError> {
+    ImageReader::open(path)
+        .map_err(|error| SceneFileError::Io {
+            path: path.to_path_buf(),
+            error,
+        })?
+        .decode()
+        .map_err(|error| SceneFileError::Texture {
+            path: path.to_path_buf(),
+            error,
+        })
+}
+
+#[test]
+fn test_parse_scene() {
+    let json = r#"{
+        "width": 80,
+        "height": 60,
+        "camera": { "position": [0, 1, 2], "look_at": [0, 0, -4], "fov": 60 },
+        "samples_per_pixel": 16,
+        "filter": "mitchell",
+        "integrator": "path_tracing",
+        "lights": [
+            { "type": "directional", "direction": [0, -1, -1], "color": [1, 1, 1], "intensity": 20 },
+            { "type": "disk", "center": [0, 5, -4], "normal": [0, -1, 0], "radius": 0.5, "color": [1, 1, 1], "intensity": 500 }
+        ],
+        "elements": [
+            {
+                "type": "sphere",
+                "center": [0, 0, -4],
+                "radius": 1,
+                "material": {
+                    "color": [0.2, 1, 0.2],
+                    "surface": { "type": "reflective", "reflectivity": 0.2 }
+                }
+            },
+            {
+                "type": "plane",
+                "p0": [0, -2, 0],
+                "normal": [0, -1, 0],
+                "material": { "color": [1, 1, 1], "albedo": 0.5 }
+            }
+        ]
+    }"#;
+
+    let scene = parse_scene(json, Path::new("scene.json")).unwrap();
+    assert_eq!(scene.width, 80);
+    assert!((scene.camera.fov - 60.).abs() < 0.0001);
+    assert_eq!(scene.filter, Filter::Mitchell);
+    assert_eq!(scene.sample_pattern, SamplePattern::Jittered);
+    assert_eq!(scene.integrator, Integrator::PathTracing);
+    assert!((scene.camera.position.y - 1.).abs() < 0.0001);
+    assert_eq!(scene.max_recursion_depth, default_max_recursion_depth());
+    assert_eq!(scene.lights.len(), 2);
+    match &scene.lights[1] {
+        Light::Disk(disk) => assert_eq!(disk.samples, default_light_samples()),
+        _ => panic!("expected a disk light"),
+    }
+    assert_eq!(scene.elements().len(), 2);
+    assert!((scene.elements()[1].albedo() - 0.5).abs() < 0.0001);
+    match scene.elements()[0].material().surface_type {
+        SurfaceType::Reflective { reflectivity } => assert!((reflectivity - 0.2).abs() < 0.0001),
+        _ => panic!("expected a reflective sphere"),
+    }
+}
+
+#[test]
+fn test_parse_scene_reports_position_of_errors() {
+    let json = r#"{
+        "width": 80,
+        "height": 60,
+        "elements": [{ "type": "cube" }]
+    }"#;
+
+    let error = parse_scene(json, Path::new("scene.json")).err().unwrap();
+    let message = error.to_string();
+    assert!(message.starts_with("scene.json: unknown variant `cube`"));
+    assert!(message.contains("line 4"));
+}
+
+#[test]
+fn test_load_example_scene() {
+    let scene = load_scene("scenes/default.json").unwrap();
+    assert_eq!(scene.lights.len(), 3);
+    assert_eq!(scene.elements().len(), 5);
+    assert!(matches!(
+        scene.elements()[3].material().color,
+        Coloration::Texture(_)
+    ));
+}
+
+#[test]
+fn test_load_spot_light_with_ies_profile() {
+    let scene = load_scene("scenes/spotlights.json").unwrap();
+    match &scene.lights[1] {
+        Light::Spot(spot) => {
+            let profile = spot.profile.as_ref().unwrap();
+            assert_eq!(profile.vertical_angles.len(), 10);
+            // The brightest ring of the profile is at 20 degrees
+            assert!((spot.falloff(&vector([0., -1., 0.])) - 0.6).abs() < 0.0001);
+            let ring = vector([20f64.to_radians().tan(), -1., 0.]);
+            assert!((spot.falloff(&ring) - 1.0).abs() < 0.0001);
+        }
+        _ => panic!("expected a spot light"),
+    }
+}
+
+#[test]
+fn test_parse_microfacet_material() {
+    let json = r#"{
+        "width": 80,
+        "height": 60,
+        "camera": { "position": [0, 1, 2], "look_at": [0, 0, -4], "fov": 60 },
+        "lights": [],
+        "elements": [
+            {
+                "type": "sphere",
+                "center": [0, 0, -4],
+                "radius": 1,
+                "material": {
+                    "color": [1, 0.78, 0.34],
+                    "surface": { "type": "microfacet", "metallic": 1, "roughness": 0.3 }
+                }
+            },
+            {
+                "type": "plane",
+                "p0": [0, -2, 0],
+                "normal": [0, -1, 0],
+                "material": {
+                    "color": [1, 1, 1],
+                    "surface": {
+                        "type": "microfacet",
+                        "roughness": { "texture": "checkerboard.png" }
+                    }
+                }
+            }
+        ]
+    }"#;
+
+    let scene = parse_scene(json, Path::new("scene.json")).unwrap();
+    let coords = crate::scene::TextureCoords { x: 0.1, y: 0.1 };
+    let gold = scene.elements()[0].material().microfacet(&coords).unwrap();
+    assert!((gold.metallic - 1.).abs() < 0.0001);
+    assert!((gold.roughness - 0.3).abs() < 0.0001);
+    match &scene.elements()[1].material().surface_type {
+        SurfaceType::Microfacet {
+            metallic: Scalar::Value(metallic),
+            roughness: Scalar::Texture(_),
+        } => assert_eq!(*metallic, 0.0),
+        _ => panic!("expected a microfacet floor with a roughness texture"),
+    }
+}
+
+#[test]
+fn test_load_environment_background() {
+    let scene = load_scene("scenes/environment.json").unwrap();
+    assert_eq!(scene.background_samples, 16);
+    assert!(matches!(scene.background, Background::Environment(_)));
+    // The sky above is blue
+    let sky = scene.background.radiance(&vector([0., 1., 0.]));
+    assert!(sky.blue > sky.red);
+
+    let json = r#"{
+        "width": 8,
+        "height": 6,
+        "background": { "type": "gradient", "bottom": [0, 0, 0], "top": [0.2, 0.4, 1] }
+    }"#;
+    let scene = parse_scene(json, Path::new("scene.json")).unwrap();
+    let horizon = scene.background.radiance(&vector([1., 0., 0.]));
+    assert!((horizon.blue - 0.5).abs() < 0.0001);
+}
+
+#[test]
+fn test_load_sky_adds_the_sun() {
+    let scene = load_scene("scenes/sky.json").unwrap();
+    assert!(matches!(scene.background, Background::Sky(_)));
+    match &scene.lights[..] {
+        [Light::Directional(sun)] => {
+            assert!((sun.intensity - 6.).abs() < 0.0001);
+            // Sunlight is reddened by the atmosphere
+            assert!(sun.color.red > sun.color.blue);
+        }
+        _ => panic!("expected the sun as the only light"),
+    }
+}
+
+#[test]
+fn test_load_instances() {
+    let scene = load_scene("scenes/instances.json").unwrap();
+    // 35 crystals sharing one mesh, and the floor
+    assert_eq!(scene.elements().len(), 36);
+    match (&scene.elements()[0], &scene.elements()[1]) {
+        (Element::Instance(a), Element::Instance(b)) => {
+            assert!(Arc::ptr_eq(&a.object, &b.object));
+            assert!(matches!(*a.object, Element::Mesh(_)));
+        }
+        _ => panic!("expected instances"),
+    }
+
+    let scene_with = |elements: &str| {
+        let json = format!(
+            r#"{{
+                "width": 8,
+                "height": 6,
+                "objects": {{
+                    "ball": {{ "type": "sphere", "center": [0, 0, 0], "radius": 1, "material": {{ "color": [1, 1, 1] }} }}
+                }},
+                "elements": [{}]
+            }}"#,
+            elements
+        );
+        parse_scene(&json, Path::new("scene.json"))
+    };
+    // Steps apply in order: scaled about the origin, then moved
+    let scene = scene_with(
+        r#"{ "type": "instance", "object": "ball", "transform": [{ "scale": [2, 2, 2] }, { "translate": [0, 0, -5] }] }"#,
+    )
+    .unwrap();
+    let bounds = scene.elements()[0].bounding_box().unwrap();
+    assert!((bounds.min.z + 7.).abs() < 1e-9 && (bounds.max.z + 3.).abs() < 1e-9);
+
+    let error = scene_with(r#"{ "type": "instance", "object": "cube" }"#).err();
+    assert!(matches!(error, Some(SceneFileError::UnknownObject(name)) if name == "cube"));
+    let error = scene_with(
+        r#"{ "type": "instance", "object": "ball", "transform": [{ "scale": [1, 0, 1] }] }"#,
+    )
+    .err();
+    assert!(matches!(error, Some(SceneFileError::SingularTransform(_))));
+}
+
+#[test]
+fn test_load_solid_primitives() {
+    let scene = load_scene("scenes/shapes.json").unwrap();
+    assert!(matches!(
+        scene.elements(),
+        [
+            Element::Cuboid(_),
+            Element::Cylinder(_),
+            Element::Cone(_),
+            Element::Torus(torus),
+            Element::Disk(_),
+            Element::Plane(_),
+        ] if (torus.axis.length() - 1.).abs() < 1e-9
+    ));
+    // Shapes stand upright by default
+    match &scene.elements()[1] {
+        Element::Cylinder(cylinder) => assert_eq!(cylinder.axis.y, 1.),
+        _ => panic!("expected a cylinder"),
+    }
+}
+
+#[test]
+fn test_load_csg() {
+    let scene = load_scene("scenes/csg.json").unwrap();
+    match &scene.elements()[0] {
+        // Children are combined in order, so the last one cut away is on top
+        Element::Csg(csg) => {
+            assert_eq!(csg.operation, CsgOperation::Difference);
+            assert!(matches!(*csg.right, Element::Cuboid(_)));
+            assert!(matches!(*csg.left, Element::Csg(_)));
+        }
+        _ => panic!("expected a csg element"),
+    }
+
+    let scene_with = |children: &str| {
+        let json = format!(
+            r#"{{
+                "width": 8,
+                "height": 6,
+                "elements": [{{ "type": "csg", "operation": "union", "children": [{}] }}]
+            }}"#,
+            children
+        );
+        parse_scene(&json, Path::new("scene.json"))
+    };
+    let error = scene_with(
+        r#"{ "type": "disk", "center": [0, 0, 0], "normal": [0, 1, 0], "radius": 1, "material": { "color": [1, 1, 1] } }"#,
+    )
+    .err();
+    assert!(matches!(error, Some(SceneFileError::NotSolid("disk"))));
+    assert!(matches!(scene_with(""), Err(SceneFileError::EmptyCsg)));
+}
+
+/// What a scene with the given top level fields is rejected for, if it is degenerate.
+#[cfg(test)]
+fn degenerate(fields: &str) -> Option<(&'static str, &'static str)> {
+    let json = format!(r#"{{ "width": 8, "height": 6, {} }}"#, fields);
+    match parse_scene(&json, Path::new("scene.json")) {
+        Err(SceneFileError::Degenerate { kind, field }) => Some((kind, field)),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_reject_degenerate_shapes() {
+    let element = |element: &str| {
+        degenerate(&format!(
+            r#""elements": [{{ {}, "material": {{ "color": [1, 1, 1] }} }}]"#,
+            element
+        ))
+    };
+    assert_eq!(
+        element(r#""type": "cone", "base": [0, 0, 0], "radius": 1, "height": 0"#),
+        Some(("cone", "height"))
+    );
+    assert_eq!(
+        element(r#""type": "cylinder", "base": [0, 0, 0], "radius": -1, "height": 2"#),
+        Some(("cylinder", "radius"))
+    );
+    assert_eq!(
+        element(
+            r#""type": "cylinder", "base": [0, 0, 0], "axis": [0, 0, 0], "radius": 1, "height": 2"#
+        ),
+        Some(("cylinder", "axis"))
+    );
+    assert_eq!(
+        element(r#""type": "torus", "center": [0, 0, 0], "major_radius": 1, "minor_radius": 0"#),
+        Some(("torus", "minor_radius"))
+    );
+    assert_eq!(
+        element(
+            r#""type": "torus", "center": [0, 0, 0], "axis": [0, 0, 0], "major_radius": 1, "minor_radius": 0.2"#
+        ),
+        Some(("torus", "axis"))
+    );
+    assert_eq!(
+        element(r#""type": "sphere", "center": [0, 0, 0], "radius": 0"#),
+        Some(("sphere", "radius"))
+    );
+    assert_eq!(
+        element(r#""type": "plane", "p0": [0, 0, 0], "normal": [0, 0, 0]"#),
+        Some(("plane", "normal"))
+    );
+    assert_eq!(
+        element(r#""type": "box", "min": [0, 0, 0], "max": [1, 0, 1]"#),
+        Some(("box", "max"))
+    );
+    assert_eq!(
+        element(r#""type": "disk", "center": [0, 0, 0], "normal": [0, 1, 0], "radius": -1"#),
+        Some(("disk", "radius"))
+    );
+    assert_eq!(
+        element(r#""type": "disk", "center": [0, 0, 0], "normal": [0, 0, 0], "radius": 1"#),
+        Some(("disk", "normal"))
+    );
+    assert_eq!(
+        element(r#""type": "torus", "center": [0, 0, 0], "major_radius": 1, "minor_radius": 0.2"#),
+        None
+    );
+
+    let json = r#"{
+        "width": 8,
+        "height": 6,
+        "elements": [{
+            "type": "cone",
+            "base": [0, 0, 0],
+            "radius": 1,
+            "height": 0,
+            "material": { "color": [1, 1, 1] }
+        }]
+    }"#;
+    let error = parse_scene(json, Path::new("scene.json")).err().unwrap();
+    assert_eq!(error.to_string(), "cone has a degenerate `height`");
+}
+
+#[test]
+fn test_reject_degenerate_lights() {
+    let light = |light: &str| {
+        degenerate(&format!(
+            r#""lights": [{{ {}, "color": [1, 1, 1], "intensity": 1 }}]"#,
+            light
+        ))
+    };
+    assert_eq!(
+        light(r#""type": "directional", "direction": [0, 0, 0]"#),
+        Some(("directional light", "direction"))
+    );
+    assert_eq!(
+        light(
+            r#""type": "spot", "position": [0, 0, 0], "direction": [0, 0, 0], "outer_angle": 30"#
+        ),
+        Some(("spot light", "direction"))
+    );
+    assert_eq!(
+        light(
+            r#""type": "rectangle", "corner": [0, 0, 0], "edge1": [0, 0, 0], "edge2": [0, 0, 1]"#
+        ),
+        Some(("rectangle light", "edge1"))
+    );
+    assert_eq!(
+        light(
+            r#""type": "rectangle", "corner": [0, 0, 0], "edge1": [1, 0, 0], "edge2": [2, 0, 0]"#
+        ),
+        Some(("rectangle light", "edge2"))
+    );
+    assert_eq!(
+        light(r#""type": "disk", "center": [0, 0, 0], "normal": [0, -1, 0], "radius": 0"#),
+        Some(("disk light", "radius"))
+    );
+    assert_eq!(
+        light(r#""type": "disk", "center": [0, 0, 0], "normal": [0, 0, 0], "radius": 1"#),
+        Some(("disk light", "normal"))
+    );
+    assert_eq!(
+        light(r#""type": "sphere", "center": [0, 0, 0], "radius": -0.5"#),
+        Some(("sphere light", "radius"))
+    );
+    assert_eq!(
+        light(
+            r#""type": "rectangle", "corner": [0, 0, 0], "edge1": [1, 0, 0], "edge2": [0, 0, 1]"#
+        ),
+        None
+    );
+}
+
+#[test]
+fn test_reject_degenerate_camera() {
+    let camera = |camera: &str| degenerate(&format!(r#""camera": {{ {} }}"#, camera));
+    assert_eq!(
+        camera(r#""position": [0, 0, 0], "look_at": [0, 0, 0]"#),
+        Some(("camera", "look_at"))
+    );
+    assert_eq!(
+        camera(r#""position": [0, 2, 0], "look_at": [0, 0, 0]"#),
+        Some(("camera", "up"))
+    );
+    assert_eq!(
+        camera(r#""position": [0, 2, 0], "look_at": [0, 0, 0], "up": [0, 0, -1]"#),
+        None
+    );
+}
+
+#[test]
+fn test_load_sdf() {
+    let scene = load_scene("scenes/sdf.json").unwrap();
+    match &scene.elements()[2] {
+        Element::DistanceField(field) => match &field.sdf {
+            Sdf::Translate { shape, .. } => match **shape {
+                // Twists are given in degrees
+                Sdf::Twist { amount, .. } => {
+                    assert!((amount - std::f64::consts::FRAC_PI_3).abs() < 1e-9)
+                }
+                _ => panic!("expected a twist"),
+            },
+            _ => panic!("expected a translation"),
+        },
+        _ => panic!("expected a distance field"),
+    }
+
+    let scene_with = |shape: &str| {
+        let json = format!(
+            r#"{{
+                "width": 8,
+                "height": 6,
+                "elements": [{{
+                    "type": "sdf",
+                    "shape": {},
+                    "material": {{ "color": [1, 1, 1] }}
+                }}]
+            }}"#,
+            shape
+        );
+        parse_scene(&json, Path::new("scene.json"))
+    };
+    assert!(matches!(
+        scene_with(r#"{ "type": "smooth_union", "shapes": [] }"#),
+        Err(SceneFileError::EmptySmoothUnion)
+    ));
+    assert!(matches!(
+        scene_with(
+            r#"{ "type": "scale", "factor": 0, "shape": { "type": "sphere", "radius": 1 } }"#
+        ),
+        Err(SceneFileError::NonPositiveScale(_))
+    ));
+    assert!(matches!(
+        scene_with(r#"{ "type": "mandelbulb", "power": 1.5 }"#),
+        Err(SceneFileError::MandelbulbPower(_))
+    ));
+}
+
+#[test]
+fn test_load_heightfield() {
+    let scene = load_scene("scenes/terrain.json").unwrap();
+    match &scene.elements()[0] {
+        Element::Heightfield(heightfield) => {
+            let bounds = heightfield.bounding_box();
+            assert_eq!([bounds.min.x, bounds.min.y, bounds.min.z], [-5., -1., -8.]);
+            assert_eq!([bounds.max.x, bounds.max.y, bounds.max.z], [5., 1.5, 2.]);
+        }
+        _ => panic!("expected a heightfield"),
+    }
+
+    let directory = std::env::temp_dir();
+    let path = directory.join("ray_tracing_test_heightmap.png");
+    image::GrayImage::new(1, 4).save(&path).unwrap();
+    let json = r#"{
+        "width": 8,
+        "height": 6,
+        "elements": [{
+            "type": "heightfield",
+            "path": "ray_tracing_test_heightmap.png",
+            "min": [0, 0, 0],
+            "size": [1, 1, 1],
+            "material": { "color": [1, 1, 1] }
+        }]
+    }"#;
+    let result = parse_scene(json, &directory.join("scene.json"));
+    let _ = std::fs::remove_file(&path);
+    assert!(matches!(result, Err(SceneFileError::HeightmapTooSmall(_))));
+}
@@ -0,0 +1,444 @@
+//! Shapes given by signed distance functions, which tell how far a point is from the
+//! surface: positive outside, negative inside.
+//!
+//! Knowing the distance to the nearest surface, a ray can safely step that far ahead
+//! without passing through anything. Sphere tracing repeats these steps until the ray
+//! is close enough to count as a hit. Normals are the gradient of the distance.
+//!
+//! Distance functions are easy to combine, blend and distort, which gives blobby
+//! shapes, endless repetitions and fractals like the Mandelbulb.
+//!
+//! See https://iquilezles.org/articles/distfunctions/
+
+use crate::bvh::BoundingBox;
+use crate::math::{Point, Vector3};
+use crate::rendering::Ray;
+use crate::scene::{Intersectable, Material, TextureCoords};
+
+/// How close a ray must come to the surface to hit it.
+const HIT_DISTANCE: f64 = 1e-4;
+/// Most steps taken along a ray before giving up.
+const MAX_STEPS: u32 = 512;
+/// How far rays are followed through shapes without bounds, like repetitions.
+const MAX_DISTANCE: f64 = 1e3;
+
+/// A signed distance function, built from shapes around the origin and operations on
+/// them.
+pub enum Sdf {
+    Sphere {
+        radius: f64,
+    },
+    /// A box reaching `half_size` from the origin along each axis, with its edges
+    /// rounded off by `rounding`. Rounding cuts into the box, without growing it.
+    Box {
+        half_size: Vector3,
+        rounding: f64,
+    },
+    /// A ring around the y axis.
+    Torus {
+        major_radius: f64,
+        minor_radius: f64,
+    },
+    /// The Mandelbulb fractal, about 2.2 wide at power 8. Higher `power` gives more
+    /// lobes, more `iterations` finer detail. The power must be at least 2.
+    Mandelbulb {
+        power: f64,
+        iterations: u32,
+    },
+    Translate {
+        offset: Vector3,
+        shape: Box<Sdf>,
+    },
+    Scale {
+        factor: f64,
+        shape: Box<Sdf>,
+    },
+    /// The union of two shapes, melting them together where they are closer than
+    /// `smoothness`. A smoothness of 0 gives a sharp union.
+    SmoothUnion {
+        left: Box<Sdf>,
+        right: Box<Sdf>,
+        smoothness: f64,
+    },
+    /// Twists the shape around the y axis, by `amount` radians per unit of height.
+    Twist {
+        amount: f64,
+        shape: Box<Sdf>,
+    },
+    /// Repeats the shape endlessly, every `period` along each axis. A period of 0
+    /// leaves that axis alone. The shape should fit within one period.
+    Repeat {
+        period: Vector3,
+        shape: Box<Sdf>,
+    },
+}
+
+impl Sdf {
+    /// The distance from the point to the surface, negative inside. Distorting
+    /// operations may return less than the true distance, but never more.
+    pub fn distance(&self, point: &Point) -> f64 {
+        match self {
+            Sdf::Sphere { radius } => point.as_vector().length() - radius,
+            Sdf::Box {
+                half_size,
+                rounding,
+            } => {
+                // A sharp box shrunk by the rounding, then grown back by it
+                // See https://iquilezles.org/articles/distfunctions/
+                let q = Vector3 {
+                    x: point.x.abs() - half_size.x + rounding,
+                    y: point.y.abs() - half_size.y + rounding,
+                    z: point.z.abs() - half_size.z + rounding,
+                };
+                let outside = Vector3 {
+                    x: q.x.max(0.),
+                    y: q.y.max(0.),
+                    z: q.z.max(0.),
+                };
+                outside.length() + q.x.max(q.y).max(q.z).min(0.) - rounding
+            }
+            Sdf::Torus {
+                major_radius,
+                minor_radius,
+            } => {
+                let across = point.x.hypot(point.z) - major_radius;
+                across.hypot(point.y) - minor_radius
+            }
+            Sdf::Mandelbulb { power, iterations } => mandelbulb(point, *power, *iterations),
+            Sdf::Translate { offset, shape } => {
+                shape.distance(&(point.as_vector() - *offset).as_point())
+            }
+            Sdf::Scale { factor, shape } => {
+                shape.distance(&(point.as_vector() * (1. / factor)).as_point()) * factor
+            }
+            Sdf::SmoothUnion {
+                left,
+                right,
+                smoothness,
+            } => {
+                let (a, b) = (left.distance(point), right.distance(point));
+                if *smoothness <= 0. {
+                    return a.min(b);
+                }
+                // Polynomial smooth minimum
+                let h = (smoothness - (a - b).abs()).max(0.) / smoothness;
+                a.min(b) - h * h * smoothness / 4.
+            }
+            Sdf::Twist { amount, shape } => {
+                let (sin, cos) = (amount * point.y).sin_cos();
+                let twisted = Point {
+                    x: cos * point.x - sin * point.z,
+                    y: point.y,
+                    z: sin * point.x + cos * point.z,
+                };
+                // Twisting stretches space further from the axis, so distances there
+                // are shrunk to stay safe to step
+                let stretch = (amount * point.x.hypot(point.z)).hypot(1.);
+                shape.distance(&twisted) / stretch
+            }
+            Sdf::Repeat { period, shape } => {
+                let wrap = |x: f64, period: f64| {
+                    if period > 0. {
+                        x - period * (x / period).round()
+                    } else {
+                        x
+                    }
+                };
+                shape.distance(&Point {
+                    x: wrap(point.x, period.x),
+                    y: wrap(point.y, period.y),
+                    z: wrap(point.z, period.z),
+                })
+            }
+        }
+    }
+
+    /// A box around the shape, or `None` if it goes on forever.
+    pub fn bounds(&self) -> Option<BoundingBox> {
+        let around = |r: Vector3| BoundingBox {
+            min: (r * -1.).as_point(),
+            max: r.as_point(),
+        };
+        let cube = |r: f64| around(Vector3 { x: r, y: r, z: r });
+        match self {
+            Sdf::Sphere { radius } => Some(cube(*radius)),
+            Sdf::Box { half_size, .. } => Some(around(*half_size)),
+            Sdf::Torus {
+                major_radius,
+                minor_radius,
+            } => {
+                let r = major_radius + minor_radius;
+                Some(around(Vector3 {
+                    x: r,
+                    y: *minor_radius,
+                    z: r,
+                }))
+            }
+            Sdf::Mandelbulb { power, .. } => Some(cube(mandelbulb_radius(*power))),
+            Sdf::Translate { offset, shape } => shape.bounds().map(|b| BoundingBox {
+                min: (b.min.as_vector() + *offset).as_point(),
+                max: (b.max.as_vector() + *offset).as_point(),
+            }),
+            Sdf::Scale { factor, shape } => shape.bounds().map(|b| BoundingBox {
+                min: (b.min.as_vector() * *factor).as_point(),
+                max: (b.max.as_vector() * *factor).as_point(),
+            }),
+            Sdf::SmoothUnion {
+                left,
+                right,
+                smoothness,
+            } => {
+                // Blending can swell the shapes a little where they meet
+                let b = left.bounds()?.union(&right.bounds()?);
+                let swell = Vector3 {
+                    x: *smoothness,
+                    y: *smoothness,
+                    z: *smoothness,
+                };
+                Some(BoundingBox {
+                    min: (b.min.as_vector() - swell).as_point(),
+                    max: (b.max.as_vector() + swell).as_point(),
+                })
+            }
+            Sdf::Twist { shape, .. } => {
+                let b = shape.bounds()?;
+                let reach = b
+                    .corners()
+                    .iter()
+                    .map(|c| c.x.hypot(c.z))
+                    .fold(0., f64::max);
+                Some(BoundingBox {
+                    min: Point::from_list([-reach, b.min.y, -reach]),
+                    max: Point::from_list([reach, b.max.y, reach]),
+                })
+            }
+            Sdf::Repeat { .. } => None,
+        }
+    }
+}
+
+/// Estimated distance to the Mandelbulb, from how fast the point escapes when
+/// iterated.
+///
+/// See http://blog.hvidtfeldts.net/index.php/2011/09/distance-estimated-3d-fractals-v-the-mandelbulb-different-de-approximations/
+fn mandelbulb(point: &Point, power: f64, iterations: u32) -> f64 {
+    let c = point.as_vector();
+    // The estimate is too large far away, where the bounding sphere is closer anyway
+    if c.length() > 2. {
+        return c.length() - mandelbulb_radius(power);
+    }
+    let mut z = c;
+    let mut derivative = 1.;
+    let mut r = z.length();
+    for _ in 0..iterations {
+        if r > 2. {
+            break;
+        }
+        // Raise z to the power in spherical coordinates, around the y axis
+        let theta = if r > 0. {
+            (z.y / r).clamp(-1., 1.).acos() * power
+        } else {
+            0.
+        };
+        let phi = z.z.atan2(z.x) * power;
+        derivative = r.powf(power - 1.) * power * derivative + 1.;
+        let scaled = r.powf(power);
+        z = Vector3 {
+            x: theta.sin() * phi.cos(),
+            y: theta.cos(),
+            z: theta.sin() * phi.sin(),
+        } * scaled
+            + c;
+        r = z.length();
+    }
+    if r <= 0. {
+        return 0.;
+    }
+    0.5 * r.ln() * r / derivative
+}
+
+/// The radius of a sphere around the Mandelbulb. Points further out than this grow
+/// with every iteration, so they escape. It is at most 2, the escape radius, for
+/// powers of at least 2.
+fn mandelbulb_radius(power: f64) -> f64 {
+    2f64.powf(1. / (power - 1.))
+}
+
+/// An element shaped by a signed distance function.
+pub struct DistanceField {
+    pub sdf: Sdf,
+    pub material: Material,
+}
+
+impl DistanceField {
+    pub fn bounding_box(&self) -> Option<BoundingBox> {
+        self.sdf.bounds()
+    }
+
+    /// The gradient of the distance, from four samples around the point at the
+    /// corners of a tetrahedron.
+    fn gradient(&self, point: &Point) -> Vector3 {
+        let h = HIT_DISTANCE * 0.1;
+        [[1., -1., -1.], [-1., -1., 1.], [-1., 1., -1.], [1., 1., 1.]]
+            .iter()
+            .map(|&k| {
+                let k = Point::from_list(k).as_vector();
+                let sample = (point.as_vector() + k * h).as_point();
+                k * self.sdf.distance(&sample)
+            })
+            .fold(Vector3::zero(), |sum, v| sum + v)
+    }
+}
+
+impl Intersectable for DistanceField {
+    fn intersect(&self, ray: &Ray) -> Option<f64> {
+        let (mut t, end) = match self.sdf.bounds() {
+            Some(bounds) => {
+                let (enter, exit) = bounds.span(ray)?;
+                (enter.max(0.), exit.min(MAX_DISTANCE))
+            }
+            None => (0., MAX_DISTANCE),
+        };
+
+        // Rays leaving the surface, like shadow rays, start right on it. They must get
+        // clear of it before a hit counts.
+        let mut clear = false;
+        for _ in 0..MAX_STEPS {
+            if t > end {
+                return None;
+            }
+            let point = (ray.origin.as_vector() + ray.direction * t).as_point();
+            // Stepping by the distance's size walks out of the inside, too
+            let distance = self.sdf.distance(&point).abs();
+            if distance < HIT_DISTANCE {
+                if clear && t > 0. {
+                    return Some(t);
+                }
+                t += HIT_DISTANCE;
+            } else {
+                clear = true;
+                t += distance;
+            }
+        }
+        None
+    }
+
+    fn surface_normal(&self, hit_point: &Point) -> Vector3 {
+        self.gradient(hit_point).normalize()
+    }
+
+    /// Projected onto the plane the surface faces most, like the faces of a box.
+    fn texture_coords(&self, hit_point: &Point) -> TextureCoords {
+        let n = self.surface_normal(hit_point);
+        let (u, v) = if n.x.abs() >= n.y.abs() && n.x.abs() >= n.z.abs() {
+            (hit_point.z, hit_point.y)
+        } else if n.y.abs() >= n.z.abs() {
+            (hit_point.x, hit_point.z)
+        } else {
+            (hit_point.x, hit_point.y)
+        };
+        TextureCoords {
+            x: u as f32,
+            y: v as f32,
+        }
+    }
+}
+
+#[test]
+fn test_distances() {
+    let at = |x, y, z| Point::from_list([x, y, z]);
+    let sphere = Sdf::Sphere { radius: 1. };
+    assert!((sphere.distance(&at(0., 3., 0.)) - 2.).abs() < 1e-9);
+    assert!((sphere.distance(&at(0., 0., 0.)) + 1.).abs() < 1e-9);
+
+    let cube = Sdf::Box {
+        half_size: Vector3 {
+            x: 1.,
+            y: 1.,
+            z: 1.,
+        },
+        rounding: 0.,
+    };
+    assert!((cube.distance(&at(3., 0., 0.)) - 2.).abs() < 1e-9);
+    // To the corner
+    assert!((cube.distance(&at(2., 2., 1.)) - 2f64.sqrt()).abs() < 1e-9);
+    // Rounding keeps the faces where they are, and cuts off the corners
+    let rounded = Sdf::Box {
+        half_size: Vector3 {
+            x: 1.,
+            y: 1.,
+            z: 1.,
+        },
+        rounding: 0.25,
+    };
+    assert!((rounded.distance(&at(3., 0., 0.)) - 2.).abs() < 1e-9);
+    assert!(rounded.distance(&at(0.99, 0.99, 0.99)) > 0.);
+
+    // Far apart, a smooth union is a plain union
+    let pair = Sdf::SmoothUnion {
+        left: Box::new(Sdf::Sphere { radius: 1. }),
+        right: Box::new(Sdf::Translate {
+            offset: Vector3 {
+                x: 10.,
+                y: 0.,
+                z: 0.,
+            },
+            shape: Box::new(Sdf::Sphere { radius: 1. }),
+        }),
+        smoothness: 0.5,
+    };
+    assert!((pair.distance(&at(0., 3., 0.)) - 2.).abs() < 1e-9);
+    assert!((pair.distance(&at(10., 3., 0.)) - 2.).abs() < 1e-9);
+    // Between close shapes it bulges out
+    let blob = Sdf::SmoothUnion {
+        left: Box::new(Sdf::Sphere { radius: 1. }),
+        right: Box::new(Sdf::Translate {
+            offset: Vector3 {
+                x: 2.2,
+                y: 0.,
+                z: 0.,
+            },
+            shape: Box::new(Sdf::Sphere { radius: 1. }),
+        }),
+        smoothness: 0.5,
+    };
+    assert!(blob.distance(&at(1.1, 0., 0.)) < 0.);
+
+    // Every copy is there
+    let row = Sdf::Repeat {
+        period: Vector3 {
+            x: 4.,
+            y: 0.,
+            z: 0.,
+        },
+        shape: Box::new(Sdf::Sphere { radius: 1. }),
+    };
+    assert!((row.distance(&at(-40., 2., 0.)) - 1.).abs() < 1e-9);
+    assert!(row.bounds().is_none());
+
+    // The center of the Mandelbulb is inside it, far away is outside
+    let bulb = Sdf::Mandelbulb {
+        power: 8.,
+        iterations: 8,
+    };
+    assert!(bulb.distance(&at(0., 0., 0.)) <= 0.);
+    let far = bulb.distance(&at(0., 5., 0.));
+    assert!(far > 3. && far < 5.);
+    // Lower powers give a larger shape
+    let bounds = |power| {
+        Sdf::Mandelbulb {
+            power,
+            iterations: 8,
+        }
+        .bounds()
+        .unwrap()
+    };
+    assert!(bounds(8.).max.x < 1.25);
+    assert!((bounds(2.).max.x - 2.).abs() < 1e-9);
+    let squared = Sdf::Mandelbulb {
+        power: 2.,
+        iterations: 8,
+    };
+    // Its tip reaches nearly as far as the bounds
+    assert!(squared.distance(&at(0., -1.9, 0.)) < 0.05);
+}
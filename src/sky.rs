@@ -0,0 +1,240 @@
+//! A clear daytime sky, following the analytic model of Preetham, Shirley and Smits.
+//!
+//! The sky's brightness and color in every direction depend on where the sun is and on
+//! the turbidity of the air: 2 is a very clear sky, 10 a hazy one. The sun itself is a
+//! `DirectionalLight`, reddened by the atmosphere when it is low.
+//!
+//! See "A Practical Analytic Model for Daylight" (1999),
+//! https://www2.cs.utah.edu/~shirley/papers/sunsky/sunsky.pdf
+
+use std::f64::consts::PI;
+
+use crate::color::{Color, BLACK};
+use crate::math::Vector3;
+use crate::scene::DirectionalLight;
+
+/// Illuminance in klux of the sun that a `DirectionalLight` with intensity 1 stands for.
+/// Sets how bright the sky is compared to the sun.
+const SUN_ILLUMINANCE: f64 = 100.0;
+
+pub struct Sky {
+    /// Towards the sun
+    sun_direction: Vector3,
+    /// Zenith angle of the sun
+    sun_theta: f64,
+    /// Perez coefficients for luminance and the two chromaticity coordinates
+    perez: [[f64; 5]; 3],
+    /// Luminance (kcd/m²) and chromaticity at the zenith
+    zenith: [f64; 3],
+    /// Converts luminance in kcd/m² to scene units
+    scale: f64,
+    /// Light reflected by the ground, seen below the horizon
+    ground: Color,
+}
+
+impl Sky {
+    /// The sky for the given sun, and the ground below it. `turbidity` is clamped to
+    /// the 2 to 10 range the model was fitted for.
+    pub fn new(sun: &DirectionalLight, turbidity: f32, ground_albedo: Color) -> Sky {
+        let t = turbidity.clamp(2.0, 10.0) as f64;
+        let sun_direction = (sun.direction * -1.).normalize();
+        let sun_theta = sun_direction.y.clamp(0.0, 1.0).acos();
+
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * sun_theta);
+        let zenith_luminance = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.0);
+        let (th, th2, th3) = (sun_theta, sun_theta * sun_theta, sun_theta.powi(3));
+        let zenith_x = t * t * (0.00166 * th3 - 0.00375 * th2 + 0.00209 * th)
+            + t * (-0.02903 * th3 + 0.06377 * th2 - 0.03202 * th + 0.00394)
+            + (0.11693 * th3 - 0.21196 * th2 + 0.06052 * th + 0.25886);
+        let zenith_y = t * t * (0.00275 * th3 - 0.00610 * th2 + 0.00317 * th)
+            + t * (-0.04214 * th3 + 0.08970 * th2 - 0.04153 * th + 0.00516)
+            + (0.15346 * th3 - 0.26756 * th2 + 0.06670 * th + 0.26688);
+
+        let mut sky = Sky {
+            sun_direction,
+            sun_theta,
+            perez,
+            zenith: [zenith_luminance, zenith_x, zenith_y],
+            scale: sun.intensity as f64 / SUN_ILLUMINANCE,
+            ground: BLACK,
+        };
+
+        // The ground is lit by the sun and the whole sky, and reflects diffusely
+        let sun_irradiance = sun.color * (sun.intensity * sun_direction.y.max(0.0) as f32);
+        sky.ground = ground_albedo * ((sun_irradiance + sky.irradiance()) * (1.0 / PI as f32));
+        sky
+    }
+
+    /// The light arriving from the sky along `direction`, which points away from the
+    /// scene.
+    pub fn radiance(&self, direction: &Vector3) -> Color {
+        let direction = direction.normalize();
+        if direction.y <= 0.0 {
+            return self.ground;
+        }
+        self.sky_radiance(&direction)
+    }
+
+    fn sky_radiance(&self, direction: &Vector3) -> Color {
+        let theta = direction.y.clamp(0.0, 1.0).acos();
+        let gamma = direction.dot(&self.sun_direction).clamp(-1.0, 1.0).acos();
+        let value = |i: usize| {
+            self.zenith[i] * perez(&self.perez[i], theta, gamma)
+                / perez(&self.perez[i], 0.0, self.sun_theta)
+        };
+        xyy_to_rgb(value(1), value(2), value(0) * self.scale)
+    }
+
+    /// Irradiance on a horizontal surface from the sky alone, by numerical
+    /// integration over the upper hemisphere.
+    fn irradiance(&self) -> Color {
+        let (rings, segments) = (16, 32);
+        let mut total = BLACK;
+        for i in 0..rings {
+            let theta = (i as f64 + 0.5) / rings as f64 * PI / 2.0;
+            for j in 0..segments {
+                let phi = (j as f64 + 0.5) / segments as f64 * 2.0 * PI;
+                let direction = Vector3 {
+                    x: theta.sin() * phi.cos(),
+                    y: theta.cos(),
+                    z: theta.sin() * phi.sin(),
+                };
+                let solid_angle =
+                    theta.sin() * (PI / 2.0 / rings as f64) * (2.0 * PI / segments as f64);
+                let weight = (theta.cos() * solid_angle) as f32;
+                total = total + self.sky_radiance(&direction) * weight;
+            }
+        }
+        total
+    }
+}
+
+/// The sun as a directional light shining along `direction`, with `intensity` before it
+/// passes through the atmosphere. A low sun is dimmer and redder, more so in hazy air.
+///
+/// The sunlight is attenuated by Rayleigh scattering in the air and by aerosols (haze),
+/// following the Preetham paper's appendix, at the wavelengths of red, green and blue.
+pub fn sun_light(direction: Vector3, intensity: f32, turbidity: f32) -> DirectionalLight {
+    let t = turbidity.clamp(2.0, 10.0) as f64;
+    let direction = direction.normalize();
+    let elevation = (-direction.y).clamp(0.0, 1.0).asin().to_degrees();
+    let theta = 90.0 - elevation;
+    // Relative optical mass of the air (Kasten and Young)
+    let air_mass = 1.0 / (theta.to_radians().cos() + 0.50572 * (96.07995 - theta).powf(-1.6364));
+    let beta = 0.04608 * t - 0.04586;
+    let transmittance = |wavelength: f64| {
+        let rayleigh = 0.008735 * wavelength.powf(-4.08);
+        let aerosol = beta * wavelength.powf(-1.3);
+        (-air_mass * (rayleigh + aerosol)).exp() as f32
+    };
+
+    DirectionalLight {
+        direction,
+        color: Color {
+            red: transmittance(0.68),
+            green: transmittance(0.55),
+            blue: transmittance(0.44),
+        },
+        intensity,
+    }
+}
+
+/// The Perez sky luminance distribution, for a direction at zenith angle `theta` and
+/// angle `gamma` from the sun.
+fn perez(coefficients: &[f64; 5], theta: f64, gamma: f64) -> f64 {
+    let [a, b, c, d, e] = *coefficients;
+    let cos_theta = theta.cos().max(0.01);
+    (1.0 + a * (b / cos_theta).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+}
+
+/// Linear sRGB from CIE chromaticity `x`, `y` and luminance `luminance`.
+fn xyy_to_rgb(x: f64, y: f64, luminance: f64) -> Color {
+    if y <= 0.0 {
+        return BLACK;
+    }
+    let big_x = x / y * luminance;
+    let big_z = (1.0 - x - y) / y * luminance;
+    let big_y = luminance;
+    Color {
+        red: (3.2406 * big_x - 1.5372 * big_y - 0.4986 * big_z).max(0.0) as f32,
+        green: (-0.9689 * big_x + 1.8758 * big_y + 0.0415 * big_z).max(0.0) as f32,
+        blue: (0.0557 * big_x - 0.2040 * big_y + 1.0570 * big_z).max(0.0) as f32,
+    }
+}
+
+#[test]
+fn test_sky_is_blue_and_brightest_near_the_sun() {
+    use crate::color::WHITE;
+
+    let sun = sun_light(
+        Vector3 {
+            x: 0.,
+            y: -1.,
+            z: -1.,
+        },
+        5.0,
+        3.0,
+    );
+    // A high sun is barely reddened, but more in haze
+    assert!(sun.color.blue < sun.color.red && sun.color.blue > 0.4);
+    let hazy = sun_light(sun.direction, 5.0, 8.0);
+    assert!(hazy.color.blue < sun.color.blue);
+
+    let sky = Sky::new(&sun, 3.0, WHITE * 0.3);
+    let zenith = sky.radiance(&Vector3 {
+        x: 0.,
+        y: 1.,
+        z: 0.,
+    });
+    assert!(zenith.blue > zenith.red);
+
+    let near_sun = sky.radiance(&Vector3 {
+        x: 0.,
+        y: 0.8,
+        z: 1.,
+    });
+    let away_from_sun = sky.radiance(&Vector3 {
+        x: 0.,
+        y: 0.8,
+        z: -1.,
+    });
+    assert!(near_sun.red > away_from_sun.red);
+
+    // The ground reflects what the sky and sun shine on it
+    let ground = sky.radiance(&Vector3 {
+        x: 0.,
+        y: -1.,
+        z: 0.,
+    });
+    let darker = Sky::new(&sun, 3.0, WHITE * 0.1).radiance(&Vector3 {
+        x: 0.,
+        y: -1.,
+        z: 0.,
+    });
+    assert!((ground.green - 3.0 * darker.green).abs() < 1e-5);
+    assert!(ground.green > 0.0);
+}
@@ -1,61 +1,83 @@
 mod tests {
+    use std::sync::Arc;
+
     use image::DynamicImage;
-    use image::GenericImageView;
 
-    use crate::color::Color;
+    use crate::background::Background;
+    use crate::camera::Camera;
+    use crate::cast_ray;
+    use crate::color::{Color, ToneMap, BLACK, WHITE};
+    use crate::csg::{Csg, CsgOperation, Interval};
+    use crate::fresnel;
+    use crate::heightfield::Heightfield;
+    use crate::light_from_elements;
     use crate::math::Point;
     use crate::math::Vector3;
+    use crate::math::{Matrix4, Transform};
     use crate::render;
     use crate::rendering::Ray;
+    use crate::sampling::{Filter, Rng};
+    use crate::scene::intersect_triangle;
     use crate::scene::Coloration;
     use crate::scene::DirectionalLight;
+    use crate::scene::DiskLight;
     use crate::scene::Element;
+    use crate::scene::Emission;
+    use crate::scene::Instance;
+    use crate::scene::Integrator;
     use crate::scene::Intersectable;
+    use crate::scene::Intersection;
     use crate::scene::Light;
     use crate::scene::Material;
+    use crate::scene::Mesh;
     use crate::scene::Plane;
+    use crate::scene::RectangleLight;
+    use crate::scene::Specular;
+    use crate::scene::SphereLight;
+    use crate::scene::SphericalLight;
+    use crate::scene::SpotLight;
     use crate::scene::SurfaceType;
+    use crate::scene::TextureCoords;
+    use crate::scene::Triangle;
+    use crate::scene::{Cone, Cuboid, Cylinder, Disk, Torus};
     use crate::scene::{Scene, Sphere};
+    use crate::sdf::{DistanceField, Sdf};
 
     #[test]
     fn test_can_render_scene() {
-        let scene = Scene {
-            width: 80,
-            height: 60,
-            max_recursion_depth: 3,
-            shadow_bias: 1e-6,
-            fov: 90.0,
-            lights: vec![Light::Directional(DirectionalLight {
-                direction: Vector3 {
-                    x: 0.,
-                    y: 0.,
-                    z: -1.,
-                },
-                color: Color {
-                    red: 1.,
-                    green: 1.,
-                    blue: 1.,
-                },
-                intensity: 100.,
-            })],
-            elements: vec![Element::Sphere(Sphere {
-                center: Point {
-                    x: 0.0,
-                    y: 0.0,
-                    z: -5.0,
-                },
-                radius: 1.0,
-                material: Material {
-                    surface_type: SurfaceType::Diffuse,
-                    color: Coloration::Color(Color {
-                        red: 0.4,
-                        green: 1.0,
-                        blue: 0.4,
-                    }),
-                    albedo: 0.18,
-                },
-            })],
-        };
+        let mut scene = Scene::new(80, 60, 90.0, 1e-6, 3);
+        scene.add_light(Light::Directional(DirectionalLight {
+            direction: Vector3 {
+                x: 0.,
+                y: 0.,
+                z: -1.,
+            },
+            color: Color {
+                red: 1.,
+                green: 1.,
+                blue: 1.,
+            },
+            intensity: 100.,
+        }));
+        scene.add_element(Element::Sphere(Sphere {
+            center: Point {
+                x: 0.0,
+                y: 0.0,
+                z: -5.0,
+            },
+            radius: 1.0,
+            material: Material {
+                surface_type: SurfaceType::Diffuse,
+                color: Coloration::Color(Color {
+                    red: 0.4,
+                    green: 1.0,
+                    blue: 0.4,
+                }),
+                albedo: 0.18,
+                emission: None,
+                specular: None,
+            },
+        }));
 
         let img: DynamicImage = render(&scene);
         assert_eq!(scene.width, img.width());
@@ -79,6 +101,8 @@ mod tests {
                     blue: 0.,
                 }),
                 albedo: 0.17,
+                emission: None,
+                specular: None,
             },
         };
 
@@ -105,6 +129,29 @@ mod tests {
         assert!(sphere.intersect(&ray2).is_none());
     }
 
+    #[test]
+    fn test_plane_texture_orientation() {
+        let plane = |normal: [f64; 3]| Plane {
+            normal: Point::from_list(normal).as_vector(),
+            p0: Point::zero(),
+            material: grey_material(),
+        };
+        let coords = |plane: &Plane, point: [f64; 3]| {
+            let coords = plane.texture_coords(&Point::from_list(point));
+            [coords.x, coords.y]
+        };
+
+        // On a floor, u runs along +z and v along +x
+        let floor = plane([0., -1., 0.]);
+        assert_eq!(coords(&floor, [0., 0., 2.]), [2., 0.]);
+        assert_eq!(coords(&floor, [3., 0., 0.]), [0., 3.]);
+
+        // On a wall facing the camera, u runs down along -y and v along +x
+        let wall = plane([0., 0., -1.]);
+        assert_eq!(coords(&wall, [0., 2., 0.]), [-2., 0.]);
+        assert_eq!(coords(&wall, [3., 0., 0.]), [0., 3.]);
+    }
+
     #[test]
     fn test_plane_intersect() {
         let plane = Plane {
@@ -126,6 +173,8 @@ mod tests {
                     blue: 1.0,
                 }),
                 albedo: 0.18,
+                emission: None,
+                specular: None,
             },
         };
 
@@ -153,4 +202,1942 @@ mod tests {
         assert!(intersection.is_some());
         assert!(intersection2.is_some());
     }
+
+    #[test]
+    fn test_intersect_from_inside_sphere() {
+        let sphere = Sphere {
+            center: Point::zero(),
+            radius: 1.0,
+            material: Material {
+                surface_type: SurfaceType::Diffuse,
+                color: Coloration::Color(Color {
+                    red: 0.,
+                    green: 0.,
+                    blue: 0.,
+                }),
+                albedo: 0.17,
+                emission: None,
+                specular: None,
+            },
+        };
+
+        let ray = Ray {
+            direction: Vector3 {
+                x: 1.,
+                y: 0.,
+                z: 0.,
+            },
+            origin: Point::zero(),
+        };
+
+        let distance = sphere.intersect(&ray).unwrap();
+        assert!((distance - 1.0).abs() < 0.0001);
+    }
+
+    #[test]
+    fn test_fresnel() {
+        let normal = Vector3 {
+            x: 0.,
+            y: 0.,
+            z: 1.,
+        };
+        let head_on = Vector3 {
+            x: 0.,
+            y: 0.,
+            z: -1.,
+        };
+
+        // About 4% of light is reflected off glass at normal incidence
+        let kr = fresnel(head_on, normal, 1.5);
+        assert!((kr - 0.04).abs() < 0.001);
+
+        // A grazing ray leaving glass is totally internally reflected
+        let grazing_from_inside = Vector3 {
+            x: 1.,
+            y: 0.,
+            z: 0.1,
+        }
+        .normalize();
+        assert_eq!(fresnel(grazing_from_inside, normal, 1.5), 1.0);
+    }
+
+    #[test]
+    fn test_refractive_sphere_shows_background() {
+        let white = Color {
+            red: 1.,
+            green: 1.,
+            blue: 1.,
+        };
+        let mut scene = Scene::new(10, 10, 90.0, 1e-6, 5);
+        scene.add_light(Light::Directional(DirectionalLight {
+            direction: Vector3 {
+                x: 0.,
+                y: -1.,
+                z: -1.,
+            },
+            color: white,
+            intensity: 20.,
+        }));
+        scene.add_element(Element::Sphere(Sphere {
+            center: Point {
+                x: 0.0,
+                y: 0.0,
+                z: -5.0,
+            },
+            radius: 1.0,
+            material: Material {
+                surface_type: SurfaceType::Refractive {
+                    index: 1.5,
+                    transparency: 1.0,
+                },
+                color: Coloration::Color(white),
+                albedo: 0.18,
+                emission: None,
+                specular: None,
+            },
+        }));
+        scene.add_element(Element::Plane(Plane {
+            normal: Vector3 {
+                x: 0.,
+                y: 0.,
+                z: -1.0,
+            },
+            p0: Point {
+                x: 0.,
+                y: 0.,
+                z: -20.,
+            },
+            material: Material {
+                surface_type: SurfaceType::Diffuse,
+                color: Coloration::Color(white),
+                albedo: 0.18,
+                emission: None,
+                specular: None,
+            },
+        }));
+
+        let ray = Ray {
+            direction: Vector3 {
+                x: 0.,
+                y: 0.,
+                z: -1.,
+            },
+            origin: Point::zero(),
+        };
+
+        // Looking straight through the glass sphere, most of the lit back plane comes through
+        let through_glass = cast_ray(&scene, &ray, 0, &mut Rng::new(0));
+        assert!(through_glass.red > 0.5);
+    }
+
+    #[test]
+    fn test_partly_transparent_surface_keeps_its_light() {
+        let white = Color {
+            red: 1.,
+            green: 1.,
+            blue: 1.,
+        };
+        let look = |transparency: f32| {
+            let mut scene = Scene::new(10, 10, 90.0, 1e-6, 5);
+            scene.add_light(Light::Directional(DirectionalLight {
+                direction: Vector3 {
+                    x: 0.,
+                    y: -1.,
+                    z: -1.,
+                },
+                color: white,
+                intensity: 20.,
+            }));
+            // A single pane, so that the light passing through it meets no
+            // other transparent surface
+            scene.add_element(Element::Triangle(Triangle {
+                v0: Point::from_list([-2., -2., -5.]),
+                v1: Point::from_list([2., -2., -5.]),
+                v2: Point::from_list([0., 3., -5.]),
+                material: Material {
+                    surface_type: SurfaceType::Refractive {
+                        index: 1.5,
+                        transparency,
+                    },
+                    color: Coloration::Color(white),
+                    albedo: 0.18,
+                    emission: None,
+                    specular: None,
+                },
+            }));
+            scene.add_element(Element::Plane(Plane {
+                normal: Vector3 {
+                    x: 0.,
+                    y: 0.,
+                    z: -1.0,
+                },
+                p0: Point::from_list([0., 0., -20.]),
+                material: grey_material(),
+            }));
+            let ray = Ray {
+                origin: Point::zero(),
+                direction: Vector3 {
+                    x: 0.,
+                    y: 0.2,
+                    z: -1.,
+                }
+                .normalize(),
+            };
+            cast_ray(&scene, &ray, 0, &mut Rng::new(0))
+        };
+
+        // Half transparent is halfway between glass and a diffuse surface, instead of
+        // half of the glass and nothing else
+        let (glass, diffuse, half) = (look(1.0), look(0.0), look(0.5));
+        assert!(glass.red > 0.1 && diffuse.red > 0.1);
+        let expected = (glass.red + diffuse.red) / 2.;
+        assert!((half.red - expected).abs() < 1e-4);
+    }
+
+    fn grey_material() -> Material {
+        Material {
+            surface_type: SurfaceType::Diffuse,
+            color: Coloration::Color(Color {
+                red: 0.5,
+                green: 0.5,
+                blue: 0.5,
+            }),
+            albedo: 0.18,
+            emission: None,
+            specular: None,
+        }
+    }
+
+    #[test]
+    fn test_triangle_intersect() {
+        let triangle = Triangle {
+            v0: Point {
+                x: -1.,
+                y: -1.,
+                z: -5.,
+            },
+            v1: Point {
+                x: 1.,
+                y: -1.,
+                z: -5.,
+            },
+            v2: Point {
+                x: 0.,
+                y: 1.,
+                z: -5.,
+            },
+            material: grey_material(),
+        };
+
+        let ray = Ray {
+            direction: Vector3 {
+                x: 0.,
+                y: 0.,
+                z: -1.,
+            },
+            origin: Point::zero(),
+        };
+
+        let ray2 = Ray {
+            direction: Vector3 {
+                x: 1.,
+                y: 1.,
+                z: -1.,
+            }
+            .normalize(),
+            origin: Point::zero(),
+        };
+
+        let distance = triangle.intersect(&ray).unwrap();
+        assert!((distance - 5.).abs() < 0.0001);
+        assert!(triangle.intersect(&ray2).is_none());
+
+        // Counter-clockwise vertices face the camera
+        let normal = triangle.surface_normal(&Point {
+            x: 0.,
+            y: 0.,
+            z: -5.,
+        });
+        assert!((normal.z - 1.).abs() < 0.0001);
+    }
+
+    #[test]
+    fn test_mesh_interpolates_normals_and_texture_coords() {
+        let up = Vector3 {
+            x: 0.,
+            y: 0.,
+            z: 1.,
+        };
+        let tilted = Vector3 {
+            x: 1.,
+            y: 0.,
+            z: 1.,
+        }
+        .normalize();
+
+        // A unit square in the z = 0 plane made of two triangles
+        let mesh = Mesh::new(
+            vec![
+                Point::from_list([0., 0., 0.]),
+                Point::from_list([1., 0., 0.]),
+                Point::from_list([1., 1., 0.]),
+                Point::from_list([0., 1., 0.]),
+            ],
+            vec![up, tilted, tilted, up],
+            vec![
+                TextureCoords { x: 0., y: 0. },
+                TextureCoords { x: 1., y: 0. },
+                TextureCoords { x: 1., y: 1. },
+                TextureCoords { x: 0., y: 1. },
+            ],
+            vec![[0, 1, 2], [0, 2, 3]],
+            grey_material(),
+        );
+
+        let ray = Ray {
+            direction: Vector3 {
+                x: 0.,
+                y: 0.,
+                z: -1.,
+            },
+            origin: Point::from_list([0.25, 0.75, 1.]),
+        };
+
+        let distance = mesh.intersect(&ray).unwrap();
+        assert!((distance - 1.).abs() < 0.0001);
+
+        let hit_point = Point::from_list([0.25, 0.75, 0.]);
+        let coords = mesh.texture_coords(&hit_point);
+        assert!((coords.x - 0.25).abs() < 0.0001);
+        assert!((coords.y - 0.75).abs() < 0.0001);
+
+        // Halfway between the two columns of normals
+        let normal = mesh.surface_normal(&Point::from_list([0.5, 0.5, 0.]));
+        let expected = (up + tilted).normalize();
+        assert!((normal.x - expected.x).abs() < 0.0001);
+        assert!((normal.z - expected.z).abs() < 0.0001);
+        // Points off the mesh fall back to the nearest face
+        let off = mesh.surface_normal(&Point::from_list([0.5, 0.5, 3.]));
+        assert!((off.x - expected.x).abs() < 0.0001);
+        assert!((off.z - expected.z).abs() < 0.0001);
+        let coords = mesh.texture_coords(&Point::from_list([0.25, 0.75, 3.]));
+        assert!((coords.x - 0.25).abs() < 0.0001);
+        assert!((coords.y - 0.75).abs() < 0.0001);
+
+        // Traced hits remember the face they hit, also through instances
+        let mut scene = Scene::new(1, 1, 90.0, 1e-6, 1);
+        scene.add_element(Element::Instance(Instance {
+            object: Arc::new(Element::Mesh(mesh)),
+            transform: Transform::new(Matrix4::translation(Vector3 {
+                x: 0.,
+                y: 0.,
+                z: -2.,
+            }))
+            .unwrap(),
+        }));
+        let down = Ray {
+            origin: Point::from_list([0.25, 0.75, 1.]),
+            ..ray
+        };
+        let intersection = scene.trace(&down).unwrap();
+        let face = intersection.face.expect("expected the face that was hit");
+        assert_eq!(face.face, 1);
+        let (w, u, v) = face.barycentric;
+        assert!((w - 0.25).abs() < 1e-9 && (u - 0.25).abs() < 1e-9 && (v - 0.5).abs() < 1e-9);
+        let hit_point = Point::from_list([0.25, 0.75, -2.]);
+        let coords = intersection.texture_coords(&hit_point);
+        assert!((coords.x - 0.25).abs() < 0.0001);
+        assert!((coords.y - 0.75).abs() < 0.0001);
+        let normal = intersection.surface_normal(&hit_point);
+        let expected = (up * 0.75 + tilted * 0.25).normalize();
+        assert!(normal.dot(&expected) > 1. - 1e-9);
+    }
+
+    #[test]
+    fn test_bvh_trace_matches_linear_trace() {
+        let mut scene = Scene::new(40, 30, 90.0, 1e-6, 3);
+        for i in 0..50 {
+            let angle = i as f64 * 0.7;
+            scene.add_element(Element::Sphere(Sphere {
+                center: Point {
+                    x: angle.cos() * 3.0,
+                    y: angle.sin() * 3.0,
+                    z: -5.0 - i as f64 * 0.3,
+                },
+                radius: 0.5,
+                material: grey_material(),
+            }));
+        }
+        scene.add_element(Element::Plane(Plane {
+            normal: Vector3 {
+                x: 0.,
+                y: -1.,
+                z: 0.,
+            },
+            p0: Point {
+                x: 0.,
+                y: -2.,
+                z: 0.,
+            },
+            material: grey_material(),
+        }));
+
+        let rays: Vec<Ray> = (0..scene.height)
+            .flat_map(|y| (0..scene.width).map(move |x| (x, y)))
+            .map(|(x, y)| Ray::create_prime(x, y, &scene))
+            .collect();
+        let linear: Vec<Option<f64>> = rays
+            .iter()
+            .map(|r| scene.trace_linear(r).map(|i| i.distance))
+            .collect();
+        let with_bvh: Vec<Option<f64>> = rays
+            .iter()
+            .map(|r| scene.trace(r).map(|i| i.distance))
+            .collect();
+
+        assert_eq!(linear, with_bvh);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parallel_render_matches_serial() {
+        use crate::{render_pixels, render_row};
+
+        let scene = crate::test_scene::test_scene(64, 48);
+
+        let parallel = render_pixels(&scene);
+        let serial: Vec<Color> = (0..scene.height)
+            .flat_map(|y| render_row(&scene, y))
+            .collect();
+
+        assert_eq!(parallel.len(), serial.len());
+        for (p, s) in parallel.iter().zip(serial.iter()) {
+            assert_eq!(p.red.to_bits(), s.red.to_bits());
+            assert_eq!(p.green.to_bits(), s.green.to_bits());
+            assert_eq!(p.blue.to_bits(), s.blue.to_bits());
+        }
+    }
+
+    #[test]
+    fn test_camera_can_look_in_any_direction() {
+        let mut scene = Scene::new(11, 11, 60.0, 1e-6, 3);
+        scene.add_element(Element::Sphere(Sphere {
+            center: Point {
+                x: 3.,
+                y: 4.,
+                z: 5.,
+            },
+            radius: 1.0,
+            material: grey_material(),
+        }));
+
+        let center = Ray::create_prime(5, 5, &scene);
+        assert!(scene.trace(&center).is_none());
+
+        scene.camera = Camera {
+            position: Point {
+                x: 3.,
+                y: 4.,
+                z: 15.,
+            },
+            look_at: Point {
+                x: 3.,
+                y: 4.,
+                z: 5.,
+            },
+            up: Vector3 {
+                x: 0.,
+                y: 1.,
+                z: 0.,
+            },
+            fov: 60.,
+            aspect: None,
+        };
+
+        let center = Ray::create_prime(5, 5, &scene);
+        let distance = scene.trace(&center).unwrap().distance;
+        assert!((distance - 9.).abs() < 0.0001);
+
+        // The click-to-place feature in the browser puts objects under the cursor
+        let placed = scene.pixel_to_world_coordinates(5, 5, -3.0);
+        assert!(placed.distance(&Point::from_list([3., 4., 12.])) < 0.0001);
+    }
+
+    #[test]
+    fn test_anti_aliasing_blends_edges() {
+        use crate::render_pixels;
+
+        // A flat, evenly lit triangle in front of a black background
+        let mut scene = Scene::new(16, 16, 90.0, 1e-6, 3);
+        scene.add_element(Element::Triangle(Triangle {
+            v0: Point::from_list([-0.7, -0.6, -1.]),
+            v1: Point::from_list([0.8, -0.7, -1.]),
+            v2: Point::from_list([0.1, 0.75, -1.]),
+            material: grey_material(),
+        }));
+        scene.add_light(Light::Directional(DirectionalLight {
+            direction: Vector3 {
+                x: 0.,
+                y: 0.,
+                z: -1.,
+            },
+            color: Color {
+                red: 1.,
+                green: 1.,
+                blue: 1.,
+            },
+            intensity: 1.,
+        }));
+
+        let lit = render_pixels(&scene)
+            .iter()
+            .map(|c| c.red)
+            .fold(0.0, f32::max);
+        let is_partial = |c: &Color| c.red > 0.001 && c.red < lit - 0.001;
+
+        assert!(!render_pixels(&scene).iter().any(is_partial));
+
+        for filter in [
+            Filter::Box,
+            Filter::Tent,
+            Filter::Gaussian,
+            Filter::Mitchell,
+        ] {
+            scene.samples_per_pixel = 16;
+            scene.filter = filter;
+            assert!(render_pixels(&scene).iter().any(is_partial));
+        }
+    }
+
+    fn white_material() -> Material {
+        Material {
+            surface_type: SurfaceType::Diffuse,
+            color: Coloration::Color(Color {
+                red: 1.,
+                green: 1.,
+                blue: 1.,
+            }),
+            albedo: 0.8,
+            emission: None,
+            specular: None,
+        }
+    }
+
+    /// A white sphere above a white floor, lit from straight above.
+    fn sphere_over_floor() -> Scene {
+        let mut scene = Scene::new(32, 32, 90.0, 1e-6, 8);
+        scene.add_element(Element::Sphere(Sphere {
+            center: Point::from_list([0., 0., -5.]),
+            radius: 1.0,
+            material: white_material(),
+        }));
+        scene.add_element(Element::Plane(Plane {
+            p0: Point::from_list([0., -1.5, 0.]),
+            normal: Vector3 {
+                x: 0.,
+                y: -1.,
+                z: 0.,
+            },
+            material: white_material(),
+        }));
+        scene.add_light(Light::Directional(DirectionalLight {
+            direction: Vector3 {
+                x: 0.,
+                y: -1.,
+                z: 0.,
+            },
+            color: Color {
+                red: 1.,
+                green: 1.,
+                blue: 1.,
+            },
+            intensity: 2.,
+        }));
+        scene
+    }
+
+    #[test]
+    fn test_path_tracing_lights_surfaces_indirectly() {
+        use crate::path_tracing::trace_path;
+
+        let scene = sphere_over_floor();
+
+        // The underside of the sphere faces away from the light, and only sees the floor
+        let ray = Ray {
+            origin: Point::zero(),
+            direction: Vector3 {
+                x: 0.,
+                y: -0.8,
+                z: -4.4,
+            }
+            .normalize(),
+        };
+        let hit = scene.trace(&ray).unwrap();
+        assert!(matches!(hit.object, Element::Sphere(_)));
+        assert_eq!(cast_ray(&scene, &ray, 0, &mut Rng::new(0)).red, 0.0);
+
+        let mut rng = Rng::new(7);
+        let n = 512;
+        let mut total = 0.0;
+        for _ in 0..n {
+            total += trace_path(&scene, &ray, &mut rng).red;
+        }
+        assert!(total / n as f32 > 0.01);
+    }
+
+    #[test]
+    fn test_path_tracing_matches_whitted_for_direct_light() {
+        use crate::path_tracing::trace_path;
+
+        let mut scene = sphere_over_floor();
+        scene.elements_mut().remove(0);
+
+        // With nothing but the floor, there is no indirect light to add
+        let ray = Ray {
+            origin: Point::zero(),
+            direction: Vector3 {
+                x: 0.2,
+                y: -1.,
+                z: -1.,
+            }
+            .normalize(),
+        };
+        let whitted = cast_ray(&scene, &ray, 0, &mut Rng::new(0));
+        let path = trace_path(&scene, &ray, &mut Rng::new(1));
+        assert!(whitted.red > 0.0);
+        assert!((whitted.red - path.red).abs() < 1e-5);
+
+        scene.integrator = Integrator::PathTracing;
+        assert!((render(&scene).to_rgb8().get_pixel(16, 31)[0] as i32) > 0);
+    }
+
+    /// The light arriving at a point from `light`, before it is reflected.
+    fn incident_light(
+        scene: &Scene,
+        light: &Light,
+        hit_point: Vector3,
+        surface_normal: Vector3,
+        rng: &mut Rng,
+    ) -> Color {
+        crate::direct_light(scene, light, hit_point, surface_normal, rng, &|_| WHITE)
+    }
+
+    #[test]
+    fn test_shadow_rays_start_at_the_shadow_bias() {
+        // A disk just above the hit point, closer than the larger bias
+        let scene_with_bias = |shadow_bias| {
+            let mut scene = Scene::new(1, 1, 90.0, shadow_bias, 1);
+            scene.add_element(Element::Disk(Disk {
+                center: Point::from_list([0., 1e-4, 0.]),
+                normal: axis_y(),
+                radius: 1.,
+                material: grey_material(),
+            }));
+            scene
+        };
+        let white = Color {
+            red: 1.,
+            green: 1.,
+            blue: 1.,
+        };
+        let lights = [
+            Light::Directional(DirectionalLight {
+                direction: axis_y() * -1.,
+                color: white,
+                intensity: 1.,
+            }),
+            Light::Spherical(SphericalLight {
+                position: Point::from_list([0., 2., 0.]),
+                color: white,
+                intensity: 100.,
+            }),
+            Light::Sphere(SphereLight {
+                center: Point::from_list([0., 2., 0.]),
+                radius: 0.1,
+                color: white,
+                intensity: 100.,
+                samples: 4,
+            }),
+        ];
+        for light in lights.iter() {
+            let lit = |shadow_bias| {
+                let scene = scene_with_bias(shadow_bias);
+                incident_light(&scene, light, Vector3::zero(), axis_y(), &mut Rng::new(0)).red
+            };
+            assert_eq!(lit(1e-6), 0.);
+            assert!(lit(1e-3) > 0.);
+        }
+    }
+
+    #[test]
+    fn test_background_shadow_rays_start_at_the_shadow_bias() {
+        let lit = |shadow_bias| {
+            let mut scene = Scene::new(1, 1, 90.0, shadow_bias, 1);
+            scene.background = Background::Color(WHITE);
+            scene.add_element(Element::Disk(Disk {
+                center: Point::from_list([0., 1e-4, 0.]),
+                normal: axis_y(),
+                radius: 1.,
+                material: grey_material(),
+            }));
+            let mut rng = Rng::new(0);
+            light_from_elements(&scene, Vector3::zero(), axis_y(), &mut rng, &|_| WHITE).red
+        };
+        // Only rays grazing past the edge of the disk see the background
+        assert!(lit(1e-6) < 0.01);
+        assert!(lit(1e-3) > 0.9);
+    }
+
+    #[test]
+    fn test_small_area_lights_match_point_light() {
+        let scene = Scene::new(1, 1, 90.0, 1e-6, 1);
+        let white = Color {
+            red: 1.,
+            green: 1.,
+            blue: 1.,
+        };
+        let hit_point = Vector3::zero();
+        let up = Vector3 {
+            x: 0.,
+            y: 1.,
+            z: 0.,
+        };
+        let light_position = Point::from_list([1., 10., 0.]);
+        let point = Light::Spherical(SphericalLight {
+            position: light_position,
+            color: white,
+            intensity: 1000.,
+        });
+        let expected = incident_light(&scene, &point, hit_point, up, &mut Rng::new(0)).red;
+
+        // A small sphere looks like a point light of the same power. A flat light only
+        // shines into one half space, and most strongly along its normal, so facing the
+        // hit point it is four times as bright, times the cosine at the light.
+        let area_lights = [
+            (
+                Light::Sphere(SphereLight {
+                    center: light_position,
+                    radius: 0.1,
+                    color: white,
+                    intensity: 1000.,
+                    samples: 16,
+                }),
+                1.0,
+            ),
+            (
+                Light::Disk(DiskLight {
+                    center: light_position,
+                    normal: light_position.as_vector() * -1.,
+                    radius: 0.1,
+                    color: white,
+                    intensity: 1000.,
+                    samples: 16,
+                }),
+                0.25,
+            ),
+            (
+                Light::Rectangle(RectangleLight {
+                    corner: light_position,
+                    edge1: Vector3 {
+                        x: 0.1,
+                        y: 0.,
+                        z: 0.,
+                    },
+                    edge2: Vector3 {
+                        x: 0.,
+                        y: 0.,
+                        z: 0.1,
+                    },
+                    color: white,
+                    intensity: 1000.,
+                    samples: 16,
+                }),
+                101.0_f32.sqrt() / 40.0,
+            ),
+        ];
+        for (light, ratio) in area_lights.iter() {
+            let light_color = incident_light(&scene, light, hit_point, up, &mut Rng::new(0));
+            assert!((light_color.red * ratio / expected - 1.0).abs() < 0.02);
+        }
+
+        // Flat lights only shine to one side
+        let flipped = Light::Disk(DiskLight {
+            center: light_position,
+            normal: light_position.as_vector(),
+            radius: 0.1,
+            color: white,
+            intensity: 1000.,
+            samples: 16,
+        });
+        let light_color = incident_light(&scene, &flipped, hit_point, up, &mut Rng::new(0));
+        assert_eq!(light_color.red, 0.0);
+    }
+
+    #[test]
+    fn test_area_light_casts_soft_shadows() {
+        // A square light above a blocker that covers the half space x < 0
+        let mut scene = Scene::new(1, 1, 90.0, 1e-6, 1);
+        scene.add_element(Element::Triangle(Triangle {
+            v0: Point::from_list([0., 1., -100.]),
+            v1: Point::from_list([0., 1., 100.]),
+            v2: Point::from_list([-100., 1., 0.]),
+            material: grey_material(),
+        }));
+        let light = Light::Rectangle(RectangleLight {
+            corner: Point::from_list([-1., 2., -1.]),
+            edge1: Vector3 {
+                x: 2.,
+                y: 0.,
+                z: 0.,
+            },
+            edge2: Vector3 {
+                x: 0.,
+                y: 0.,
+                z: 2.,
+            },
+            color: Color {
+                red: 1.,
+                green: 1.,
+                blue: 1.,
+            },
+            intensity: 100.,
+            samples: 64,
+        });
+        let up = Vector3 {
+            x: 0.,
+            y: 1.,
+            z: 0.,
+        };
+        let mut rng = Rng::new(3);
+        let mut light_at =
+            |x: f64| incident_light(&scene, &light, Vector3 { x, y: 0., z: 0. }, up, &mut rng).red;
+
+        let umbra = light_at(-1.5);
+        let penumbra = light_at(0.);
+        let lit = light_at(1.);
+        assert_eq!(umbra, 0.0);
+        assert!(penumbra > 0.0);
+        assert!(penumbra < lit);
+    }
+
+    #[test]
+    fn test_spot_light_cone() {
+        let scene = Scene::new(1, 1, 90.0, 1e-6, 1);
+        let white = Color {
+            red: 1.,
+            green: 1.,
+            blue: 1.,
+        };
+        let up = Vector3 {
+            x: 0.,
+            y: 1.,
+            z: 0.,
+        };
+        let position = Point::from_list([0., 10., 0.]);
+        let spot = Light::Spot(SpotLight {
+            position,
+            direction: Vector3 {
+                x: 0.,
+                y: -1.,
+                z: 0.,
+            },
+            inner_angle: 20.,
+            outer_angle: 30.,
+            color: white,
+            intensity: 1000.,
+            profile: None,
+        });
+        let point = Light::Spherical(SphericalLight {
+            position,
+            color: white,
+            intensity: 1000.,
+        });
+
+        // Points on the floor at 10, 25 and 40 degrees from the spot's direction
+        let light_at = |degrees: f64, light: &Light| {
+            let x = 10. * degrees.to_radians().tan();
+            incident_light(
+                &scene,
+                light,
+                Vector3 { x, y: 0., z: 0. },
+                up,
+                &mut Rng::new(0),
+            )
+            .red
+        };
+        assert_eq!(light_at(10., &spot), light_at(10., &point));
+        assert!(light_at(25., &spot) > 0.0);
+        assert!(light_at(25., &spot) < light_at(25., &point));
+        assert_eq!(light_at(40., &spot), 0.0);
+    }
+
+    #[test]
+    fn test_glowing_sphere_lights_the_scene() {
+        let mut scene = Scene::new(32, 32, 90.0, 1e-6, 3);
+        scene.add_element(Element::Plane(Plane {
+            p0: Point::from_list([0., -1., 0.]),
+            normal: Vector3 {
+                x: 0.,
+                y: -1.,
+                z: 0.,
+            },
+            material: white_material(),
+        }));
+        let mut lamp = white_material();
+        lamp.emission = Some(Emission {
+            color: Color {
+                red: 1.,
+                green: 0.5,
+                blue: 0.,
+            },
+            strength: 2.,
+        });
+        scene.add_element(Element::Sphere(Sphere {
+            center: Point::from_list([0., 0., -3.]),
+            radius: 0.5,
+            material: lamp,
+        }));
+
+        // There are no lights, so everything comes from the sphere
+        let lamp_ray = Ray::create_prime(16, 16, &scene);
+        let floor_ray = Ray {
+            origin: Point::zero(),
+            direction: Vector3 {
+                x: 0.,
+                y: -1.,
+                z: -2.,
+            }
+            .normalize(),
+        };
+        for integrator in [Integrator::Whitted, Integrator::PathTracing] {
+            scene.integrator = integrator;
+            let mut rng = Rng::new(5);
+            let lamp_color = crate::radiance(&scene, &lamp_ray, &mut rng);
+            assert!(lamp_color.red >= 2.0);
+            assert!((lamp_color.blue - 0.0).abs() < 0.0001);
+
+            let n = 256;
+            let mut floor = BLACK;
+            for _ in 0..n {
+                floor = floor + crate::radiance(&scene, &floor_ray, &mut rng) * (1.0 / n as f32);
+            }
+            assert!(floor.red > 0.0);
+            assert!(floor.red > floor.green);
+            assert_eq!(floor.blue, 0.0);
+        }
+    }
+
+    #[test]
+    fn test_glowing_mesh_matches_area_light() {
+        // A square mesh glowing downwards, and a light with the same radiance
+        let corner = Point::from_list([-1., 4., -1.]);
+        let (edge1, edge2) = (
+            Vector3 {
+                x: 2.,
+                y: 0.,
+                z: 0.,
+            },
+            Vector3 {
+                x: 0.,
+                y: 0.,
+                z: 2.,
+            },
+        );
+        let vertices = vec![
+            corner,
+            (corner.as_vector() + edge1).as_point(),
+            (corner.as_vector() + edge1 + edge2).as_point(),
+            (corner.as_vector() + edge2).as_point(),
+        ];
+        let mut lamp = white_material();
+        lamp.emission = Some(Emission {
+            color: Color {
+                red: 1.,
+                green: 1.,
+                blue: 1.,
+            },
+            strength: 10.,
+        });
+        let mut scene = Scene::new(1, 1, 90.0, 1e-6, 1);
+        scene.add_element(Element::Mesh(Mesh::new(
+            vertices,
+            vec![],
+            vec![],
+            vec![[0, 1, 2], [0, 2, 3]],
+            lamp,
+        )));
+        let light = Light::Rectangle(RectangleLight {
+            corner,
+            edge1,
+            edge2,
+            color: Color {
+                red: 1.,
+                green: 1.,
+                blue: 1.,
+            },
+            // Radiance is intensity / (pi * area)
+            intensity: 10. * std::f32::consts::PI * 4.,
+            samples: 1,
+        });
+
+        let up = Vector3 {
+            x: 0.,
+            y: 1.,
+            z: 0.,
+        };
+        let hit_point = Vector3 {
+            x: 0.5,
+            y: 0.,
+            z: 0.,
+        };
+        let (mut from_mesh, mut from_light) = (0.0, 0.0);
+        let mut rng = Rng::new(9);
+        for _ in 0..4000 {
+            from_mesh += light_from_elements(&scene, hit_point, up, &mut rng, &|_| WHITE).red;
+            from_light += incident_light(&scene, &light, hit_point, up, &mut rng).red;
+        }
+        assert!((from_mesh / from_light - 1.0).abs() < 0.03);
+    }
+
+    #[test]
+    fn test_specular_highlights() {
+        use crate::shade_diffuse;
+
+        // A glossy floor, lit from the front at 45 degrees
+        let mut glossy = white_material();
+        glossy.specular = Some(Specular {
+            color: WHITE,
+            shininess: 100.,
+        });
+        let mut scene = Scene::new(1, 1, 90.0, 1e-6, 1);
+        scene.add_element(Element::Plane(Plane {
+            p0: Point::zero(),
+            normal: Vector3 {
+                x: 0.,
+                y: -1.,
+                z: 0.,
+            },
+            material: glossy,
+        }));
+        scene.add_light(Light::Directional(DirectionalLight {
+            direction: Vector3 {
+                x: 0.,
+                y: -1.,
+                z: 1.,
+            },
+            color: WHITE,
+            intensity: 0.5,
+        }));
+        let up = Vector3 {
+            x: 0.,
+            y: 1.,
+            z: 0.,
+        };
+        let shade = |scene: &Scene, incident: Vector3| {
+            let floor = Intersection::new(0., &scene.elements()[0]);
+            shade_diffuse(
+                scene,
+                &floor,
+                Vector3::zero(),
+                up,
+                incident.normalize(),
+                &mut Rng::new(0),
+            )
+            .red
+        };
+
+        // Looking along the mirror direction of the light we see the highlight
+        let mirror = shade(
+            &scene,
+            Vector3 {
+                x: 0.,
+                y: -1.,
+                z: -1.,
+            },
+        );
+        let sideways = shade(
+            &scene,
+            Vector3 {
+                x: 1.,
+                y: -1.,
+                z: 0.,
+            },
+        );
+        assert!(mirror > 5.0 * sideways);
+
+        // Highlights are shadowed like the rest of the light
+        scene.add_element(Element::Sphere(Sphere {
+            center: Point::from_list([0., 1., -1.]),
+            radius: 0.5,
+            material: grey_material(),
+        }));
+        assert_eq!(
+            shade(
+                &scene,
+                Vector3 {
+                    x: 0.,
+                    y: -1.,
+                    z: -1.
+                }
+            ),
+            0.0
+        );
+    }
+
+    #[test]
+    fn test_smooth_metal_reflects_like_a_mirror() {
+        use crate::path_tracing::trace_path;
+        use crate::scene::Scalar;
+
+        let mut scene = sphere_over_floor();
+        if let Light::Directional(light) = &mut scene.lights[0] {
+            light.direction = Vector3 {
+                x: 0.,
+                y: 0.,
+                z: -1.,
+            };
+        }
+        // Reflects off the floor, into the lit front of the sphere
+        let ray = Ray {
+            origin: Point::zero(),
+            direction: Vector3 {
+                x: 0.,
+                y: -1.5,
+                z: -3.,
+            }
+            .normalize(),
+        };
+        let set_floor = |scene: &mut Scene, surface_type: SurfaceType| {
+            if let Element::Plane(floor) = &mut scene.elements_mut()[1] {
+                floor.material.surface_type = surface_type;
+            }
+        };
+
+        set_floor(&mut scene, SurfaceType::Reflective { reflectivity: 1.0 });
+        let mirror = cast_ray(&scene, &ray, 0, &mut Rng::new(0)).red;
+        assert!(mirror > 0.0);
+
+        set_floor(
+            &mut scene,
+            SurfaceType::Microfacet {
+                metallic: Scalar::Value(1.0),
+                roughness: Scalar::Value(0.0),
+            },
+        );
+        let metal = cast_ray(&scene, &ray, 0, &mut Rng::new(0)).red;
+        assert!((mirror - metal).abs() < 0.01 * mirror);
+
+        // A rough metal blurs the reflection, so less of the sphere is seen
+        set_floor(
+            &mut scene,
+            SurfaceType::Microfacet {
+                metallic: Scalar::Value(1.0),
+                roughness: Scalar::Value(0.8),
+            },
+        );
+        let mut rng = Rng::new(5);
+        let n = 512;
+        let mut rough = 0.0;
+        for _ in 0..n {
+            rough += trace_path(&scene, &ray, &mut rng).red;
+        }
+        assert!(rough / (n as f32) < 0.9 * mirror);
+    }
+
+    #[test]
+    fn test_background_is_seen_and_lights_the_scene() {
+        use crate::path_tracing::trace_path;
+
+        let mut scene = sphere_over_floor();
+        scene.lights.clear();
+        scene.elements_mut().remove(0);
+        let sky = Color {
+            red: 0.5,
+            green: 0.6,
+            blue: 0.9,
+        };
+        scene.background = Background::Color(sky);
+
+        let up = Ray {
+            origin: Point::zero(),
+            direction: Vector3 {
+                x: 0.,
+                y: 1.,
+                z: 0.,
+            },
+        };
+        assert_eq!(cast_ray(&scene, &up, 0, &mut Rng::new(0)).blue, 0.9);
+        assert_eq!(trace_path(&scene, &up, &mut Rng::new(0)).blue, 0.9);
+
+        // A floor under an evenly lit sky reflects its albedo times the sky
+        let down = Ray {
+            origin: Point::zero(),
+            direction: Vector3 {
+                x: 0.3,
+                y: -1.,
+                z: -1.,
+            }
+            .normalize(),
+        };
+        let whitted = cast_ray(&scene, &down, 0, &mut Rng::new(0));
+        assert!((whitted.blue - 0.8 * 0.9).abs() < 1e-4);
+        let path = trace_path(&scene, &down, &mut Rng::new(1));
+        assert!((path.blue - 0.8 * 0.9).abs() < 1e-4);
+
+        // Without background samples, paths still find the sky by hitting it
+        scene.background_samples = 0;
+        let unsampled = trace_path(&scene, &down, &mut Rng::new(1));
+        assert!((unsampled.blue - 0.8 * 0.9).abs() < 1e-4);
+
+        // The sphere shadows the floor below it
+        let mut scene_with_sphere = sphere_over_floor();
+        scene_with_sphere.lights.clear();
+        scene_with_sphere.background = Background::Color(sky);
+        let below_sphere = Ray {
+            origin: Point::zero(),
+            direction: Vector3 {
+                x: 0.,
+                y: -1.5,
+                z: -5.,
+            }
+            .normalize(),
+        };
+        let shadowed = cast_ray(&scene_with_sphere, &below_sphere, 0, &mut Rng::new(0));
+        assert!(shadowed.blue < 0.9 * whitted.blue);
+    }
+
+    #[test]
+    fn test_bright_light_is_not_clipped_before_output() {
+        let mut scene = sphere_over_floor();
+        scene.elements_mut().remove(0);
+        if let Light::Directional(light) = &mut scene.lights[0] {
+            light.intensity = 100.;
+        }
+        let ray = Ray {
+            origin: Point::zero(),
+            direction: Vector3 {
+                x: 0.,
+                y: -1.,
+                z: -1.,
+            }
+            .normalize(),
+        };
+        // White floor with albedo 0.8: 100 * 0.8 / pi
+        let color = cast_ray(&scene, &ray, 0, &mut Rng::new(0));
+        assert!((color.red - 80. / std::f32::consts::PI).abs() < 1e-3);
+
+        // Clipped to white by default, while a tone mapper keeps it below white
+        scene.width = 1;
+        scene.height = 1;
+        scene.camera.look_at = Point::from_list([0., -1., -1.]);
+        assert_eq!(render(&scene).to_rgb8().get_pixel(0, 0)[0], 255);
+        scene.tone_map = ToneMap::Aces;
+        scene.exposure = -5.;
+        assert!(render(&scene).to_rgb8().get_pixel(0, 0)[0] < 255);
+
+        // High dynamic range output keeps the light as it is, scaled by the exposure
+        let hdr = crate::render_hdr(&scene);
+        assert!((hdr.get_pixel(0, 0)[0] - color.red / 32.).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_instance_transforms_rays_and_normals() {
+        let sphere = Arc::new(Element::Sphere(Sphere {
+            center: Point::zero(),
+            radius: 1.0,
+            material: grey_material(),
+        }));
+        // Stretched to twice its width, then moved away from the camera
+        let transform = Transform::new(
+            Matrix4::translation(Vector3 {
+                x: 0.,
+                y: 0.,
+                z: -5.,
+            }) * Matrix4::scaling(Vector3 {
+                x: 2.,
+                y: 1.,
+                z: 1.,
+            }),
+        )
+        .unwrap();
+        let instance = Element::Instance(Instance {
+            object: Arc::clone(&sphere),
+            transform,
+        });
+
+        let ahead = Ray {
+            origin: Point::zero(),
+            direction: Vector3 {
+                x: 0.,
+                y: 0.,
+                z: -1.,
+            },
+        };
+        assert!((instance.intersect(&ahead).unwrap() - 4.).abs() < 1e-9);
+
+        // Distances are measured in the world, not in the object's space
+        let sideways = Ray {
+            origin: Point::from_list([-10., 0., -5.]),
+            direction: Vector3 {
+                x: 1.,
+                y: 0.,
+                z: 0.,
+            },
+        };
+        assert!((instance.intersect(&sideways).unwrap() - 8.).abs() < 1e-9);
+
+        // The normal tilts towards the flatter side of the ellipsoid
+        let angle = std::f64::consts::FRAC_PI_4;
+        let hit_point = Point::from_list([2. * angle.cos(), angle.sin(), -5.]);
+        let normal = instance.surface_normal(&hit_point);
+        let expected = Vector3 {
+            x: angle.cos() / 2.,
+            y: angle.sin(),
+            z: 0.,
+        }
+        .normalize();
+        assert!((normal.dot(&expected) - 1.).abs() < 1e-9);
+
+        let bounds = instance.bounding_box().unwrap();
+        assert!((bounds.min.x + 2.).abs() < 1e-9 && (bounds.max.z + 4.).abs() < 1e-9);
+        assert!(!instance.is_light_source());
+    }
+
+    #[test]
+    fn test_instances_render_like_placed_elements() {
+        let light = || {
+            Light::Directional(DirectionalLight {
+                direction: Vector3 {
+                    x: -1.,
+                    y: -1.,
+                    z: -1.,
+                },
+                color: WHITE,
+                intensity: 10.,
+            })
+        };
+        let mut placed = Scene::new(40, 30, 90.0, 1e-6, 3);
+        placed.add_light(light());
+        let mut instanced = Scene::new(40, 30, 90.0, 1e-6, 3);
+        instanced.add_light(light());
+
+        let sphere = Arc::new(Element::Sphere(Sphere {
+            center: Point::zero(),
+            radius: 1.0,
+            material: grey_material(),
+        }));
+        for x in [-2., 0., 2.] {
+            placed.add_element(Element::Sphere(Sphere {
+                center: Point::from_list([x, 0., -5.]),
+                radius: 1.0,
+                material: grey_material(),
+            }));
+            instanced.add_element(Element::Instance(Instance {
+                object: Arc::clone(&sphere),
+                transform: Transform::new(Matrix4::translation(Vector3 { x, y: 0., z: -5. }))
+                    .unwrap(),
+            }));
+        }
+
+        let expected = render(&placed).to_rgb8();
+        let actual = render(&instanced).to_rgb8();
+        for (a, b) in expected.pixels().zip(actual.pixels()) {
+            for channel in 0..3 {
+                assert!((a[channel] as i32 - b[channel] as i32).abs() <= 1);
+            }
+        }
+    }
+
+    /// Checks the distance to the nearest hit, and the normal there.
+    fn assert_hit(
+        element: &Element,
+        origin: [f64; 3],
+        direction: [f64; 3],
+        distance: f64,
+        normal: [f64; 3],
+    ) {
+        let ray = Ray {
+            origin: Point::from_list(origin),
+            direction: Point::from_list(direction).as_vector().normalize(),
+        };
+        let hit = element.intersect(&ray).expect("expected a hit");
+        assert!(
+            (hit - distance).abs() < 1e-6,
+            "hit at {} instead of {}",
+            hit,
+            distance
+        );
+        let hit_point = (ray.origin.as_vector() + ray.direction * hit).as_point();
+        let expected = Point::from_list(normal).as_vector().normalize();
+        assert!(element.normal(&hit_point).dot(&expected) > 1. - 1e-6);
+        assert!(element.bounding_box().unwrap().contains(&hit_point, 1e-9));
+    }
+
+    fn axis_y() -> Vector3 {
+        Vector3 {
+            x: 0.,
+            y: 1.,
+            z: 0.,
+        }
+    }
+
+    #[test]
+    fn test_solid_primitives() {
+        let cuboid = Element::Cuboid(Cuboid {
+            min: Point::from_list([-1., -1., -6.]),
+            max: Point::from_list([1., 2., -4.]),
+            material: grey_material(),
+        });
+        assert_hit(&cuboid, [0., 0., 0.], [0., 0., -1.], 4., [0., 0., 1.]);
+        assert_hit(&cuboid, [5., 0., -5.], [-1., 0., 0.], 4., [1., 0., 0.]);
+        // From inside, as refracted rays are
+        assert_hit(&cuboid, [0., 0., -5.], [0., 1., 0.], 2., [0., 1., 0.]);
+        let coords = cuboid.texture_coords(&Point::from_list([0., 0.5, -4.]));
+        assert!((coords.x - 0.5).abs() < 1e-6 && (coords.y - 0.5).abs() < 1e-6);
+
+        let cylinder = Element::Cylinder(Cylinder {
+            base: Point::from_list([0., -1., -5.]),
+            axis: axis_y(),
+            radius: 1.,
+            height: 2.,
+            material: grey_material(),
+        });
+        assert_hit(&cylinder, [0., 0., 0.], [0., 0., -1.], 4., [0., 0., 1.]);
+        assert_hit(&cylinder, [0., 5., -5.], [0., -1., 0.], 4., [0., 1., 0.]);
+        assert_hit(&cylinder, [0.5, -5., -5.], [0., 1., 0.], 4., [0., -1., 0.]);
+        // Over the top
+        let above = Ray {
+            origin: Point::from_list([0., 1.5, 0.]),
+            direction: Vector3 {
+                x: 0.,
+                y: 0.,
+                z: -1.,
+            },
+        };
+        assert!(cylinder.intersect(&above).is_none());
+        let coords = cylinder.texture_coords(&Point::from_list([0., 0., -4.]));
+        assert!((coords.y - 0.5).abs() < 1e-6);
+        let coords = cylinder.texture_coords(&Point::from_list([0., 1., -5.]));
+        assert!((coords.x - 0.5).abs() < 1e-6 && (coords.y - 0.5).abs() < 1e-6);
+
+        let cone = Element::Cone(Cone {
+            base: Point::from_list([0., -1., -5.]),
+            axis: axis_y(),
+            radius: 1.,
+            height: 2.,
+            material: grey_material(),
+        });
+        // Halfway up, the cone is half as wide, and the side leans back
+        assert_hit(&cone, [0., 0., 0.], [0., 0., -1.], 4.5, [0., 1., 2.]);
+        assert_hit(&cone, [0., 5., -5.], [0., -1., 0.], 4., [0., 1., 0.]);
+        assert_hit(&cone, [0., -5., -5.], [0., 1., 0.], 4., [0., -1., 0.]);
+        // Not the mirrored cone above the tip
+        let above = Ray {
+            origin: Point::from_list([0., 1.5, 0.]),
+            direction: Vector3 {
+                x: 0.,
+                y: 0.,
+                z: -1.,
+            },
+        };
+        assert!(cone.intersect(&above).is_none());
+
+        let disk = Element::Disk(Disk {
+            center: Point::from_list([0., 0., -5.]),
+            normal: Vector3 {
+                x: 0.,
+                y: 0.,
+                z: 1.,
+            },
+            radius: 1.,
+            material: grey_material(),
+        });
+        assert_hit(&disk, [0.5, 0.5, 0.], [0., 0., -1.], 5., [0., 0., 1.]);
+        let beside = Ray {
+            origin: Point::from_list([1.5, 0., 0.]),
+            direction: Vector3 {
+                x: 0.,
+                y: 0.,
+                z: -1.,
+            },
+        };
+        assert!(disk.intersect(&beside).is_none());
+        let coords = disk.texture_coords(&Point::from_list([0., 0., -5.]));
+        assert!((coords.x - 0.5).abs() < 1e-6 && (coords.y - 0.5).abs() < 1e-6);
+
+        let torus = Element::Torus(Torus {
+            center: Point::from_list([0., 0., -5.]),
+            axis: axis_y(),
+            major_radius: 2.,
+            minor_radius: 0.5,
+            material: grey_material(),
+        });
+        assert_hit(&torus, [0., 0., 0.], [0., 0., -1.], 2.5, [0., 0., 1.]);
+        assert_hit(&torus, [2., 5., -5.], [0., -1., 0.], 4.5, [0., 1., 0.]);
+        // The inside of the tube, after passing through the hole
+        assert_hit(&torus, [0., 0., -5.], [1., 0., 0.], 1.5, [-1., 0., 0.]);
+        let through_hole = Ray {
+            origin: Point::from_list([0., 5., -5.]),
+            direction: Vector3 {
+                x: 0.,
+                y: -1.,
+                z: 0.,
+            },
+        };
+        assert!(torus.intersect(&through_hole).is_none());
+        // Precise even for rays from far away
+        assert_hit(
+            &torus,
+            [2., 0., 1000.],
+            [0., 0., -1.],
+            1003.5,
+            [0.8, 0., 0.6],
+        );
+        let coords = torus.texture_coords(&Point::from_list([2., 0.5, -5.]));
+        assert!((coords.y - 0.75).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_solid_primitives_sample_their_surface() {
+        let tilted = Vector3 {
+            x: 1.,
+            y: 2.,
+            z: 0.5,
+        }
+        .normalize();
+        let center = Point::from_list([0.5, -1., -5.]);
+        let cuboid = Cuboid {
+            min: Point::from_list([-1., -1., -6.]),
+            max: Point::from_list([1., 2., -4.]),
+            material: grey_material(),
+        };
+        let cylinder = Cylinder {
+            base: center,
+            axis: tilted,
+            radius: 1.,
+            height: 2.,
+            material: grey_material(),
+        };
+        let cone = Cone {
+            base: center,
+            axis: tilted,
+            radius: 1.,
+            height: 2.,
+            material: grey_material(),
+        };
+        let torus = Torus {
+            center,
+            axis: tilted,
+            major_radius: 2.,
+            minor_radius: 0.5,
+            material: grey_material(),
+        };
+
+        let mut rng = Rng::new(3);
+        let samples = (0..200).flat_map(|_| {
+            vec![
+                (
+                    &cuboid as &dyn Intersectable,
+                    cuboid.sample_surface(&mut rng),
+                ),
+                (&cylinder, cylinder.sample_surface(&mut rng)),
+                (&cone, cone.sample_surface(&mut rng)),
+                (&torus, torus.sample_surface(&mut rng)),
+            ]
+        });
+        for (shape, (point, normal)) in samples {
+            // Coming back to the point from just outside finds it, with the same normal
+            let ray = Ray {
+                origin: (point.as_vector() + normal * 0.01).as_point(),
+                direction: normal * -1.,
+            };
+            let distance = shape.intersect(&ray).unwrap();
+            assert!((distance - 0.01).abs() < 1e-6);
+            assert!(shape.surface_normal(&point).dot(&normal) > 1. - 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_glowing_disk_matches_disk_light() {
+        let mut lamp = white_material();
+        lamp.emission = Some(Emission {
+            color: WHITE,
+            strength: 10.,
+        });
+        let center = Point::from_list([0., 4., 0.]);
+        let down = Vector3 {
+            x: 0.,
+            y: -1.,
+            z: 0.,
+        };
+        let mut scene = Scene::new(1, 1, 90.0, 1e-6, 1);
+        scene.add_element(Element::Disk(Disk {
+            center,
+            normal: down,
+            radius: 1.,
+            material: lamp,
+        }));
+        let light = Light::Disk(DiskLight {
+            center,
+            normal: down,
+            radius: 1.,
+            color: WHITE,
+            // Radiance is intensity / (pi * area)
+            intensity: 10. * std::f32::consts::PI * std::f32::consts::PI,
+            samples: 1,
+        });
+
+        let hit_point = Vector3 {
+            x: 0.5,
+            y: 0.,
+            z: 0.,
+        };
+        let (mut from_disk, mut from_light) = (0.0, 0.0);
+        let mut rng = Rng::new(9);
+        for _ in 0..4000 {
+            from_disk += light_from_elements(&scene, hit_point, axis_y(), &mut rng, &|_| WHITE).red;
+            from_light += incident_light(&scene, &light, hit_point, axis_y(), &mut rng).red;
+        }
+        assert!((from_disk / from_light - 1.0).abs() < 0.03);
+    }
+
+    fn grey_sphere(center: [f64; 3], radius: f64) -> Element {
+        Element::Sphere(Sphere {
+            center: Point::from_list(center),
+            radius,
+            material: grey_material(),
+        })
+    }
+
+    #[test]
+    fn test_solids_give_intervals_along_rays() {
+        let ray = Ray {
+            origin: Point::zero(),
+            direction: Vector3 {
+                x: 0.,
+                y: 0.,
+                z: -1.,
+            },
+        };
+        // Behind the ray as well as in front of it
+        let sphere = grey_sphere([0., 0., 2.], 1.);
+        assert_eq!(
+            sphere.intervals(&ray),
+            vec![Interval {
+                enter: -3.,
+                exit: -1.
+            }]
+        );
+        let torus = Element::Torus(Torus {
+            center: Point::from_list([0., 0., -5.]),
+            axis: axis_y(),
+            major_radius: 2.,
+            minor_radius: 0.5,
+            material: grey_material(),
+        });
+        let intervals = torus.intervals(&ray);
+        assert_eq!(intervals.len(), 2);
+        assert!((intervals[0].enter - 2.5).abs() < 1e-6 && (intervals[0].exit - 3.5).abs() < 1e-6);
+        assert!((intervals[1].enter - 6.5).abs() < 1e-6 && (intervals[1].exit - 7.5).abs() < 1e-6);
+
+        // Everything below the floor
+        let floor = Element::Plane(Plane {
+            p0: Point::from_list([0., -1., 0.]),
+            normal: Vector3 {
+                x: 0.,
+                y: -1.,
+                z: 0.,
+            },
+            material: grey_material(),
+        });
+        let down = Ray {
+            origin: Point::zero(),
+            direction: Vector3 {
+                x: 0.,
+                y: -1.,
+                z: 0.,
+            },
+        };
+        assert_eq!(
+            floor.intervals(&down),
+            vec![Interval {
+                enter: 1.,
+                exit: f64::INFINITY
+            }]
+        );
+        assert!(floor.is_solid());
+        assert!(!Element::Triangle(Triangle {
+            v0: Point::zero(),
+            v1: Point::from_list([1., 0., 0.]),
+            v2: Point::from_list([0., 1., 0.]),
+            material: grey_material(),
+        })
+        .is_solid());
+    }
+
+    #[test]
+    fn test_csg_combines_solids() {
+        let ahead = Ray {
+            origin: Point::zero(),
+            direction: Vector3 {
+                x: 0.,
+                y: 0.,
+                z: -1.,
+            },
+        };
+        let combine = |operation| {
+            Element::Csg(Csg {
+                operation,
+                left: Box::new(grey_sphere([0., 0., -5.], 1.)),
+                right: Box::new(grey_sphere([0., 0., -4.], 1.)),
+            })
+        };
+
+        let union = combine(CsgOperation::Union);
+        assert_hit(&union, [0., 0., 0.], [0., 0., -1.], 3., [0., 0., 1.]);
+        let intersection = combine(CsgOperation::Intersection);
+        assert_hit(&intersection, [0., 0., 0.], [0., 0., -1.], 4., [0., 0., 1.]);
+        let bounds = intersection.bounding_box().unwrap();
+        assert!((bounds.min.z + 5.).abs() < 1e-9 && (bounds.max.z + 4.).abs() < 1e-9);
+
+        // The front sphere takes a bite out of the back one, whose hollow faces out
+        let difference = combine(CsgOperation::Difference);
+        assert_hit(&difference, [0., 0., 0.], [0., 0., -1.], 5., [0., 0., 1.]);
+        assert_hit(&difference, [0., 5., -5.], [0., -1., 0.], 4., [0., 1., 0.]);
+        let hollow = Point::from_list([0., 0., -5.]);
+        let normal = difference.normal(&hollow);
+        assert!((normal.z - 1.).abs() < 1e-9);
+
+        // A ray from inside leaves through the nearest surface
+        let inside = Ray {
+            origin: Point::from_list([0., 0., -5.5]),
+            ..ahead
+        };
+        assert!((difference.intersect(&inside).unwrap() - 0.5).abs() < 1e-9);
+
+        // Differences of differences flip the cut surface back
+        let nested = Element::Csg(Csg {
+            operation: CsgOperation::Difference,
+            left: Box::new(grey_sphere([0., 0., -5.], 3.)),
+            right: Box::new(combine(CsgOperation::Difference)),
+        });
+        // The sphere at -5 is cut out of the big one, except where the sphere at -4
+        // overlaps it
+        assert_hit(&nested, [0., 0., 0.], [0., 0., -1.], 2., [0., 0., 1.]);
+        assert_hit(&nested, [0., 0., -2.5], [0., 0., -1.], 2.5, [0., 0., -1.]);
+        assert_hit(&nested, [0., 0., -5.5], [0., 0., -1.], 0.5, [0., 0., 1.]);
+    }
+
+    #[test]
+    fn test_distance_fields_are_traced_like_solids() {
+        let ball = |center: [f64; 3]| Sdf::Translate {
+            offset: Point::from_list(center).as_vector(),
+            shape: Box::new(Sdf::Sphere { radius: 1. }),
+        };
+        let field = Element::DistanceField(DistanceField {
+            sdf: ball([0., 0., -5.]),
+            material: grey_material(),
+        });
+        let sphere = grey_sphere([0., 0., -5.], 1.);
+
+        // Rays towards the sphere from all around agree with the exact sphere
+        let mut rng = Rng::new(5);
+        for _ in 0..50 {
+            let ray = Ray {
+                origin: Point::zero(),
+                direction: Vector3 {
+                    x: rng.next_f64() * 0.3 - 0.15,
+                    y: rng.next_f64() * 0.3 - 0.15,
+                    z: -1.,
+                }
+                .normalize(),
+            };
+            match (field.intersect(&ray), sphere.intersect(&ray)) {
+                (Some(traced), Some(exact)) => {
+                    assert!((traced - exact).abs() < 1e-3);
+                    let hit_point = (ray.origin.as_vector() + ray.direction * exact).as_point();
+                    assert!(field.normal(&hit_point).dot(&sphere.normal(&hit_point)) > 0.999);
+                }
+                (None, None) => {}
+                (traced, exact) => panic!("traced {:?}, exact {:?}", traced, exact),
+            }
+        }
+
+        // Rays leaving the surface do not hit it again, but rays going in find the far
+        // side
+        let on_surface = Point::from_list([0., 0., -4.]);
+        let leaving = Ray {
+            origin: on_surface,
+            direction: Vector3 {
+                x: 0.,
+                y: 0.,
+                z: 1.,
+            },
+        };
+        assert_eq!(field.intersect(&leaving), None);
+        let entering = Ray {
+            origin: on_surface,
+            direction: Vector3 {
+                x: 0.,
+                y: 0.,
+                z: -1.,
+            },
+        };
+        let exit = field.intersect(&entering).expect("expected the far side");
+        assert!((exit - 2.).abs() < 1e-3);
+
+        // Endless repetitions are found without bounds
+        let row = Element::DistanceField(DistanceField {
+            sdf: Sdf::Repeat {
+                period: Vector3 {
+                    x: 0.,
+                    y: 0.,
+                    z: 10.,
+                },
+                shape: Box::new(ball([2., 0., 0.])),
+            },
+            material: grey_material(),
+        });
+        assert!(row.bounding_box().is_none());
+        let along = Ray {
+            origin: Point::from_list([2., 0., -3.]),
+            direction: Vector3 {
+                x: 0.,
+                y: 0.,
+                z: -1.,
+            },
+        };
+        let hit = row.intersect(&along).expect("expected a copy");
+        assert!((hit - 6.).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_heightfield_matches_its_triangles() {
+        let (columns, rows) = (9, 7);
+        let mut rng = Rng::new(11);
+        let heights: Vec<f64> = (0..columns * rows).map(|_| rng.next_f64()).collect();
+        let min = Point::from_list([-2., -1., -8.]);
+        let size = Vector3 {
+            x: 4.,
+            y: 1.5,
+            z: 3.,
+        };
+        let vertex = |column: usize, row: usize| {
+            Point::from_list([
+                min.x + column as f64 * size.x / (columns - 1) as f64,
+                min.y + heights[row * columns + column] * size.y,
+                min.z + row as f64 * size.z / (rows - 1) as f64,
+            ])
+        };
+        let mut triangles = vec![];
+        for row in 0..rows - 1 {
+            for column in 0..columns - 1 {
+                let (a, b) = (vertex(column, row), vertex(column + 1, row));
+                let (c, d) = (vertex(column, row + 1), vertex(column + 1, row + 1));
+                triangles.push([a, b, d]);
+                triangles.push([a, d, c]);
+            }
+        }
+        let heightfield = Heightfield::new(heights.clone(), columns, min, size, grey_material())
+            .expect("expected a heightfield");
+
+        // Rays from all around, including from below and straight down, hit the same
+        // spot as the nearest of all the triangles
+        for _ in 0..500 {
+            let origin = Point::from_list([
+                rng.next_f64() * 8. - 4.,
+                rng.next_f64() * 4. - 1.5,
+                rng.next_f64() * 6. - 9.5,
+            ]);
+            let target = Point::from_list([
+                min.x + rng.next_f64() * size.x,
+                min.y + rng.next_f64() * size.y,
+                min.z + rng.next_f64() * size.z,
+            ]);
+            let ray = Ray {
+                origin,
+                direction: (target - origin).normalize(),
+            };
+            let expected = triangles
+                .iter()
+                .filter_map(|[a, b, c]| intersect_triangle(&ray, a, b, c))
+                .min_by(|a, b| a.partial_cmp(b).unwrap());
+            match (heightfield.intersect(&ray), expected) {
+                (Some(hit), Some(expected)) => assert!((hit - expected).abs() < 1e-9),
+                (None, None) => {}
+                (hit, expected) => panic!("hit {:?} instead of {:?}", hit, expected),
+            }
+        }
+        let peak = vertex(3, 2);
+        let down = Ray {
+            origin: Point {
+                y: peak.y + 5.,
+                ..peak
+            },
+            direction: Vector3 {
+                x: 0.,
+                y: -1.,
+                z: 0.,
+            },
+        };
+        assert!((heightfield.intersect(&down).unwrap() - 5.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_heightfield_normals_and_texture_coords() {
+        // A ramp, rising by 1 over 2 along x
+        let heights = vec![0., 0.5, 1., 0., 0.5, 1., 0., 0.5, 1.];
+        let ramp = Element::Heightfield(
+            Heightfield::new(
+                heights,
+                3,
+                Point::from_list([0., 0., -2.]),
+                Vector3 {
+                    x: 2.,
+                    y: 1.,
+                    z: 2.,
+                },
+                grey_material(),
+            )
+            .unwrap(),
+        );
+        let slope = [-1., 2., 0.];
+        assert_hit(&ramp, [0.5, 5., -0.5], [0., -1., 0.], 4.75, slope);
+        assert_hit(&ramp, [1.5, 5., -1.7], [0., -1., 0.], 4.25, slope);
+        let coords = ramp.texture_coords(&Point::from_list([0.5, 0.25, -0.5]));
+        assert!((coords.x - 0.25).abs() < 1e-6 && (coords.y - 0.75).abs() < 1e-6);
+
+        // A bump in the middle of a flat field leans its normals smoothly away
+        let mut heights = vec![0.; 9];
+        heights[4] = 1.;
+        let bump = Heightfield::new(
+            heights,
+            3,
+            Point::from_list([-1., 0., -1.]),
+            Vector3 {
+                x: 2.,
+                y: 1.,
+                z: 2.,
+            },
+            grey_material(),
+        )
+        .unwrap();
+        let up = Vector3 {
+            x: 0.,
+            y: 1.,
+            z: 0.,
+        };
+        assert!(
+            bump.surface_normal(&Point::from_list([0., 1., 0.]))
+                .dot(&up)
+                > 1. - 1e-9
+        );
+        let (near, far) = (
+            bump.surface_normal(&Point::from_list([0.2, 0.8, 0.])),
+            bump.surface_normal(&Point::from_list([0.6, 0.4, 0.])),
+        );
+        assert!(near.x > 0. && far.x > near.x);
+
+        assert!(Heightfield::new(vec![0.; 3], 3, Point::zero(), up, grey_material()).is_none());
+    }
 }
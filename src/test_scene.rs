@@ -10,15 +10,7 @@ use crate::{
 };
 
 pub fn test_scene(width: u32, height: u32) -> Scene {
-    let mut scene = Scene {
-        width,
-        height,
-        fov: 90.,
-        shadow_bias: 1e-6,
-        max_recursion_depth: 10,
-        lights: vec![],
-        elements: vec![],
-    };
+    let mut scene = Scene::new(width, height, 90., 1e-6, 10);
 
     let mut lights = vec![
         Light::Directional(DirectionalLight {
@@ -77,6 +69,8 @@ pub fn test_scene(width: u32, height: u32) -> Scene {
                 blue: 0.2,
             }),
             albedo: 0.18,
+            emission: None,
+            specular: None,
         },
     });
 
@@ -95,6 +89,8 @@ pub fn test_scene(width: u32, height: u32) -> Scene {
                 blue: 1.,
             }),
             albedo: 0.58,
+            emission: None,
+            specular: None,
         },
     });
 
@@ -113,6 +109,8 @@ pub fn test_scene(width: u32, height: u32) -> Scene {
                 blue: 0.2,
             }),
             albedo: 0.08,
+            emission: None,
+            specular: None,
         },
     });
 
@@ -135,6 +133,8 @@ pub fn test_scene(width: u32, height: u32) -> Scene {
                 blue: 0.,
             }),
             albedo: 0.18,
+            emission: None,
+            specular: None,
         },
     });
 
@@ -157,11 +157,13 @@ pub fn test_scene(width: u32, height: u32) -> Scene {
                 blue: 1.0,
             }),
             albedo: 0.18,
+            emission: None,
+            specular: None,
         },
     });
 
-    while !lights.is_empty() {
-        scene.add_light(lights.pop().unwrap());
+    while let Some(light) = lights.pop() {
+        scene.add_light(light);
     }
 
     scene.add_element(blue_sphere);